@@ -0,0 +1,330 @@
+//! Language server for WAT files, using the same checker as the app.
+//!
+//! Speaks the Language Server Protocol over stdin/stdout and supports:
+//! - Diagnostics from parsing, name resolution and validation, and warnings about unused code
+//! - Hover showing the instruction and the types on the stack before and after it
+//! - Go to definition for `$labels`, `$locals`, `$funcs`, `$globals` and `$memory`
+//! - Document symbols for every function
+//! - Renaming any `$name` along with everything that refers to it
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    ops::Range,
+};
+
+use app_lib::{
+    build_structure,
+    diagnostic::{self, Severity},
+    marker::SerializableWatType,
+    rename::rename_at,
+    source::SourceMap,
+};
+use serde_json::{json, Value};
+
+/// JSON-RPC error code for requests that are not supported
+const METHOD_NOT_FOUND: i64 = -32601;
+/// LSP error code for requests that could not be done
+const REQUEST_FAILED: i64 = -32803;
+/// LSP `SymbolKind::Function`
+const SYMBOL_FUNCTION: u32 = 12;
+/// LSP `DiagnosticSeverity::Error`
+const SEVERITY_ERROR: u32 = 1;
+/// LSP `DiagnosticSeverity::Warning`
+const SEVERITY_WARNING: u32 = 2;
+
+/// Read one message, returning [None] once the client closes the stream
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Missing Content-Length header",
+        ));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+/// Convert a byte offset into an LSP position, which counts UTF-16 code units
+fn position(text: &str, offset: u32) -> Value {
+    let before = &text[..(offset as usize).min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+fn range(text: &str, span: &Range<u32>) -> Value {
+    json!({ "start": position(text, span.start), "end": position(text, span.end) })
+}
+
+/// Convert an LSP position back into a byte offset
+fn offset(text: &str, position: &Value) -> u32 {
+    let line = position["line"].as_u64().unwrap_or(0) as usize;
+    let character = position["character"].as_u64().unwrap_or(0) as usize;
+    let line_start = match line {
+        0 => 0,
+        _ => text
+            .match_indices('\n')
+            .nth(line - 1)
+            .map_or(text.len(), |(i, _)| i + 1),
+    };
+    let mut units = 0;
+    let column = text[line_start..]
+        .char_indices()
+        .find(|(_, c)| {
+            let is_past = units >= character || *c == '\n';
+            units += c.len_utf16();
+            is_past
+        })
+        .map_or(text.len() - line_start, |(i, _)| i);
+    (line_start + column) as u32
+}
+
+fn format_stack(types: &[SerializableWatType]) -> String {
+    let types = types
+        .iter()
+        .map(|typ| typ.to_string().to_lowercase())
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("[{types}]")
+}
+
+fn diagnostics(text: &str) -> Value {
+    diagnostic::diagnostics(text)
+        .iter()
+        .map(|diagnostic| {
+            json!({
+                "range": range(text, &diagnostic.span),
+                "severity": match diagnostic.severity {
+                    Severity::Error => SEVERITY_ERROR,
+                    Severity::Warning => SEVERITY_WARNING,
+                },
+                "source": "wasvd",
+                "message": diagnostic.message,
+            })
+        })
+        .collect()
+}
+
+fn hover(text: &str, at: u32) -> Option<Value> {
+    let source = SourceMap::try_new(text).ok()?;
+    let (function_index, function) = source.function_at(at)?;
+    let index = function.instruction_at(at)?;
+    let structure = build_structure(text).ok()?;
+    let instruction = structure
+        .functions()
+        .get(function_index)?
+        .instructions()
+        .get(index)?;
+    let stack_types = structure.stack_types().into_iter().nth(function_index)?;
+    let mut contents = format!("```wat\n{instruction}\n```\n");
+    if let Some(before) = stack_types.before.get(index) {
+        contents += &format!("\nStack before: `{}`", format_stack(before));
+    }
+    match (stack_types.after(index), &stack_types.error) {
+        (Some(after), _) => contents += &format!("\n\nStack after: `{}`", format_stack(after)),
+        (None, Some((failed, err))) if *failed == index => contents += &format!("\n\n{err}"),
+        _ => {}
+    }
+    Some(json!({
+        "contents": { "kind": "markdown", "value": contents },
+        "range": range(text, &function.instructions[index]),
+    }))
+}
+
+fn definition(uri: &Value, text: &str, at: u32) -> Option<Value> {
+    let source = SourceMap::try_new(text).ok()?;
+    let def = source.definition_at(text, at)?;
+    Some(json!({ "uri": uri, "range": range(text, &def.span) }))
+}
+
+fn document_symbols(text: &str) -> Value {
+    let Ok(source) = SourceMap::try_new(text) else {
+        return json!([]);
+    };
+    let symbols = source
+        .functions
+        .iter()
+        .enumerate()
+        .map(|(i, function)| {
+            let (name, selection) = match &function.name {
+                Some(def) => (format!("${}", def.name), &def.span),
+                None => (format!("func {i}"), &function.keyword),
+            };
+            json!({
+                "name": name,
+                "kind": SYMBOL_FUNCTION,
+                "range": range(text, &function.span),
+                "selectionRange": range(text, selection),
+            })
+        })
+        .collect();
+    Value::Array(symbols)
+}
+
+fn rename(uri: &Value, text: &str, at: u32, new_name: &str) -> Result<Value, (i64, String)> {
+    let edits = rename_at(text, at, new_name).map_err(|err| (REQUEST_FAILED, err.to_string()))?;
+    let edits: Vec<_> = edits
+        .iter()
+        .map(|edit| json!({ "range": range(text, &edit.span), "newText": edit.text }))
+        .collect();
+    let mut changes = serde_json::Map::new();
+    changes.insert(uri.as_str().unwrap_or_default().to_string(), json!(edits));
+    Ok(json!({ "changes": changes }))
+}
+
+/// Open documents by their URI
+#[derive(Debug, Default)]
+struct Server {
+    documents: HashMap<String, String>,
+    is_shutting_down: bool,
+}
+
+impl Server {
+    /// Handle a request, returning its result
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        let uri = &params["textDocument"]["uri"];
+        let text = uri.as_str().and_then(|uri| self.documents.get(uri));
+        let at = |text: &str| offset(text, &params["position"]);
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    // Full text is sent on every change
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                    "renameProvider": true,
+                },
+                "serverInfo": { "name": "wasvd-lsp", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "shutdown" => {
+                self.is_shutting_down = true;
+                Ok(Value::Null)
+            }
+            "textDocument/hover" => Ok(text
+                .and_then(|text| hover(text, at(text)))
+                .unwrap_or_default()),
+            "textDocument/definition" => Ok(text
+                .and_then(|text| definition(uri, text, at(text)))
+                .unwrap_or_default()),
+            "textDocument/documentSymbol" => {
+                Ok(text.map(|t| document_symbols(t)).unwrap_or_default())
+            }
+            "textDocument/rename" => match text {
+                Some(text) => rename(
+                    uri,
+                    text,
+                    at(text),
+                    params["newName"].as_str().unwrap_or_default(),
+                ),
+                None => Ok(Value::Null),
+            },
+            _ => Err((METHOD_NOT_FOUND, format!("Unsupported method: {method}"))),
+        }
+    }
+
+    /// Handle a notification, returning diagnostics to publish if a document changed
+    fn notification(&mut self, method: &str, params: &Value) -> Option<Value> {
+        let uri = params["textDocument"]["uri"].as_str()?.to_string();
+        let text = match method {
+            "textDocument/didOpen" => params["textDocument"]["text"].as_str()?,
+            "textDocument/didChange" => {
+                params["contentChanges"].as_array()?.last()?["text"].as_str()?
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return Some(json!({ "uri": uri, "diagnostics": [] }));
+            }
+            _ => return None,
+        };
+        let diagnostics = diagnostics(text);
+        self.documents.insert(uri.clone(), text.to_string());
+        Some(json!({ "uri": uri, "diagnostics": diagnostics }))
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout().lock();
+    let mut server = Server::default();
+    while let Some(message) = read_message(&mut input)? {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        match message.get("id") {
+            Some(id) => {
+                let response = match server.request(method, params) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err((code, msg)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": code, "message": msg },
+                    }),
+                };
+                write_message(&mut output, &response)?;
+            }
+            None if method == "exit" => {
+                std::process::exit(if server.is_shutting_down { 0 } else { 1 })
+            }
+            None => {
+                if let Some(published) = server.notification(method, params) {
+                    write_message(
+                        &mut output,
+                        &json!({
+                            "jsonrpc": "2.0",
+                            "method": "textDocument/publishDiagnostics",
+                            "params": published,
+                        }),
+                    )?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_count_utf16_units_per_line() {
+        let text = "a\nbé\nc";
+        let at = |line: u64, character: u64| {
+            offset(text, &json!({ "line": line, "character": character }))
+        };
+        assert_eq!(at(0, 0), 0);
+        assert_eq!(at(0, 1), 1);
+        assert_eq!(at(1, 2), 5);
+        // Past the end of a line stops at its newline, past the last line at the end
+        assert_eq!(at(0, 9), 1);
+        assert_eq!(at(7, 0), text.len() as u32);
+        assert_eq!(position(text, 5), json!({ "line": 1, "character": 2 }));
+        assert_eq!(position(text, 0), json!({ "line": 0, "character": 0 }));
+    }
+}
@@ -0,0 +1,424 @@
+//! Command line interface for checking and running WAT files without the GUI.
+//!
+//! ```text
+//! wasvd check <file.wat>
+//! wasvd run <file.wat> --invoke <name> [args...]
+//! wasvd trace <file.wat> --invoke <name> [args...] [--format text|json] [--watch <expr>]...
+//! wasvd cfg <file.wat> [--format dot|mermaid]
+//! ```
+
+use std::{ops::Range, process::ExitCode};
+
+use app_lib::{
+    build_structure,
+    cfg::ControlFlowGraph,
+    error::WatError,
+    helper::SerializedNumber,
+    inner_transform,
+    interpreter::{Machine, TraceStep, Trap, Value},
+    source::SourceMap,
+    watch::Watch,
+};
+use serde::Serialize;
+
+const USAGE: &str = "Usage:
+    wasvd check <file.wat>
+    wasvd run <file.wat> --invoke <name> [args...] [--max-steps <n>]
+    wasvd trace <file.wat> --invoke <name> [args...] [--format text|json] [--max-steps <n>]
+        [--watch <expr>]...
+    wasvd cfg <file.wat> [--format dot|mermaid]";
+
+/// Exit code when the module is invalid or execution traps
+const EXIT_FAILURE: u8 = 1;
+/// Exit code when the command line itself is wrong
+const EXIT_USAGE: u8 = 2;
+/// Innermost calls printed for a trap
+const MAX_CALLS_SHOWN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+    Dot,
+    Mermaid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    Check,
+    Run,
+    Trace(Format),
+    Cfg(Format),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Options {
+    command: Command,
+    path: String,
+    invoke: Option<String>,
+    args: Vec<String>,
+    max_steps: Option<u64>,
+    /// Expressions shown after every traced step
+    watches: Vec<Watch>,
+}
+
+/// Full output of `wasvd trace --format json`
+#[derive(Debug, Serialize)]
+struct TraceReport<'a> {
+    function: &'a str,
+    args: &'a [Value],
+    /// Expressions whose values are in each step's `watches`
+    watches: Vec<&'a str>,
+    steps: &'a [TraceStep],
+    results: Option<Vec<Value>>,
+    error: Option<WatError>,
+}
+
+fn parse_options(mut raw: impl Iterator<Item = String>) -> Result<Options, String> {
+    let command = match raw.next().as_deref() {
+        Some("check") => Command::Check,
+        Some("run") => Command::Run,
+        Some("trace") => Command::Trace(Format::Text),
+        Some("cfg") => Command::Cfg(Format::Dot),
+        Some(other) => return Err(format!("Unknown command: {other}")),
+        None => return Err("Missing command".to_string()),
+    };
+    let path = raw.next().ok_or("Missing WAT file")?;
+    let mut options = Options {
+        command,
+        path,
+        invoke: None,
+        args: Vec::new(),
+        max_steps: None,
+        watches: Vec::new(),
+    };
+    while let Some(arg) = raw.next() {
+        match arg.as_str() {
+            "--invoke" => options.invoke = Some(raw.next().ok_or("Missing name after --invoke")?),
+            "--max-steps" => {
+                let steps = raw.next().ok_or("Missing number after --max-steps")?;
+                options.max_steps = Some(
+                    steps
+                        .parse()
+                        .map_err(|_| format!("Invalid step count: {steps}"))?,
+                );
+            }
+            "--watch" => {
+                let watch = raw.next().ok_or("Missing expression after --watch")?;
+                options
+                    .watches
+                    .push(watch.parse().map_err(|err: WatError| err.to_string())?);
+            }
+            "--format" => {
+                let format = match raw.next().as_deref() {
+                    Some("text") => Format::Text,
+                    Some("json") => Format::Json,
+                    Some("dot") => Format::Dot,
+                    Some("mermaid") => Format::Mermaid,
+                    Some(other) => return Err(format!("Unknown format: {other}")),
+                    None => return Err("Missing format after --format".to_string()),
+                };
+                match (&mut options.command, format) {
+                    (Command::Trace(f), Format::Text | Format::Json)
+                    | (Command::Cfg(f), Format::Dot | Format::Mermaid) => *f = format,
+                    (Command::Trace(_) | Command::Cfg(_), _) => {
+                        return Err("Format not supported by this command".to_string())
+                    }
+                    _ => return Err("--format is only used by trace and cfg".to_string()),
+                }
+            }
+            _ => options.args.push(arg),
+        }
+    }
+    if matches!(options.command, Command::Run | Command::Trace(_)) && options.invoke.is_none() {
+        return Err("Missing --invoke <name>".to_string());
+    }
+    if !matches!(options.command, Command::Run | Command::Trace(_)) {
+        if options.invoke.is_some() {
+            return Err("--invoke is only used by run and trace".to_string());
+        }
+        if let Some(arg) = options.args.first() {
+            return Err(format!("Unexpected argument: {arg}"));
+        }
+    }
+    if !options.watches.is_empty() && !matches!(options.command, Command::Trace(_)) {
+        return Err("--watch is only used by trace".to_string());
+    }
+    Ok(options)
+}
+
+/// The file with the line and column of a span, counted from 1
+fn position(path: &str, text: &str, span: &Range<u32>) -> String {
+    let before = &text[..(span.start as usize).min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    format!("{path}:{line}:{column}")
+}
+
+/// Print an error, pointing to the line and column if it has a span
+fn report_error(path: &str, text: &str, error: &WatError) {
+    match error.span() {
+        Some(span) => eprintln!("{}: {error}", position(path, text, span)),
+        None => eprintln!("{path}: {error}"),
+    }
+}
+
+/// Where the instruction that trapped is written
+fn trap_span(text: &str, trap: &Trap) -> Option<Range<u32>> {
+    let location = trap.location()?;
+    let map = SourceMap::try_new(text).ok()?;
+    let function = match map.functions.iter().find(|f| {
+        f.name
+            .as_ref()
+            .is_some_and(|name| name.name == location.function)
+    }) {
+        Some(function) => function,
+        None => map
+            .functions
+            .get(location.function.parse::<usize>().ok()?)?,
+    };
+    function.instructions.get(location.index as usize).cloned()
+}
+
+/// Name of a function as written in the text format, or its index for one without a name
+fn function_label(function: &str) -> String {
+    if function.parse::<u32>().is_ok() {
+        format!("func {function}")
+    } else {
+        format!("${function}")
+    }
+}
+
+/// Print the calls a trap happened in, innermost first
+fn print_call_stack(trap: &Trap) {
+    for call in trap.call_stack.iter().rev().take(MAX_CALLS_SHOWN) {
+        eprintln!(
+            "    in {} at instruction {}",
+            function_label(&call.function),
+            call.index
+        );
+    }
+    let hidden = trap.call_stack.len().saturating_sub(MAX_CALLS_SHOWN);
+    if hidden > 0 {
+        eprintln!("    ... and {hidden} more calls");
+    }
+}
+
+fn print_step(step: &TraceStep, watches: &[Watch]) {
+    let stack = step
+        .stack_after
+        .iter()
+        .map(SerializedNumber::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    println!(
+        "{:>6} {}{}@{:<4} {:<24} [{stack}]",
+        step.step,
+        "  ".repeat(step.call_depth as usize),
+        step.function,
+        step.index,
+        step.instruction.to_string()
+    );
+    for (watch, value) in watches.iter().zip(&step.watches) {
+        println!("{:>6} {} = {value}", "", watch.text());
+    }
+    for access in &step.memory {
+        let hex = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        match &access.new {
+            Some(new) => println!(
+                "{:>6} memory {} @{}: {} -> {}",
+                "",
+                access.memory,
+                access.address,
+                hex(&access.old),
+                hex(new)
+            ),
+            None => println!(
+                "{:>6} memory {} @{}: {}",
+                "",
+                access.memory,
+                access.address,
+                hex(&access.old)
+            ),
+        }
+    }
+    if let Some(trap) = step.trap {
+        println!("{:>6} trapped: {trap}", "");
+    }
+}
+
+/// Print the control flow graph of every function
+fn print_graphs(options: &Options, text: &str, format: Format) -> Result<(), u8> {
+    let structure = build_structure(text).map_err(|err| {
+        report_error(&options.path, text, &err);
+        EXIT_FAILURE
+    })?;
+    for func in structure.functions() {
+        let graph = ControlFlowGraph::new(func.tree());
+        let name = func.name().unwrap_or_default();
+        match format {
+            Format::Mermaid => println!("{}", graph.to_mermaid(func.instructions())),
+            _ => println!("{}", graph.to_dot(&name, func.instructions())),
+        }
+    }
+    Ok(())
+}
+
+fn run(options: &Options) -> Result<(), u8> {
+    let text = std::fs::read_to_string(&options.path).map_err(|err| {
+        eprintln!("{}: {err}", options.path);
+        EXIT_USAGE
+    })?;
+    if let Command::Cfg(format) = options.command {
+        return print_graphs(options, &text, format);
+    }
+    let structure = inner_transform(&text).map_err(|err| {
+        report_error(&options.path, &text, &err);
+        EXIT_FAILURE
+    })?;
+    let Some(name) = &options.invoke else {
+        println!("{}: ok", options.path);
+        return Ok(());
+    };
+
+    let mut machine = Machine::new(&structure).map_err(|err| {
+        report_error(&options.path, &text, &err);
+        EXIT_FAILURE
+    })?;
+    if let Some(max_steps) = options.max_steps {
+        machine = machine.with_max_steps(max_steps);
+    }
+    if matches!(options.command, Command::Trace(_)) {
+        machine = machine.with_tracing().with_watches(options.watches.clone());
+    }
+    let instantiation = machine.instantiate().map_err(|err| {
+        report_error(&options.path, &text, &err);
+        EXIT_FAILURE
+    })?;
+    if let Some(trap) = &instantiation.trap {
+        let location = match trap_span(&text, trap) {
+            Some(span) => position(&options.path, &text, &span),
+            None => options.path.clone(),
+        };
+        eprintln!("{location}: trapped while instantiating: {}", trap.message);
+        print_call_stack(trap);
+        return Err(EXIT_FAILURE);
+    }
+    let function = machine.find_function(name).map_err(|err| {
+        report_error(&options.path, &text, &err);
+        EXIT_FAILURE
+    })?;
+    let params = machine.param_types(function);
+    if params.len() != options.args.len() {
+        eprintln!(
+            "{name} expects {} arguments, but got {}",
+            params.len(),
+            options.args.len()
+        );
+        return Err(EXIT_USAGE);
+    }
+    let args = params
+        .iter()
+        .zip(&options.args)
+        .map(|(typ, arg)| Value::parse(typ, arg))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| {
+            eprintln!("{err}");
+            EXIT_USAGE
+        })?;
+    let outcome = machine.invoke(function, &args).map_err(|err| {
+        match machine.trap().and_then(|trap| trap_span(&text, trap)) {
+            Some(span) => err.or_span(span),
+            None => err,
+        }
+    });
+
+    match options.command {
+        Command::Trace(Format::Json) => {
+            let (results, error) = match &outcome {
+                Ok(results) => (Some(results.clone()), None),
+                Err(err) => (None, Some(err.clone())),
+            };
+            let report = TraceReport {
+                function: name,
+                args: &args,
+                watches: options.watches.iter().map(Watch::text).collect(),
+                steps: machine.trace(),
+                results,
+                error,
+            };
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report).expect("trace is serializable")
+            );
+        }
+        Command::Trace(Format::Text) => machine
+            .trace()
+            .iter()
+            .for_each(|step| print_step(step, &options.watches)),
+        _ => {}
+    }
+    match outcome {
+        Ok(results) => {
+            if options.command == Command::Run {
+                results.iter().for_each(|val| println!("{val}"));
+            }
+            Ok(())
+        }
+        Err(err) => {
+            report_error(&options.path, &text, &err);
+            if let Some(trap) = machine.trap() {
+                print_call_stack(trap);
+            }
+            Err(EXIT_FAILURE)
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let options = match parse_options(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("{msg}\n\n{USAGE}");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    match run(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(code) => ExitCode::from(code),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        parse_options(args.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn invoking_is_only_for_run_and_trace() {
+        assert!(parse("run f.wat --invoke f 1 2").is_ok());
+        assert!(parse("check f.wat --invoke f").is_err());
+        assert!(parse("cfg f.wat --invoke f").is_err());
+        assert!(parse("check f.wat 1").is_err());
+        assert!(parse("run f.wat").is_err());
+    }
+
+    #[test]
+    fn traps_point_to_the_trapping_instruction() {
+        let text = "(module\n  (func $f\n    nop\n    unreachable))";
+        let structure = inner_transform(text).unwrap();
+        let mut machine = Machine::new(&structure).unwrap();
+        assert!(machine.invoke(0, &[]).is_err());
+        let span = trap_span(text, machine.trap().unwrap()).unwrap();
+        assert_eq!(position("f.wat", text, &span), "f.wat:4:5");
+    }
+}
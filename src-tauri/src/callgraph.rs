@@ -0,0 +1,345 @@
+//! Which functions call which, across the whole module.
+//!
+//! Only direct `call`s are followed, `call_indirect` goes through a table which is not supported yet.
+//! Recursion is found from the strongly connected components of the graph.
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    build_structure, error::WatResult, instruction::SerializedInstruction,
+    validator::try_name_to_index, InterpreterStructure, NumLocationKind, WastFunc,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum Recursion {
+    /// Calls itself
+    Direct,
+    /// Calls itself through these other functions
+    Mutual(Vec<u32>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct CallNode {
+    pub name: String,
+    /// Names the function is exported as
+    pub exports: Vec<String>,
+    pub is_start: bool,
+    /// Can be called from an export or the start function
+    pub is_reachable: bool,
+    pub recursion: Option<Recursion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct CallEdge {
+    pub from: u32,
+    pub to: u32,
+    /// Number of `call` instructions making this call
+    pub count: u32,
+    /// Part of a cycle of calls
+    pub is_recursive: bool,
+}
+
+/// Functions as nodes, in module order, and calls between them as edges
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, Type)]
+pub struct CallGraph {
+    pub nodes: Vec<CallNode>,
+    pub edges: Vec<CallEdge>,
+}
+
+/// Index of the function named by a `call` or `start`
+pub(crate) fn resolve_function(functions: &[WastFunc], location: &str) -> Option<usize> {
+    match try_name_to_index(location) {
+        Ok(index) => (index < functions.len()).then_some(index),
+        Err(name) => functions
+            .iter()
+            .position(|f| f.name().as_deref() == Some(name)),
+    }
+}
+
+/// Functions called directly by the function, once for each `call`
+fn callees(functions: &[WastFunc], func: &WastFunc) -> Vec<usize> {
+    func.instructions()
+        .iter()
+        .filter_map(|instruction| match instruction {
+            SerializedInstruction::Call { index } => resolve_function(functions, index),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Strongly connected components with Tarjan's algorithm, each as a list of nodes
+fn components(successors: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    struct Search<'a> {
+        successors: &'a [Vec<usize>],
+        next: usize,
+        order: Vec<Option<usize>>,
+        low: Vec<usize>,
+        stack: Vec<usize>,
+        on_stack: Vec<bool>,
+        components: Vec<Vec<usize>>,
+    }
+
+    impl Search<'_> {
+        fn visit(&mut self, node: usize) {
+            self.order[node] = Some(self.next);
+            self.low[node] = self.next;
+            self.next += 1;
+            self.stack.push(node);
+            self.on_stack[node] = true;
+            for &to in &self.successors[node] {
+                match self.order[to] {
+                    None => {
+                        self.visit(to);
+                        self.low[node] = self.low[node].min(self.low[to]);
+                    }
+                    Some(order) if self.on_stack[to] => {
+                        self.low[node] = self.low[node].min(order);
+                    }
+                    Some(_) => {}
+                }
+            }
+            if Some(self.low[node]) == self.order[node] {
+                let mut component = Vec::new();
+                while let Some(member) = self.stack.pop() {
+                    self.on_stack[member] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                component.sort();
+                self.components.push(component);
+            }
+        }
+    }
+
+    let count = successors.len();
+    let mut search = Search {
+        successors,
+        next: 0,
+        order: vec![None; count],
+        low: vec![0; count],
+        stack: Vec::new(),
+        on_stack: vec![false; count],
+        components: Vec::new(),
+    };
+    for node in 0..count {
+        if search.order[node].is_none() {
+            search.visit(node);
+        }
+    }
+    search.components
+}
+
+impl CallGraph {
+    pub fn new(structure: &InterpreterStructure) -> Self {
+        let functions = &structure.func;
+        let calls: Vec<Vec<usize>> = functions
+            .iter()
+            .map(|func| callees(functions, func))
+            .collect();
+        let mut successors: Vec<Vec<usize>> = calls.clone();
+        successors.iter_mut().for_each(|to| {
+            to.sort();
+            to.dedup();
+        });
+        let mut exports: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+        for (name, (kind, index)) in &structure.exported {
+            if *kind == NumLocationKind::Function {
+                exports
+                    .entry(*index as usize)
+                    .or_default()
+                    .push(name.clone());
+            }
+        }
+        let start = structure
+            .start
+            .as_deref()
+            .and_then(|start| resolve_function(functions, start));
+        // Everything that can be called from an export or the start function
+        let mut reached = HashSet::new();
+        let mut pending: Vec<usize> = exports.keys().copied().chain(start).collect();
+        while let Some(index) = pending.pop() {
+            if reached.insert(index) {
+                pending.extend(&successors[index]);
+            }
+        }
+        let mut component_of = vec![0; functions.len()];
+        let components = components(&successors);
+        for (id, component) in components.iter().enumerate() {
+            component
+                .iter()
+                .for_each(|member| component_of[*member] = id);
+        }
+        let nodes = functions
+            .iter()
+            .enumerate()
+            .map(|(index, func)| {
+                let component = &components[component_of[index]];
+                let recursion = if component.len() > 1 {
+                    Some(Recursion::Mutual(
+                        component
+                            .iter()
+                            .filter(|member| **member != index)
+                            .map(|member| *member as u32)
+                            .collect(),
+                    ))
+                } else if successors[index].contains(&index) {
+                    Some(Recursion::Direct)
+                } else {
+                    None
+                };
+                let mut exported_as = exports.get(&index).cloned().unwrap_or_default();
+                exported_as.sort();
+                CallNode {
+                    name: func.name().unwrap_or_default(),
+                    exports: exported_as,
+                    is_start: start == Some(index),
+                    is_reachable: reached.contains(&index),
+                    recursion,
+                }
+            })
+            .collect();
+        let edges = successors
+            .iter()
+            .enumerate()
+            .flat_map(|(from, to)| to.iter().map(move |to| (from, *to)))
+            .map(|(from, to)| CallEdge {
+                from: from as u32,
+                to: to as u32,
+                count: calls[from].iter().filter(|callee| **callee == to).count() as u32,
+                // A call to itself is its own cycle
+                is_recursive: component_of[from] == component_of[to],
+            })
+            .collect();
+        Self { nodes, edges }
+    }
+
+    /// Graph in the Graphviz DOT language.
+    ///
+    /// Exports have a double border, the start function is a diamond,
+    /// recursive calls are red and unreachable functions are dashed.
+    pub fn to_dot(&self, name: &str) -> String {
+        let escape = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut dot = format!("digraph \"{}\" {{\n", escape(name));
+        dot += "    node [shape=box, fontname=\"monospace\"];\n";
+        for (id, node) in self.nodes.iter().enumerate() {
+            let mut label = format!("${}", escape(&node.name));
+            for export in &node.exports {
+                label += &format!("\\nexport \\\"{}\\\"", escape(export));
+            }
+            let mut attributes = vec![format!("label=\"{label}\"")];
+            if !node.exports.is_empty() {
+                attributes.push("peripheries=2".to_string());
+            }
+            if node.is_start {
+                attributes.push("shape=diamond".to_string());
+            }
+            if !node.is_reachable {
+                attributes.push("style=dashed".to_string());
+            }
+            if node.recursion.is_some() {
+                attributes.push("color=red".to_string());
+            }
+            dot += &format!("    f{id} [{}];\n", attributes.join(", "));
+        }
+        for edge in &self.edges {
+            let mut attributes = Vec::new();
+            if edge.count > 1 {
+                attributes.push(format!("label=\"{}\"", edge.count));
+            }
+            if edge.is_recursive {
+                attributes.push("color=red".to_string());
+            }
+            dot += &format!("    f{} -> f{}", edge.from, edge.to);
+            if !attributes.is_empty() {
+                dot += &format!(" [{}]", attributes.join(", "));
+            }
+            dot += ";\n";
+        }
+        dot += "}\n";
+        dot
+    }
+}
+
+/// Call graph of a module, along with its DOT form
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct CallGraphReport {
+    pub graph: CallGraph,
+    pub dot: String,
+}
+
+/// Build the call graph of the module in the text
+pub fn call_graph(text: &str) -> WatResult<CallGraphReport> {
+    let structure = build_structure(text)?;
+    let graph = CallGraph::new(&structure);
+    Ok(CallGraphReport {
+        dot: graph.to_dot(&structure.name),
+        graph,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(text: &str) -> CallGraph {
+        call_graph(text).unwrap().graph
+    }
+
+    #[test]
+    fn recursion_is_direct_or_mutual() {
+        let graph = graph(
+            r#"(module
+                (func $fact (export "fact") call $fact)
+                (func $even (export "even") call $odd)
+                (func $odd call $even call $even)
+                (func $leaf))"#,
+        );
+        let recursion: Vec<_> = graph.nodes.iter().map(|n| n.recursion.clone()).collect();
+        assert_eq!(
+            recursion,
+            [
+                Some(Recursion::Direct),
+                Some(Recursion::Mutual(vec![2])),
+                Some(Recursion::Mutual(vec![1])),
+                None,
+            ]
+        );
+        assert!(graph.edges.contains(&CallEdge {
+            from: 2,
+            to: 1,
+            count: 2,
+            is_recursive: true,
+        }));
+    }
+
+    #[test]
+    fn only_exports_and_start_reach_functions() {
+        let graph = graph(
+            r#"(module
+                (func $init call $helper)
+                (func $helper)
+                (func $main (export "main") (export "run"))
+                (func $unused call $helper)
+                (start $init))"#,
+        );
+        let reachable: Vec<_> = graph.nodes.iter().map(|n| n.is_reachable).collect();
+        assert_eq!(reachable, [true, true, true, false]);
+        assert!(graph.nodes[0].is_start);
+        assert_eq!(graph.nodes[2].exports, ["main", "run"]);
+        assert!(graph.edges.iter().all(|edge| !edge.is_recursive));
+    }
+
+    #[test]
+    fn dot_marks_recursive_calls() {
+        let dot = call_graph(r#"(module (func $f (export "f") call $f call $f))"#)
+            .unwrap()
+            .dot;
+        assert!(dot.contains(r#"f0 [label="$f\nexport \"f\"", peripheries=2, color=red];"#));
+        assert!(dot.contains(r#"f0 -> f0 [label="2", color=red];"#));
+    }
+}
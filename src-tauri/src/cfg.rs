@@ -0,0 +1,411 @@
+//! Control flow graph of each function, built from its instruction array.
+//!
+//! Branches go where the interpreter sends them: the instruction after a loop's start,
+//! or the instruction after a block's end. Targets past the last instruction leave the function.
+
+use std::{fmt::Display, ops::Range};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    build_structure,
+    error::WatResult,
+    inspect::enclosing_blocks,
+    instruction::{BranchTarget, NodeMark, SerializedInstruction, SerializedInstructionTree},
+    marker::{BlockKind, SimpleInstruction},
+    validator::StackTypes,
+};
+
+/// Why control moves from one block to another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum EdgeKind {
+    /// Falls through to the next instruction
+    Next,
+    /// `br`, or `br_if` when the value on the stack is not 0
+    Branch,
+    /// `br_if` when the value on the stack is 0
+    NotTaken,
+    /// `br_table` with the value on the stack as the index into its labels
+    Table(u32),
+    /// `br_table` with a value past its labels
+    TableDefault,
+    /// `if` when the value on the stack is not 0
+    Then,
+    /// `if` when the value on the stack is 0, going to the else or the end
+    Else,
+    Return,
+}
+
+impl Display for EdgeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EdgeKind::Next => Ok(()),
+            EdgeKind::Branch => f.write_str("br"),
+            EdgeKind::NotTaken => f.write_str("not taken"),
+            EdgeKind::Table(index) => write!(f, "case {index}"),
+            EdgeKind::TableDefault => f.write_str("default"),
+            EdgeKind::Then => f.write_str("then"),
+            EdgeKind::Else => f.write_str("else"),
+            EdgeKind::Return => f.write_str("return"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct Edge {
+    pub from: u32,
+    pub to: u32,
+    pub kind: EdgeKind,
+    /// Goes back to an earlier instruction, like a branch to a loop
+    pub is_back_edge: bool,
+}
+
+/// Instructions that always run one after another
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct BasicBlock {
+    /// Indices into the instruction array, empty for the exit block
+    pub instructions: Range<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, Type)]
+pub struct ControlFlowGraph {
+    /// Blocks in instruction order, the first is the entry and the last is the exit
+    pub blocks: Vec<BasicBlock>,
+    pub edges: Vec<Edge>,
+}
+
+/// Where control can go after an instruction, as instruction indices.
+///
+/// The instruction count stands for leaving the function.
+pub(crate) fn successors(tree: &SerializedInstructionTree, index: u32) -> Vec<(u32, EdgeKind)> {
+    let exit = tree.array.len() as u32;
+    let next = index + 1;
+    match &tree.array[index as usize] {
+        SerializedInstruction::Simple(SimpleInstruction::Return) => vec![(exit, EdgeKind::Return)],
+        SerializedInstruction::Simple(SimpleInstruction::Unreachable) => Vec::new(),
+        SerializedInstruction::Block {
+            kind: BlockKind::If,
+            ..
+        } => {
+            let otherwise = tree
+                .root
+                .iter()
+                .skip(1)
+                .find(|node| node.start == index)
+                .map(|node| match node.kind {
+                    NodeMark::Conditional(else_index) if else_index != 0 => else_index + 1,
+                    _ => node.end,
+                });
+            [(Some(next), EdgeKind::Then), (otherwise, EdgeKind::Else)]
+                .into_iter()
+                .filter_map(|(to, kind)| Some((to?, kind)))
+                .collect()
+        }
+        // The then arm is done, skip over the else arm
+        SerializedInstruction::Block {
+            kind: BlockKind::Else,
+            ..
+        } => enclosing_blocks(tree, index)
+            .iter()
+            .find(|b| b.kind == NodeMark::Conditional(index))
+            .map(|b| vec![(b.end, EdgeKind::Next)])
+            .unwrap_or_default(),
+        SerializedInstruction::Branch {
+            other_labels,
+            is_conditional,
+            targets,
+            ..
+        } => {
+            let to = |target: &BranchTarget| target.continuation;
+            if !other_labels.is_empty() {
+                targets
+                    .iter()
+                    .enumerate()
+                    .map(|(i, target)| {
+                        if i < other_labels.len() {
+                            (to(target), EdgeKind::Table(i as u32))
+                        } else {
+                            (to(target), EdgeKind::TableDefault)
+                        }
+                    })
+                    .collect()
+            } else if *is_conditional {
+                targets
+                    .first()
+                    .map(|target| (to(target), EdgeKind::Branch))
+                    .into_iter()
+                    .chain([(next, EdgeKind::NotTaken)])
+                    .collect()
+            } else {
+                targets
+                    .first()
+                    .map(|target| vec![(to(target), EdgeKind::Branch)])
+                    .unwrap_or_default()
+            }
+        }
+        _ => vec![(next, EdgeKind::Next)],
+    }
+}
+
+/// Shape the stack of values along an edge out of the instruction at `from`.
+///
+/// A branch keeps the values under its target block and the values it carries to it,
+/// like the interpreter does. Other edges leave the stack as it is.
+pub(crate) fn carry_values<T>(
+    tree: &SerializedInstructionTree,
+    stack_types: &StackTypes,
+    from: u32,
+    (to, kind): (u32, EdgeKind),
+    stack: &mut Vec<T>,
+) {
+    if !matches!(
+        kind,
+        EdgeKind::Branch | EdgeKind::Table(_) | EdgeKind::TableDefault | EdgeKind::Return
+    ) {
+        return;
+    }
+    let node = match &tree.array[from as usize] {
+        SerializedInstruction::Branch { targets, .. } => targets
+            .iter()
+            .find(|target| target.continuation == to)
+            .and_then(|target| tree.root.get(target.node as usize)),
+        _ => None,
+    };
+    // The function body starts with an empty stack
+    let base = match node {
+        Some(node) if node.depth != 0 => {
+            let start = node.start as usize;
+            let popped = stack_types.effects.get(start).map_or(0, |e| e.popped.len());
+            stack_types.before[start].len().saturating_sub(popped)
+        }
+        _ => 0,
+    };
+    let height = stack_types.before[to as usize].len();
+    let base = base.min(height);
+    let carried = stack.len().saturating_sub(height - base);
+    let top = stack.split_off(carried);
+    stack.truncate(base);
+    stack.extend(top);
+}
+
+/// Whether control can only continue to the next instruction
+fn falls_through(instruction: &SerializedInstruction) -> bool {
+    !matches!(
+        instruction,
+        SerializedInstruction::Simple(SimpleInstruction::Return | SimpleInstruction::Unreachable)
+            | SerializedInstruction::Branch { .. }
+            | SerializedInstruction::Block {
+                kind: BlockKind::If | BlockKind::Else,
+                ..
+            }
+    )
+}
+
+impl ControlFlowGraph {
+    pub fn new(tree: &SerializedInstructionTree) -> Self {
+        let count = tree.array.len() as u32;
+        let successors: Vec<_> = (0..count).map(|i| successors(tree, i)).collect();
+        // Blocks start at the entry, at every target, and after anything that does not fall through
+        let mut leaders = vec![false; count as usize + 1];
+        leaders[0] = true;
+        for (index, instruction) in tree.array.iter().enumerate() {
+            if !falls_through(instruction) {
+                leaders[index + 1] = true;
+            }
+            for (to, kind) in &successors[index] {
+                if *kind != EdgeKind::Next || *to != index as u32 + 1 {
+                    leaders[*to as usize] = true;
+                }
+            }
+        }
+        let starts: Vec<u32> = (0..count).filter(|i| leaders[*i as usize]).collect();
+        let mut blocks: Vec<_> = starts
+            .iter()
+            .zip(starts.iter().skip(1).chain([&count]))
+            .map(|(start, end)| BasicBlock {
+                instructions: *start..*end,
+            })
+            .collect();
+        blocks.push(BasicBlock {
+            instructions: count..count,
+        });
+        let block_of = |index: u32| {
+            blocks
+                .iter()
+                .position(|b| b.instructions.contains(&index))
+                .unwrap_or(blocks.len() - 1) as u32
+        };
+        let edges = blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, block)| !block.instructions.is_empty())
+            .flat_map(|(from, block)| {
+                let last = block.instructions.end - 1;
+                successors[last as usize]
+                    .iter()
+                    .map(move |(to, kind)| (from as u32, last, *to, *kind))
+            })
+            .map(|(from, last, to, kind)| Edge {
+                from,
+                to: block_of(to),
+                kind,
+                is_back_edge: to <= last,
+            })
+            .collect();
+        Self { blocks, edges }
+    }
+
+    /// Index of the block with no instructions that stands for leaving the function
+    pub fn exit(&self) -> u32 {
+        self.blocks.len().saturating_sub(1) as u32
+    }
+
+    /// Lines of text for each block, with the exit block named "exit"
+    fn block_lines(&self, instructions: &[SerializedInstruction]) -> Vec<Vec<String>> {
+        self.blocks
+            .iter()
+            .map(|block| {
+                if block.instructions.is_empty() {
+                    return vec!["exit".to_string()];
+                }
+                block
+                    .instructions
+                    .clone()
+                    .map(|i| instructions[i as usize].to_string())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Graph in the Graphviz DOT language
+    pub fn to_dot(&self, name: &str, instructions: &[SerializedInstruction]) -> String {
+        let escape = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut dot = format!("digraph \"{}\" {{\n", escape(name));
+        dot += "    node [shape=box, fontname=\"monospace\"];\n";
+        for (id, lines) in self.block_lines(instructions).iter().enumerate() {
+            if id as u32 == self.exit() {
+                dot += &format!("    b{id} [label=\"exit\", shape=oval];\n");
+            } else {
+                // `\l` ends a left aligned line
+                let label: String = lines.iter().map(|l| escape(l) + "\\l").collect();
+                dot += &format!("    b{id} [label=\"{label}\"];\n");
+            }
+        }
+        for edge in &self.edges {
+            let mut attributes = Vec::new();
+            if edge.kind != EdgeKind::Next {
+                attributes.push(format!("label=\"{}\"", edge.kind));
+            }
+            if edge.is_back_edge {
+                attributes.push("style=dashed".to_string());
+            }
+            dot += &format!("    b{} -> b{}", edge.from, edge.to);
+            if !attributes.is_empty() {
+                dot += &format!(" [{}]", attributes.join(", "));
+            }
+            dot += ";\n";
+        }
+        dot += "}\n";
+        dot
+    }
+
+    /// Graph as a Mermaid flowchart
+    pub fn to_mermaid(&self, instructions: &[SerializedInstruction]) -> String {
+        let escape = |text: &str| text.replace('"', "#quot;");
+        let mut mermaid = "flowchart TD\n".to_string();
+        for (id, lines) in self.block_lines(instructions).iter().enumerate() {
+            if id as u32 == self.exit() {
+                mermaid += &format!("    b{id}((exit))\n");
+            } else {
+                let label: Vec<_> = lines.iter().map(|l| escape(l)).collect();
+                mermaid += &format!("    b{id}[\"{}\"]\n", label.join("<br/>"));
+            }
+        }
+        for edge in &self.edges {
+            let arrow = if edge.is_back_edge { "-.->" } else { "-->" };
+            let label = edge.kind.to_string();
+            if label.is_empty() {
+                mermaid += &format!("    b{} {arrow} b{}\n", edge.from, edge.to);
+            } else {
+                mermaid += &format!("    b{} {arrow}|{label}| b{}\n", edge.from, edge.to);
+            }
+        }
+        mermaid
+    }
+}
+
+/// Control flow graph of a function, along with its exported forms
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct FunctionGraph {
+    pub name: String,
+    pub graph: ControlFlowGraph,
+    pub dot: String,
+    pub mermaid: String,
+}
+
+/// Build the control flow graph of every function in the text
+pub fn control_flow_graphs(text: &str) -> WatResult<Vec<FunctionGraph>> {
+    let structure = build_structure(text)?;
+    Ok(structure
+        .func
+        .iter()
+        .map(|func| {
+            let name = func.name().unwrap_or_default();
+            let graph = ControlFlowGraph::new(&func.block);
+            FunctionGraph {
+                dot: graph.to_dot(&name, &func.block.array),
+                mermaid: graph.to_mermaid(&func.block.array),
+                name,
+                graph,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::module_with_func;
+
+    /// Edges as (first instruction of the source block, first instruction of the target, kind, back edge)
+    fn edges(body: &str) -> Vec<(u32, u32, EdgeKind, bool)> {
+        let graph = control_flow_graphs(&module_with_func("", "(param i32)", body))
+            .unwrap()
+            .remove(0)
+            .graph;
+        let start = |block: u32| graph.blocks[block as usize].instructions.start;
+        graph
+            .edges
+            .iter()
+            .map(|edge| {
+                (
+                    start(edge.from),
+                    start(edge.to),
+                    edge.kind,
+                    edge.is_back_edge,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loops_have_back_edges() {
+        let edges = edges("loop $l local.get 0 br_if $l end");
+        assert!(edges.contains(&(1, 1, EdgeKind::Branch, true)));
+        assert!(edges.contains(&(1, 3, EdgeKind::NotTaken, false)));
+        assert_eq!(edges.iter().filter(|edge| edge.3).count(), 1);
+    }
+
+    #[test]
+    fn conditionals_and_tables_branch_to_each_arm() {
+        let arms = edges("local.get 0 if nop else nop end");
+        assert!(arms.contains(&(0, 2, EdgeKind::Then, false)));
+        assert!(arms.contains(&(0, 4, EdgeKind::Else, false)));
+
+        let cases = edges("block block local.get 0 br_table 0 1 end nop end");
+        assert!(cases.contains(&(0, 5, EdgeKind::Table(0), false)));
+        assert!(cases.contains(&(0, 7, EdgeKind::TableDefault, false)));
+        assert!(cases.iter().all(|edge| !edge.3));
+    }
+}
@@ -0,0 +1,551 @@
+//! Completion candidates for the WAT text being typed.
+//!
+//! The word under the cursor is blanked out so the rest of the module can still be parsed,
+//! then the validator's stack at that point decides which instructions fit.
+
+use std::{ops::Range, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use wast::{
+    core::Instruction,
+    parser::{self, ParseBuffer},
+};
+
+use crate::{
+    build_structure,
+    instruction::SerializedInstruction,
+    marker::{BlockKind, SerializableWatType},
+    source::{is_id_char, tokens, NameKind, SourceMap},
+    validator::{StackEffect, Validator, ValueMapping},
+    InterpreterStructure,
+};
+
+/// Instructions without immediates or whose immediates are filled in when checking them
+const INSTRUCTIONS: &[&str] = &[
+    "unreachable",
+    "nop",
+    "drop",
+    "return",
+    "block",
+    "loop",
+    "if",
+    "else",
+    "end",
+    "br 0",
+    "br_if 0",
+    "br_table 0",
+    "call 0",
+    "local.get 0",
+    "local.set 0",
+    "local.tee 0",
+    "global.get 0",
+    "global.set 0",
+    "memory.size",
+    "memory.grow",
+    "i32.load",
+    "i32.load8_s",
+    "i32.load8_u",
+    "i32.load16_s",
+    "i32.load16_u",
+    "i64.load",
+    "i64.load8_s",
+    "i64.load8_u",
+    "i64.load16_s",
+    "i64.load16_u",
+    "i64.load32_s",
+    "i64.load32_u",
+    "f32.load",
+    "f64.load",
+    "i32.store",
+    "i32.store8",
+    "i32.store16",
+    "i64.store",
+    "i64.store8",
+    "i64.store16",
+    "i64.store32",
+    "f32.store",
+    "f64.store",
+    "i32.const 0",
+    "i64.const 0",
+    "f32.const 0",
+    "f64.const 0",
+    "i32.wrap_i64",
+    "i32.trunc_f32_s",
+    "i32.trunc_f32_u",
+    "i32.trunc_f64_s",
+    "i32.trunc_f64_u",
+    "i64.extend_i32_s",
+    "i64.extend_i32_u",
+    "i64.trunc_f32_s",
+    "i64.trunc_f32_u",
+    "i64.trunc_f64_s",
+    "i64.trunc_f64_u",
+    "f32.convert_i32_s",
+    "f32.convert_i32_u",
+    "f32.convert_i64_s",
+    "f32.convert_i64_u",
+    "f32.demote_f64",
+    "f64.convert_i32_s",
+    "f64.convert_i32_u",
+    "f64.convert_i64_s",
+    "f64.convert_i64_u",
+    "f64.promote_f32",
+    "i32.reinterpret_f32",
+    "i64.reinterpret_f64",
+    "f32.reinterpret_i32",
+    "f64.reinterpret_i64",
+];
+
+/// Operations shared by `i32` and `i64`
+const INTEGER_OPERATIONS: &[&str] = &[
+    "eqz", "eq", "ne", "lt_s", "lt_u", "gt_s", "gt_u", "le_s", "le_u", "ge_s", "ge_u", "clz",
+    "ctz", "popcnt", "add", "sub", "mul", "div_s", "div_u", "rem_s", "rem_u", "and", "or", "xor",
+    "shl", "shr_s", "shr_u", "rotl", "rotr",
+];
+
+/// Operations shared by `f32` and `f64`
+const FLOAT_OPERATIONS: &[&str] = &[
+    "eq", "ne", "lt", "gt", "le", "ge", "abs", "neg", "ceil", "floor", "trunc", "nearest", "sqrt",
+    "add", "sub", "mul", "div", "min", "max", "copysign",
+];
+
+/// Every supported instruction with its mnemonic, parsed once
+fn catalog() -> &'static [(String, SerializedInstruction)] {
+    static CATALOG: OnceLock<Vec<(String, SerializedInstruction)>> = OnceLock::new();
+    CATALOG.get_or_init(|| {
+        let integer = ["i32", "i64"].iter().flat_map(|typ| {
+            INTEGER_OPERATIONS
+                .iter()
+                .map(move |op| format!("{typ}.{op}"))
+        });
+        let float = ["f32", "f64"]
+            .iter()
+            .flat_map(|typ| FLOAT_OPERATIONS.iter().map(move |op| format!("{typ}.{op}")));
+        INSTRUCTIONS
+            .iter()
+            .map(|text| text.to_string())
+            .chain(integer)
+            .chain(float)
+            .filter_map(|text| {
+                let buffer = ParseBuffer::new(&text).ok()?;
+                let instruction = parser::parse::<Instruction>(&buffer).ok()?;
+                let instruction = SerializedInstruction::try_from(&instruction).ok()?;
+                let mnemonic = text.split_whitespace().next()?.to_string();
+                (!matches!(instruction, SerializedInstruction::DefaultString(_)))
+                    .then_some((mnemonic, instruction))
+            })
+            .collect()
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum CompletionKind {
+    Instruction,
+    Local,
+    Global,
+    Function,
+    Label,
+    Memory,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct CompletionItem {
+    /// Text to insert
+    pub label: String,
+    pub kind: CompletionKind,
+    /// Type of the item, or what the instruction pops and pushes
+    pub detail: String,
+    /// Lower is better, items are already sorted by it
+    pub rank: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, Type)]
+pub struct Completions {
+    /// The partly typed word the items replace
+    pub span: Range<u32>,
+    pub items: Vec<CompletionItem>,
+}
+
+/// What is known about the function at the cursor
+struct Scope<'s> {
+    structure: &'s InterpreterStructure,
+    source: &'s SourceMap,
+    text: &'s str,
+    function: usize,
+    /// Index of the instruction that would come next
+    index: usize,
+    /// Types on the stack at the cursor, [None] if validation failed before it
+    stack: Option<Vec<SerializableWatType>>,
+    locals: ValueMapping<SerializableWatType>,
+}
+
+impl Scope<'_> {
+    /// Names of one kind, in the order they should be shown
+    fn names(&self, kind: NameKind) -> Vec<CompletionItem> {
+        let item = |name: &str, kind, detail: String| CompletionItem {
+            label: format!("${name}"),
+            kind,
+            detail,
+            rank: 0,
+        };
+        match kind {
+            NameKind::Local => self
+                .locals
+                .names()
+                .into_iter()
+                .filter_map(|(name, i)| {
+                    let typ = self.locals.get_by_index(i)?;
+                    Some(item(name, CompletionKind::Local, type_name(typ)))
+                })
+                .collect(),
+            NameKind::Global => self
+                .structure
+                .globals
+                .iter()
+                .filter(|g| is_name(&g.name))
+                .map(|g| {
+                    let detail = if g.is_mutable {
+                        format!("mut {}", type_name(&g.typ))
+                    } else {
+                        type_name(&g.typ)
+                    };
+                    item(&g.name, CompletionKind::Global, detail)
+                })
+                .collect(),
+            NameKind::Function => self
+                .structure
+                .func
+                .iter()
+                .filter_map(|f| {
+                    let name = f.name().filter(|name| is_name(name))?;
+                    let params: Vec<_> = f.info.input.iter().map(|(_, typ)| *typ).collect();
+                    let detail = signature(&params, &f.info.output);
+                    Some(item(&name, CompletionKind::Function, detail))
+                })
+                .collect(),
+            NameKind::Label => self.source.functions[self.function]
+                .labels_in_scope(self.text, self.index)
+                .into_iter()
+                .map(|def| item(&def.name, CompletionKind::Label, "label".to_string()))
+                .collect(),
+            NameKind::Memory => self
+                .structure
+                .memory
+                .iter()
+                .filter(|m| is_name(&m.name))
+                .map(|m| item(&m.name, CompletionKind::Memory, "memory".to_string()))
+                .collect(),
+        }
+    }
+
+    /// Indices that an instruction using this kind of name could refer to
+    fn targets(&self, kind: NameKind) -> Vec<String> {
+        let count = match kind {
+            NameKind::Local => self.locals.len(),
+            NameKind::Global => self.structure.globals.len(),
+            NameKind::Function => self.structure.func.len(),
+            // The validator looks memory up by its name
+            NameKind::Memory => {
+                return self
+                    .structure
+                    .memory
+                    .iter()
+                    .map(|m| m.name.clone())
+                    .collect();
+            }
+            NameKind::Label => 0,
+        };
+        (0..count).map(|i| i.to_string()).collect()
+    }
+
+    /// Check the instruction against the stack, trying every target if it needs one
+    fn effect_of(
+        &self,
+        validator: &mut Validator,
+        mnemonic: &str,
+        instruction: &SerializedInstruction,
+        stack: &[SerializableWatType],
+    ) -> Option<StackEffect> {
+        let results = &self.structure.func[self.function].info.output;
+        match NameKind::used_by(mnemonic) {
+            Some(kind) => self.targets(kind).iter().find_map(|target| {
+                validator.try_instruction(
+                    &with_target(instruction, target),
+                    stack,
+                    &self.locals,
+                    results,
+                )
+            }),
+            None => validator.try_instruction(instruction, stack, &self.locals, results),
+        }
+    }
+
+    fn instructions(&self) -> Vec<CompletionItem> {
+        let Some(stack) = &self.stack else {
+            return all_instructions();
+        };
+        let mut validator = Validator::new(self.structure);
+        catalog()
+            .iter()
+            .filter_map(|(mnemonic, instruction)| {
+                // Control flow depends on the blocks around the cursor, so it is always offered
+                if is_control_flow(instruction) {
+                    return Some(instruction_item(mnemonic, None, 1));
+                }
+                let effect = self.effect_of(&mut validator, mnemonic, instruction, stack)?;
+                // Prefer instructions that use what is already on the stack
+                let rank = if effect.popped.is_empty() { 1 } else { 0 };
+                Some(instruction_item(mnemonic, Some(&effect), rank))
+            })
+            .collect()
+    }
+}
+
+/// Names given by the user, instead of the index used for unnamed items
+fn is_name(name: &str) -> bool {
+    !name.is_empty() && name.parse::<usize>().is_err()
+}
+
+/// Type as written in WAT, like `i32`
+fn type_name(typ: &SerializableWatType) -> String {
+    typ.to_string().to_lowercase()
+}
+
+fn signature(input: &[SerializableWatType], output: &[SerializableWatType]) -> String {
+    let names =
+        |types: &[SerializableWatType]| types.iter().map(type_name).collect::<Vec<_>>().join(" ");
+    format!("[{}] -> [{}]", names(input), names(output))
+}
+
+fn is_control_flow(instruction: &SerializedInstruction) -> bool {
+    matches!(
+        instruction,
+        SerializedInstruction::Branch { .. }
+            | SerializedInstruction::Block {
+                kind: BlockKind::Else | BlockKind::End,
+                ..
+            }
+    )
+}
+
+fn with_target(instruction: &SerializedInstruction, target: &str) -> SerializedInstruction {
+    let mut instruction = instruction.clone();
+    match &mut instruction {
+        SerializedInstruction::Data { location, .. }
+        | SerializedInstruction::Memory { location, .. } => *location = target.to_string(),
+        SerializedInstruction::Call { index, .. } => *index = target.to_string(),
+        _ => {}
+    }
+    instruction
+}
+
+fn instruction_item(mnemonic: &str, effect: Option<&StackEffect>, rank: u32) -> CompletionItem {
+    CompletionItem {
+        label: mnemonic.to_string(),
+        kind: CompletionKind::Instruction,
+        detail: effect
+            .map(|effect| signature(&effect.popped, &effect.pushed))
+            .unwrap_or_default(),
+        rank,
+    }
+}
+
+/// Every instruction, used when the stack at the cursor is unknown
+fn all_instructions() -> Vec<CompletionItem> {
+    catalog()
+        .iter()
+        .map(|(mnemonic, _)| instruction_item(mnemonic, None, 0))
+        .collect()
+}
+
+/// Number of `(` without a matching `)`
+fn unclosed_parens(text: &str) -> usize {
+    tokens(text).into_iter().fold(0_usize, |depth, token| {
+        match &text[token.start as usize..token.end as usize] {
+            "(" => depth + 1,
+            ")" => depth.saturating_sub(1),
+            _ => depth,
+        }
+    })
+}
+
+/// Start of the word that ends at the offset
+fn word_start(text: &str, offset: usize) -> usize {
+    text.as_bytes()[..offset]
+        .iter()
+        .rposition(|b| !is_id_char(b))
+        .map_or(0, |i| i + 1)
+}
+
+/// Replace the range with spaces, keeping every other offset the same,
+/// and close any parentheses left open
+fn blank_out(text: &str, range: Range<usize>) -> String {
+    let mut start = range.start;
+    let mut end = range.end;
+    // An empty folded instruction would not parse
+    let after = text[end..].len() - text[end..].trim_start().len();
+    if text[..start].ends_with('(') && text[end + after..].starts_with(')') {
+        start -= 1;
+        end += after + 1;
+    }
+    let mut patched = String::with_capacity(text.len());
+    patched.push_str(&text[..start]);
+    patched.push_str(&" ".repeat(end - start));
+    patched.push_str(&text[end..]);
+    let missing = unclosed_parens(&patched);
+    patched.push_str(&")".repeat(missing));
+    patched
+}
+
+/// Completion candidates for the word ending at the byte offset, best first
+pub fn complete_at(text: &str, offset: u32) -> Completions {
+    let mut offset = (offset as usize).min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let start = word_start(text, offset);
+    let prefix = &text[start..offset];
+    let before = text[..start].trim_end();
+    let previous_start = word_start(text, before.len());
+    let previous = NameKind::used_by(&text[previous_start..before.len()]);
+    // Names are needed right after an instruction that takes one, memory indices are optional
+    let name_kind = match previous {
+        Some(NameKind::Memory) if prefix.is_empty() => None,
+        Some(kind) if prefix.is_empty() || prefix.starts_with('$') => Some(kind),
+        _ => None,
+    };
+    let patched = if name_kind.is_some() {
+        blank_out(text, previous_start..offset)
+    } else {
+        blank_out(text, start..offset)
+    };
+    let span = start as u32..offset as u32;
+
+    let (Ok(structure), Ok(source)) = (build_structure(&patched), SourceMap::try_new(&patched))
+    else {
+        return Completions {
+            span,
+            items: filter_prefix(all_instructions(), prefix),
+        };
+    };
+    let Some((function, function_source)) = source.function_at(start as u32) else {
+        return Completions {
+            span,
+            items: Vec::new(),
+        };
+    };
+    let index = function_source
+        .instructions
+        .iter()
+        .position(|ins| ins.end as usize > start)
+        .unwrap_or(function_source.instructions.len());
+    let func = &structure.func[function];
+    let scope = Scope {
+        structure: &structure,
+        source: &source,
+        text: &patched,
+        function,
+        index,
+        stack: structure
+            .stack_types()
+            .into_iter()
+            .nth(function)
+            .and_then(|types| types.before.get(index).cloned()),
+        locals: func
+            .info
+            .input
+            .iter()
+            .chain(func.locals.iter())
+            .cloned()
+            .collect(),
+    };
+
+    let items = match name_kind {
+        Some(kind) => {
+            let mut items = scope.names(kind);
+            // Setting a local or global works best with one of the type on top of the stack
+            if let (Some(top), true) = (
+                scope.stack.as_ref().and_then(|stack| stack.last()),
+                text[previous_start..].starts_with("local.set")
+                    || text[previous_start..].starts_with("local.tee")
+                    || text[previous_start..].starts_with("global.set"),
+            ) {
+                for item in &mut items {
+                    if !item.detail.ends_with(&type_name(top)) {
+                        item.rank = 1;
+                    }
+                }
+            }
+            items
+        }
+        None if prefix.starts_with('$') => [
+            NameKind::Local,
+            NameKind::Label,
+            NameKind::Global,
+            NameKind::Function,
+            NameKind::Memory,
+        ]
+        .into_iter()
+        .flat_map(|kind| scope.names(kind))
+        .collect(),
+        None => scope.instructions(),
+    };
+    Completions {
+        span,
+        items: filter_prefix(items, prefix),
+    }
+}
+
+/// Keep items starting with what is already typed, sorted by rank
+fn filter_prefix(items: Vec<CompletionItem>, prefix: &str) -> Vec<CompletionItem> {
+    let mut items: Vec<_> = items
+        .into_iter()
+        .filter(|item| item.label.starts_with(prefix))
+        .collect();
+    // Stable, so names keep their scope order
+    items.sort_by_key(|item| item.rank);
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Labels of the completions at the `|` in the text
+    fn labels(marked: &str) -> Vec<String> {
+        let offset = marked.find('|').unwrap();
+        let text = marked.replace('|', "");
+        complete_at(&text, offset as u32)
+            .items
+            .into_iter()
+            .map(|item| item.label)
+            .collect()
+    }
+
+    #[test]
+    fn names_follow_instructions_that_take_them() {
+        let locals = labels("(module (func (param $a i32) (local $b i64) local.get |))");
+        assert_eq!(locals, ["$a", "$b"]);
+        let functions = labels("(module (func $f) (func $g call $|))");
+        assert_eq!(functions, ["$f", "$g"]);
+    }
+
+    #[test]
+    fn instructions_fit_the_stack() {
+        let text = "(module (func (result i32) i32.const 1 i32.const 2 i32.a))";
+        let offset = text.find("a))").unwrap() as u32 + 1;
+        let completions = complete_at(text, offset);
+        assert_eq!(completions.span, offset - 5..offset);
+        let add = &completions.items[0];
+        assert_eq!(add.label, "i32.add");
+        assert_eq!(add.detail, "[i32 i32] -> [i32]");
+        // Nothing that needs an i64 can follow an i32
+        assert!(labels("(module (func i32.const 1 i64.ad|))").is_empty());
+    }
+
+    #[test]
+    fn unparsable_text_offers_every_instruction() {
+        assert_eq!(labels("(module (func (((( i32.ad|"), ["i32.add"]);
+    }
+}
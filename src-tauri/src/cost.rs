@@ -0,0 +1,270 @@
+//! Numbers about each function found without running it: how high the operand stack gets,
+//! how deeply blocks nest, and how many instructions a call runs.
+//!
+//! The cost of a call is every instruction on the way from the entry to the end of the function,
+//! with the callee's cost added for each direct `call`. A loop that can repeat,
+//! or a call into recursion, makes the most instructions unbounded.
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    callgraph::{resolve_function, CallGraph},
+    cfg::ControlFlowGraph,
+    error::WatResult,
+    inner_transform,
+    instruction::SerializedInstruction,
+    validator::Validator,
+    InterpreterStructure, WastFunc,
+};
+
+/// Most passes over the module when costs depend on each other through recursion
+const MAX_PASSES: usize = 64;
+
+/// Fewest and most instructions one call can run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, Type)]
+pub struct Cost {
+    /// [None] if the function can never return
+    pub min: Option<u32>,
+    /// [None] if there is no limit, because of a loop or recursion
+    pub max: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct FunctionCost {
+    pub name: String,
+    /// Most values on the operand stack at once
+    pub max_stack_height: u32,
+    /// Most blocks, loops and ifs inside each other
+    pub max_nesting_depth: u32,
+    pub cost: Cost,
+    /// Index of each `loop` instruction that can repeat
+    pub unbounded_loops: Vec<u32>,
+}
+
+/// Cost of the instruction by itself, adding what a direct call runs
+fn instruction_cost(
+    functions: &[WastFunc],
+    costs: &[Cost],
+    instruction: &SerializedInstruction,
+) -> Cost {
+    match instruction {
+        SerializedInstruction::Call { index } => {
+            let callee = resolve_function(functions, index)
+                .and_then(|callee| costs.get(callee))
+                .copied()
+                .unwrap_or_default();
+            Cost {
+                min: callee.min.map(|min| min.saturating_add(1)),
+                max: callee.max.map(|max| max.saturating_add(1)),
+            }
+        }
+        _ => Cost {
+            min: Some(1),
+            max: Some(1),
+        },
+    }
+}
+
+/// Fewest and most instructions on the paths through the graph, given the cost of each callee.
+///
+/// Blocks are in instruction order, so every edge that is not a back edge goes forward.
+fn path_cost(
+    functions: &[WastFunc],
+    costs: &[Cost],
+    func: &WastFunc,
+    graph: &ControlFlowGraph,
+    has_loop: bool,
+) -> Cost {
+    let count = graph.blocks.len();
+    // Cost of reaching the start of each block
+    let mut min: Vec<Option<u32>> = vec![None; count];
+    let mut max: Vec<Option<u32>> = vec![None; count];
+    let mut is_bounded = !has_loop;
+    if count > 0 {
+        min[0] = Some(0);
+        max[0] = Some(0);
+    }
+    for (index, block) in graph.blocks.iter().enumerate() {
+        let (Some(start_min), Some(start_max)) = (min[index], max[index]) else {
+            continue;
+        };
+        let (mut end_min, mut end_max) = (Some(start_min), Some(start_max));
+        for i in block.instructions.clone() {
+            let cost = instruction_cost(functions, costs, &func.block.array[i as usize]);
+            end_min = end_min.zip(cost.min).map(|(a, b)| a.saturating_add(b));
+            end_max = match (end_max, cost.max) {
+                (Some(a), Some(b)) => Some(a.saturating_add(b)),
+                _ => {
+                    // Calling something unbounded only matters if it can return
+                    is_bounded &= cost.min.is_none();
+                    None
+                }
+            };
+        }
+        let Some(end_min) = end_min else {
+            continue;
+        };
+        for edge in graph.edges.iter().filter(|e| e.from == index as u32) {
+            if edge.is_back_edge {
+                continue;
+            }
+            let to = edge.to as usize;
+            min[to] = Some(min[to].map_or(end_min, |m| m.min(end_min)));
+            if let Some(end_max) = end_max {
+                max[to] = Some(max[to].map_or(end_max, |m| m.max(end_max)));
+            }
+        }
+    }
+    let exit = graph.exit() as usize;
+    Cost {
+        min: min.get(exit).copied().flatten(),
+        max: max.get(exit).copied().flatten().filter(|_| is_bounded),
+    }
+}
+
+/// Cost of every function, repeated until recursive functions stop changing
+fn module_costs(
+    structure: &InterpreterStructure,
+    graphs: &[(ControlFlowGraph, bool)],
+) -> Vec<Cost> {
+    let functions = &structure.func;
+    let call_graph = CallGraph::new(structure);
+    let mut costs = vec![Cost::default(); functions.len()];
+    for _ in 0..MAX_PASSES {
+        let next: Vec<Cost> = functions
+            .iter()
+            .zip(graphs)
+            .zip(&call_graph.nodes)
+            .map(|((func, (graph, has_loop)), node)| {
+                let cost = path_cost(functions, &costs, func, graph, *has_loop);
+                Cost {
+                    min: cost.min,
+                    max: cost.max.filter(|_| node.recursion.is_none()),
+                }
+            })
+            .collect();
+        if next == costs {
+            break;
+        }
+        costs = next;
+    }
+    costs
+}
+
+/// Stack height, nesting depth and cost of every function in the text
+pub fn function_costs(text: &str) -> WatResult<Vec<FunctionCost>> {
+    let structure = inner_transform(text)?;
+    let mut validator = Validator::new(&structure);
+    let graphs: Vec<(ControlFlowGraph, bool)> = structure
+        .func
+        .iter()
+        .map(|func| {
+            let graph = ControlFlowGraph::new(&func.block);
+            let has_loop = graph.edges.iter().any(|edge| edge.is_back_edge);
+            (graph, has_loop)
+        })
+        .collect();
+    let costs = module_costs(&structure, &graphs);
+    Ok(structure
+        .func
+        .iter()
+        .zip(graphs)
+        .zip(costs)
+        .map(|((func, (graph, _)), cost)| {
+            let stack_types = validator.infer_stack_types(
+                &func.block.array,
+                &func.info.input,
+                &func.locals,
+                &func.info.output,
+            );
+            let mut unbounded_loops: Vec<u32> = graph
+                .edges
+                .iter()
+                .filter(|edge| edge.is_back_edge)
+                // A branch to a loop goes to the instruction after it
+                .filter_map(|edge| graph.blocks.get(edge.to as usize))
+                .map(|block| block.instructions.start.saturating_sub(1))
+                .collect();
+            unbounded_loops.sort();
+            unbounded_loops.dedup();
+            FunctionCost {
+                name: func.name().unwrap_or_default(),
+                max_stack_height: stack_types
+                    .before
+                    .iter()
+                    .map(|stack| stack.len() as u32)
+                    .max()
+                    .unwrap_or_default(),
+                max_nesting_depth: func
+                    .block
+                    .root
+                    .iter()
+                    .map(|node| node.depth)
+                    .max()
+                    .unwrap_or_default(),
+                cost,
+                unbounded_loops,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn costs(text: &str) -> Vec<FunctionCost> {
+        function_costs(text).unwrap()
+    }
+
+    fn cost(min: Option<u32>, max: Option<u32>) -> Cost {
+        Cost { min, max }
+    }
+
+    #[test]
+    fn branches_give_the_fewest_and_most_instructions() {
+        let costs = costs(
+            "(module (func (param i32)
+                local.get 0
+                if
+                    i32.const 1 i32.const 2 i32.add drop
+                else
+                    nop
+                end))",
+        );
+        // `local.get`, `if`, `nop` and `end`, or the other arm with its `else`
+        assert_eq!(costs[0].cost, cost(Some(4), Some(8)));
+        assert_eq!(costs[0].max_stack_height, 2);
+        assert_eq!(costs[0].max_nesting_depth, 1);
+        assert!(costs[0].unbounded_loops.is_empty());
+    }
+
+    #[test]
+    fn calls_add_the_cost_of_the_callee() {
+        let costs = costs(
+            "(module
+                (func $leaf nop nop)
+                (func call $leaf call $leaf))",
+        );
+        assert_eq!(costs[0].cost, cost(Some(2), Some(2)));
+        assert_eq!(costs[1].cost, cost(Some(6), Some(6)));
+    }
+
+    #[test]
+    fn loops_and_recursion_have_no_limit() {
+        let costs = costs(
+            "(module
+                (func (param i32) loop $l local.get 0 br_if $l end)
+                (func $f (param i32) local.get 0 if local.get 0 call $f end)
+                (func i32.const 0 call 1)
+                (func loop $l br $l end))",
+        );
+        assert_eq!(costs[0].cost, cost(Some(4), None));
+        assert_eq!(costs[0].unbounded_loops, [0]);
+        assert_eq!(costs[1].cost, cost(Some(3), None));
+        assert_eq!(costs[2].cost, cost(Some(5), None));
+        // Never returns
+        assert_eq!(costs[3].cost, cost(None, None));
+    }
+}
@@ -0,0 +1,284 @@
+//! Code that can never run and items that are never used, reported as warnings.
+//!
+//! Unreachable instructions are the ones the validator marks after a `br`, `br_table`,
+//! `return` or `unreachable`. Functions are used when they can be called, directly or not,
+//! from an export or the start function. `call_indirect` is not followed, since tables are not supported.
+
+use std::{collections::HashSet, ops::Range};
+
+use crate::{
+    callgraph::CallGraph,
+    diagnostic::Diagnostic,
+    instruction::SerializedInstruction,
+    marker::{BlockKind, DataInstruction},
+    source::{FunctionSource, SourceMap},
+    validator::{try_name_to_index, Validator},
+    InterpreterStructure, NumLocationKind, WastFunc,
+};
+
+/// Every warning for a module that passed validation, in text order
+pub fn dead_code(structure: &InterpreterStructure, source: &SourceMap) -> Vec<Diagnostic> {
+    let mut warnings = unreachable_code(structure, source);
+    warnings.extend(unused_locals(structure, source));
+    warnings.extend(unused_functions(structure, source));
+    warnings.extend(unused_globals(structure, source));
+    warnings.sort_by_key(|warning| warning.span.start);
+    warnings
+}
+
+/// `$name` if the item has one, otherwise its index
+fn display_name(name: Option<&str>, index: usize) -> String {
+    match name {
+        Some(name) if !name.is_empty() => format!("${name}"),
+        _ => index.to_string(),
+    }
+}
+
+/// Name of the function, or its `func` keyword when it has none
+fn function_span(function: &FunctionSource) -> Range<u32> {
+    function
+        .name
+        .as_ref()
+        .map(|def| def.span.clone())
+        .unwrap_or(function.keyword.clone())
+}
+
+/// One warning for each run of instructions that can never run
+fn unreachable_code(structure: &InterpreterStructure, source: &SourceMap) -> Vec<Diagnostic> {
+    let mut validator = Validator::new(structure);
+    let mut warnings = Vec::new();
+    for (func, func_source) in structure.func.iter().zip(&source.functions) {
+        let stack_types = validator.infer_stack_types(
+            &func.block.array,
+            &func.info.input,
+            &func.locals,
+            &func.info.output,
+        );
+        let mut runs: Vec<Range<u32>> = Vec::new();
+        let mut in_run = false;
+        for (index, instruction) in func.block.array.iter().enumerate() {
+            // The end of a block still runs when something branches to it
+            if matches!(
+                instruction,
+                SerializedInstruction::Block {
+                    kind: BlockKind::Else | BlockKind::End,
+                    ..
+                }
+            ) {
+                continue;
+            }
+            let is_unreachable = stack_types.unreachable.get(index).copied() == Some(true);
+            match (func_source.instructions.get(index), runs.last_mut()) {
+                (Some(span), Some(run)) if is_unreachable && in_run => {
+                    // Folded instructions are not in text order
+                    run.start = run.start.min(span.start);
+                    run.end = run.end.max(span.end);
+                }
+                (Some(span), _) if is_unreachable => runs.push(span.clone()),
+                _ => {}
+            }
+            in_run = is_unreachable;
+        }
+        warnings.extend(
+            runs.into_iter()
+                .map(|span| Diagnostic::warning(span, "Unreachable code".to_string())),
+        );
+    }
+    warnings
+}
+
+/// Index of a local (counting parameters first) named by a `local.*` instruction
+pub(crate) fn local_index(func: &WastFunc, location: &str) -> Option<usize> {
+    match try_name_to_index(location) {
+        Ok(index) => Some(index),
+        Err(name) => func
+            .info
+            .input
+            .iter()
+            .chain(func.locals.iter())
+            .position(|(local, _)| local.as_deref() == Some(name)),
+    }
+}
+
+/// Locals (not parameters) that are never read or written
+fn unused_locals(structure: &InterpreterStructure, source: &SourceMap) -> Vec<Diagnostic> {
+    let mut warnings = Vec::new();
+    for (func, func_source) in structure.func.iter().zip(&source.functions) {
+        let used: HashSet<usize> = func
+            .block
+            .array
+            .iter()
+            .filter_map(|instruction| match instruction {
+                SerializedInstruction::Data {
+                    kind:
+                        DataInstruction::GetLocal
+                        | DataInstruction::SetLocal
+                        | DataInstruction::TeeLocal,
+                    location,
+                } => local_index(func, location),
+                _ => None,
+            })
+            .collect();
+        let params = func.info.input.len();
+        for (i, (name, _)) in func.locals.iter().enumerate() {
+            if used.contains(&(params + i)) {
+                continue;
+            }
+            let span = name
+                .as_ref()
+                .and_then(|name| func_source.locals.iter().find(|def| &def.name == name))
+                .map(|def| def.span.clone())
+                .unwrap_or(function_span(func_source));
+            warnings.push(Diagnostic::warning(
+                span,
+                format!(
+                    "Local {} is never used",
+                    display_name(name.as_deref(), params + i)
+                ),
+            ));
+        }
+    }
+    warnings
+}
+
+/// Functions that no export or start function can reach through calls
+fn unused_functions(structure: &InterpreterStructure, source: &SourceMap) -> Vec<Diagnostic> {
+    CallGraph::new(structure)
+        .nodes
+        .iter()
+        .zip(&source.functions)
+        .enumerate()
+        .filter(|(_, (node, _))| !node.is_reachable)
+        .map(|(i, (_, func_source))| {
+            Diagnostic::warning(
+                function_span(func_source),
+                format!(
+                    "Function {} is never called or exported",
+                    display_name(func_source.name.as_ref().map(|def| def.name.as_str()), i)
+                ),
+            )
+        })
+        .collect()
+}
+
+/// Globals that no function reads or writes and that are not exported
+fn unused_globals(structure: &InterpreterStructure, source: &SourceMap) -> Vec<Diagnostic> {
+    let globals = &structure.globals;
+    let used: HashSet<usize> = structure
+        .func
+        .iter()
+        .flat_map(|func| func.block.array.iter())
+        .filter_map(|instruction| match instruction {
+            SerializedInstruction::Data {
+                kind: DataInstruction::GetGlobal | DataInstruction::SetGlobal,
+                location,
+            } => match try_name_to_index(location) {
+                Ok(index) => Some(index),
+                Err(name) => globals.iter().position(|g| g.name == name),
+            },
+            _ => None,
+        })
+        .chain(
+            structure
+                .exported
+                .values()
+                .filter(|(kind, _)| *kind == NumLocationKind::Global)
+                .map(|(_, index)| *index as usize),
+        )
+        .collect();
+    globals
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !used.contains(i))
+        .filter_map(|(i, global)| {
+            let span = source
+                .globals
+                .iter()
+                .find(|def| !global.name.is_empty() && def.name == global.name)
+                .map(|def| def.span.clone())
+                .or_else(|| source.global_keywords.get(i).cloned())?;
+            Some(Diagnostic::warning(
+                span,
+                format!(
+                    "Global {} is never used",
+                    display_name(Some(&global.name), i)
+                ),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inner_transform;
+
+    /// Each warning as the text it points to and its message
+    fn warnings(text: &str) -> Vec<(&str, String)> {
+        let structure = inner_transform(text).unwrap();
+        let source = SourceMap::try_new(text).unwrap();
+        dead_code(&structure, &source)
+            .into_iter()
+            .map(|warning| {
+                let span = warning.span.start as usize..warning.span.end as usize;
+                (&text[span], warning.message)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn code_after_a_branch_is_unreachable() {
+        let text = r#"(module (func (export "f") (result i32)
+            i32.const 1 return i32.const 2 i32.const 3 i32.add))"#;
+        assert_eq!(
+            warnings(text),
+            [(
+                "i32.const 2 i32.const 3 i32.add",
+                "Unreachable code".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn block_ends_reached_by_a_branch_are_not_unreachable() {
+        let text = r#"(module (func (export "f") block br 0 end nop))"#;
+        assert_eq!(warnings(text), []);
+    }
+
+    #[test]
+    fn unused_items_are_reported() {
+        let text = r#"(module
+            (global $used (mut i32) (i32.const 0))
+            (global $unused i32 (i32.const 0))
+            (func $helper)
+            (func $dead call $helper)
+            (func (export "f") (param $p i32) (local $x i32) (local $y i32)
+                i32.const 1 local.set $x
+                global.get $used drop))"#;
+        assert_eq!(
+            warnings(text),
+            [
+                ("$unused", "Global $unused is never used".to_string()),
+                (
+                    "$helper",
+                    "Function $helper is never called or exported".to_string()
+                ),
+                (
+                    "$dead",
+                    "Function $dead is never called or exported".to_string()
+                ),
+                ("$y", "Local $y is never used".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn exported_globals_and_the_start_function_are_used() {
+        let text = r#"(module
+            (global $g i32 (i32.const 0))
+            (export "g" (global $g))
+            (func $init)
+            (start $init))"#;
+        assert_eq!(warnings(text), []);
+    }
+}
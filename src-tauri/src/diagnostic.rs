@@ -0,0 +1,65 @@
+//! Errors and warnings about the text, in a form editors can show under the code.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    dead_code::dead_code, error::WatError, inner_transform, ranges::range_warnings,
+    source::SourceMap,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A message about part of the text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct Diagnostic {
+    pub span: Range<u32>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn warning(span: Range<u32>, message: String) -> Self {
+        Self {
+            span,
+            severity: Severity::Warning,
+            message,
+        }
+    }
+}
+
+impl From<&WatError> for Diagnostic {
+    /// Errors without a span point to the start of the text
+    fn from(value: &WatError) -> Self {
+        Self {
+            span: value.span().cloned().unwrap_or(0..0),
+            severity: Severity::Error,
+            message: match value.message() {
+                Some(msg) => format!("{} Error: {msg}", value.stage()),
+                None => format!("{} Error", value.stage()),
+            },
+        }
+    }
+}
+
+/// The first error stopping the module from compiling, or warnings about code
+/// that can never run or is never used and about values that can trap
+pub fn diagnostics(text: &str) -> Vec<Diagnostic> {
+    match inner_transform(text) {
+        Err(err) => vec![(&err).into()],
+        Ok(structure) => SourceMap::try_new(text)
+            .map(|source| {
+                let mut warnings = dead_code(&structure, &source);
+                warnings.extend(range_warnings(&structure, &source));
+                warnings.sort_by_key(|warning| warning.span.start);
+                warnings
+            })
+            .unwrap_or_default(),
+    }
+}
@@ -14,6 +14,21 @@ pub enum ErrorStage {
     TypeChecking,
     NameResolving,
     Unimplemented,
+    Runtime,
+}
+
+/// Why execution stopped, for errors that are traps in the WebAssembly specification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type, derive_more::Display)]
+pub enum TrapKind {
+    Unreachable,
+    /// Signed division of the smallest integer by -1, or a float too large for `trunc`
+    IntegerOverflow,
+    DivideByZero,
+    /// Float that is NaN converted to an integer with `trunc`
+    InvalidConversion,
+    OutOfBoundsMemory,
+    OutOfBoundsTable,
+    CallStackExhausted,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type, derive_more::Error)]
@@ -21,6 +36,7 @@ pub struct WatError {
     span: Option<Range<u32>>,
     stage: ErrorStage,
     message: Option<String>,
+    trap: Option<TrapKind>,
 }
 
 impl Display for WatError {
@@ -41,11 +57,36 @@ impl Display for WatError {
 }
 
 impl WatError {
+    /// Location in the source text this error points to, if known
+    pub fn span(&self) -> Option<&Range<u32>> {
+        self.span.as_ref()
+    }
+
+    pub fn stage(&self) -> ErrorStage {
+        self.stage
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Kind of trap, if running the code trapped
+    pub fn trap(&self) -> Option<TrapKind> {
+        self.trap
+    }
+
+    /// Point this error to a location, unless it already has one
+    pub fn or_span(mut self, span: Range<u32>) -> Self {
+        self.span.get_or_insert(span);
+        self
+    }
+
     pub fn unimplemented_error(msg: &str) -> Self {
         Self {
             span: None,
             stage: ErrorStage::Unimplemented,
             message: Some(msg.to_string()),
+            trap: None,
         }
     }
 
@@ -56,6 +97,7 @@ impl WatError {
             message: Some(format!(
                 "Not a valid {expected_type} instruction: {instruction:?}"
             )),
+            trap: None,
         }
     }
 
@@ -65,6 +107,7 @@ impl WatError {
             span: Some(offset..offset + 1),
             stage: ErrorStage::Parsing,
             message: Some(value.message()),
+            trap: None,
         }
     }
 
@@ -74,6 +117,7 @@ impl WatError {
             span: Some(offset..offset + 1),
             stage: ErrorStage::NameResolving,
             message: Some(value.message()),
+            trap: None,
         }
     }
 
@@ -82,6 +126,7 @@ impl WatError {
             span: None,
             stage: ErrorStage::NameResolving,
             message: Some(format!("{kind} {name} not found!")),
+            trap: None,
         }
     }
 
@@ -90,6 +135,7 @@ impl WatError {
             span: None,
             stage: ErrorStage::NameResolving,
             message: Some(format!("Local {name} not found!")),
+            trap: None,
         }
     }
 
@@ -98,6 +144,7 @@ impl WatError {
             span: None,
             stage: ErrorStage::NameResolving,
             message: Some(format!("Label {name} not found in flow of block!")),
+            trap: None,
         }
     }
 
@@ -106,6 +153,7 @@ impl WatError {
             span: None,
             stage: ErrorStage::TypeChecking,
             message: Some(format!("Expected {expected} type but got {actual} type!")),
+            trap: None,
         }
     }
 
@@ -114,6 +162,7 @@ impl WatError {
             span: None,
             stage: ErrorStage::TypeChecking,
             message: Some(format!("Cannot set immutable Global {name}!")),
+            trap: None,
         }
     }
 
@@ -124,6 +173,18 @@ impl WatError {
             message: Some(format!(
                 "Expected {expected_type} instruction but got nothing!"
             )),
+            trap: None,
+        }
+    }
+
+    pub fn mutable_global_in_constant_error(name: &str) -> Self {
+        Self {
+            span: None,
+            stage: ErrorStage::TypeChecking,
+            message: Some(format!(
+                "Global {name} is mutable, so it cannot be read in a constant expression!"
+            )),
+            trap: None,
         }
     }
 
@@ -131,7 +192,8 @@ impl WatError {
         Self {
             span: None,
             stage: ErrorStage::TypeChecking,
-            message: Some("Expect a single const expression for initalizing".to_string()),
+            message: Some("Expect a constant expression for initalizing".to_string()),
+            trap: None,
         }
     }
 
@@ -145,16 +207,19 @@ impl WatError {
                 span: None,
                 stage: ErrorStage::TypeChecking,
                 message: Some("Expected at least a value on the stack, but nothing is on the stack!".to_string()),
+                trap: None,
             },
             (_, 0) => Self {
                 span: None,
                 stage: ErrorStage::TypeChecking,
                 message: Some(format!("Expected at least {expected} values on the stack, but nothing is on the stack!")),
+                trap: None,
             },
             _ =>  Self {
                 span: None,
                 stage: ErrorStage::TypeChecking,
                 message: Some(format!("Expected at least {expected} values on the stack, but stack only has {actual}!")),
+                trap: None,
             },
         }
     }
@@ -181,6 +246,7 @@ impl WatError {
                 "Expected {} types to be [{expected}] on the stack, but stack has [{actual}]!",
                 if is_return { "Return" } else { "Parameter" }
             )),
+            trap: None,
         }
     }
 
@@ -189,6 +255,28 @@ impl WatError {
             span: None,
             stage: ErrorStage::NameResolving,
             message: Some(format!("Name {name} is defined multiple times")),
+            trap: None,
+        }
+    }
+
+    pub fn invalid_name_error(name: &str) -> Self {
+        Self {
+            span: None,
+            stage: ErrorStage::Parsing,
+            message: Some(format!("{name} is not a valid name")),
+            trap: None,
+        }
+    }
+
+    pub fn nothing_to_rename_error() -> Self {
+        Self {
+            span: None,
+            stage: ErrorStage::NameResolving,
+            message: Some(
+                "There is no function, global, memory, local or label name here to rename."
+                    .to_string(),
+            ),
+            trap: None,
         }
     }
 
@@ -199,6 +287,7 @@ impl WatError {
             message: Some(format!(
                 "Mismatched types, expected {expected}, but got {actual}."
             )),
+            trap: None,
         }
     }
 
@@ -207,6 +296,16 @@ impl WatError {
             span: None,
             stage: ErrorStage::TypeChecking,
             message: Some("An else block should only follow after an if block.".to_string()),
+            trap: None,
+        }
+    }
+
+    pub fn end_without_block_error() -> Self {
+        Self {
+            span: None,
+            stage: ErrorStage::TypeChecking,
+            message: Some("An end should only close a block, loop or if.".to_string()),
+            trap: None,
         }
     }
 
@@ -215,6 +314,7 @@ impl WatError {
             span: None,
             stage: ErrorStage::TypeChecking,
             message: Some(format!("Index {actual} out of range: max {expected}.")),
+            trap: None,
         }
     }
 
@@ -225,6 +325,7 @@ impl WatError {
             message: Some(format!(
                 "Expect stack arity to be {expected}, but got {actual}."
             )),
+            trap: None,
         }
     }
 
@@ -238,6 +339,7 @@ impl WatError {
             span: None,
             stage: ErrorStage::TypeChecking,
             message: Some(format!("Expect stack to be empty, but found: {found}.")),
+            trap: None,
         }
     }
 
@@ -249,6 +351,176 @@ impl WatError {
                 "The number provided cannot fit in u32: {:?}",
                 number
             )),
+            trap: None,
+        }
+    }
+
+    pub fn unreachable_error() -> Self {
+        Self {
+            span: None,
+            stage: ErrorStage::Runtime,
+            message: Some("Reached an unreachable instruction!".to_string()),
+            trap: Some(TrapKind::Unreachable),
+        }
+    }
+
+    pub fn divide_by_zero_error() -> Self {
+        Self {
+            span: None,
+            stage: ErrorStage::Runtime,
+            message: Some("Integer divide by zero!".to_string()),
+            trap: Some(TrapKind::DivideByZero),
+        }
+    }
+
+    pub fn integer_overflow_error() -> Self {
+        Self {
+            span: None,
+            stage: ErrorStage::Runtime,
+            message: Some("Integer overflow!".to_string()),
+            trap: Some(TrapKind::IntegerOverflow),
+        }
+    }
+
+    pub fn invalid_conversion_error() -> Self {
+        Self {
+            span: None,
+            stage: ErrorStage::Runtime,
+            message: Some("Invalid conversion to integer!".to_string()),
+            trap: Some(TrapKind::InvalidConversion),
+        }
+    }
+
+    pub fn call_stack_exhausted_error(depth: usize) -> Self {
+        Self {
+            span: None,
+            stage: ErrorStage::Runtime,
+            message: Some(format!("Call stack exhausted after {depth} nested calls!")),
+            trap: Some(TrapKind::CallStackExhausted),
+        }
+    }
+
+    pub fn out_of_bounds_memory_error(address: u64, size: u64) -> Self {
+        Self {
+            span: None,
+            stage: ErrorStage::Runtime,
+            message: Some(format!(
+                "Out of bounds memory access at {address}, memory is {size} bytes!"
+            )),
+            trap: Some(TrapKind::OutOfBoundsMemory),
+        }
+    }
+
+    pub fn out_of_bounds_table_error(table: &str, index: u32) -> Self {
+        Self {
+            span: None,
+            stage: ErrorStage::Runtime,
+            message: Some(format!("Table {table} has no element {index}!")),
+            trap: Some(TrapKind::OutOfBoundsTable),
+        }
+    }
+
+    pub fn step_limit_error(limit: u64) -> Self {
+        Self {
+            span: None,
+            stage: ErrorStage::Runtime,
+            message: Some(format!("Execution did not finish within {limit} steps!")),
+            trap: None,
+        }
+    }
+
+    pub fn invalid_argument_error(text: &str, expected: &SerializableWatType) -> Self {
+        Self {
+            span: None,
+            stage: ErrorStage::Runtime,
+            message: Some(format!("Cannot read {text} as a {expected} value!")),
+            trap: None,
+        }
+    }
+
+    pub fn invalid_watch_error(text: &str, reason: &str) -> Self {
+        Self {
+            span: None,
+            stage: ErrorStage::Parsing,
+            message: Some(format!("Cannot watch `{text}`, {reason}!")),
+            trap: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{invoke_function, Invocation, Machine};
+    use crate::test_util::module_with_func;
+
+    /// Kind of trap from calling the only function, exported as "f"
+    fn trap_kind(module_fields: &str, body: &str) -> TrapKind {
+        let text = module_with_func(module_fields, r#"$f (export "f")"#, body);
+        match invoke_function(&text, "f", &[]).unwrap() {
+            Invocation::Trapped(trap) => trap.kind,
+            Invocation::Returned(results) => panic!("{body} returned {results:?}"),
+        }
+    }
+
+    #[test]
+    fn each_trap_has_its_kind() {
+        let cases = [
+            ("", "unreachable", TrapKind::Unreachable),
+            (
+                "",
+                "i32.const 1 i32.const 0 i32.div_u drop",
+                TrapKind::DivideByZero,
+            ),
+            (
+                "",
+                "i64.const 1 i64.const 0 i64.rem_s drop",
+                TrapKind::DivideByZero,
+            ),
+            (
+                "",
+                "i32.const -2147483648 i32.const -1 i32.div_s drop",
+                TrapKind::IntegerOverflow,
+            ),
+            (
+                "",
+                "f32.const 1e10 i32.trunc_f32_s drop",
+                TrapKind::IntegerOverflow,
+            ),
+            (
+                "",
+                "f64.const nan i64.trunc_f64_u drop",
+                TrapKind::InvalidConversion,
+            ),
+            (
+                "(memory 1)",
+                "i32.const 65534 i32.load drop",
+                TrapKind::OutOfBoundsMemory,
+            ),
+            ("", "call $f", TrapKind::CallStackExhausted),
+        ];
+        for (module_fields, body, kind) in cases {
+            assert_eq!(trap_kind(module_fields, body), kind, "{body}");
+        }
+    }
+
+    #[test]
+    fn signed_remainder_of_the_smallest_integer_does_not_trap() {
+        let text = r#"(module (func (export "f") (result i32)
+            i32.const -2147483648 i32.const -1 i32.rem_s))"#;
+        assert_eq!(
+            invoke_function(text, "f", &[]).unwrap(),
+            Invocation::Returned(vec![0.into()])
+        );
+    }
+
+    #[test]
+    fn other_runtime_errors_are_not_traps() {
+        let structure = crate::inner_transform("(module (func loop br 0 end))").unwrap();
+        let mut machine = Machine::new(&structure).unwrap().with_max_steps(100);
+        let err = machine.invoke(0, &[]).unwrap_err();
+        assert_eq!(err.stage(), ErrorStage::Runtime);
+        assert_eq!(err.trap(), None);
+        assert!(machine.trap().is_none());
+    }
+}
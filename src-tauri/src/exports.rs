@@ -0,0 +1,179 @@
+//! Everything the UI needs to list the exports of a module and build a form to call them.
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    error::{WatError, WatResult},
+    helper::SerializedNumber,
+    inner_transform,
+    interpreter::Value,
+    marker::SerializableWatType,
+    memory, InterpreterStructure, NumLocationKind,
+};
+
+/// A parameter of an exported function
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct Param {
+    /// Name without the `$`, if it has one
+    pub name: Option<String>,
+    pub typ: SerializableWatType,
+    /// Argument to start the form with, written the way [crate::interpreter::invoke_function] reads it,
+    /// [None] for a type that cannot be passed yet
+    pub default: Option<String>,
+}
+
+/// What an export refers to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub enum ExportItem {
+    Function {
+        params: Vec<Param>,
+        results: Vec<SerializableWatType>,
+    },
+    Global {
+        typ: SerializableWatType,
+        is_mutable: bool,
+        /// Value when the module starts
+        value: SerializedNumber,
+    },
+    Memory {
+        /// Size in pages when the module starts
+        min: u32,
+        /// Most pages it can grow to, [None] for no limit
+        max: Option<u32>,
+        is_32: bool,
+        is_shared: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct Export {
+    /// Name the module exports it as
+    pub name: String,
+    pub kind: NumLocationKind,
+    /// Index of the item among items of its kind
+    pub index: u32,
+    /// Name of the item in the module, empty for a global or memory without one
+    /// and the index for a function without one
+    pub item_name: String,
+    pub item: ExportItem,
+}
+
+/// Every export of a module, sorted by export name
+pub fn exports(text: &str) -> WatResult<Vec<Export>> {
+    let structure = inner_transform(text)?;
+    let mut exports = structure
+        .exported
+        .iter()
+        .map(|(name, (kind, index))| export(&structure, name, *kind, *index))
+        .collect::<WatResult<Vec<_>>>()?;
+    exports.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(exports)
+}
+
+fn export(
+    structure: &InterpreterStructure,
+    name: &str,
+    kind: NumLocationKind,
+    index: u32,
+) -> WatResult<Export> {
+    let missing = || WatError::name_resolution_error(&index.to_string(), kind);
+    let (item_name, item) = match kind {
+        NumLocationKind::Function => {
+            let func = structure.func.get(index as usize).ok_or_else(missing)?;
+            let params = func
+                .info
+                .input
+                .iter()
+                .map(|(name, typ)| Param {
+                    name: name.clone(),
+                    typ: *typ,
+                    default: Value::default_of(typ).ok().map(|val| val.to_string()),
+                })
+                .collect();
+            (
+                func.name().unwrap_or_default(),
+                ExportItem::Function {
+                    params,
+                    results: func.info.output.clone(),
+                },
+            )
+        }
+        NumLocationKind::Global => {
+            let global = structure.globals.get(index as usize).ok_or_else(missing)?;
+            (
+                global.name.clone(),
+                ExportItem::Global {
+                    typ: global.typ,
+                    is_mutable: global.is_mutable,
+                    value: global.val,
+                },
+            )
+        }
+        NumLocationKind::Memory => {
+            let mem = structure.memory.get(index as usize).ok_or_else(missing)?;
+            let (min, max) = memory::limits(mem)?;
+            (
+                mem.name.clone(),
+                ExportItem::Memory {
+                    min,
+                    max,
+                    is_32: mem.is_32,
+                    is_shared: mem.is_shared,
+                },
+            )
+        }
+        NumLocationKind::Type => Err(WatError::unimplemented_error(
+            "Exporting types is not supported.",
+        ))?,
+    };
+    Ok(Export {
+        name: name.to_string(),
+        kind,
+        index,
+        item_name,
+        item,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_describe_their_items() {
+        let exports = exports(
+            r#"(module
+                (memory (export "mem") 1 4)
+                (global (export "g") (mut i64) (i64.const 9))
+                (export "add" (func $add))
+                (func $add (param $a i32) (param f64) (result f64) local.get 1))"#,
+        )
+        .unwrap();
+        let names: Vec<_> = exports.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["add", "g", "mem"]);
+        let ExportItem::Function { params, results } = &exports[0].item else {
+            panic!("add is a function");
+        };
+        assert_eq!(params[0].name.as_deref(), Some("a"));
+        assert_eq!(params[1].typ, SerializableWatType::F64);
+        assert_eq!(params[1].default.as_deref(), Some("0"));
+        assert_eq!(results, &[SerializableWatType::F64]);
+        assert!(matches!(
+            exports[1].item,
+            ExportItem::Global {
+                typ: SerializableWatType::I64,
+                is_mutable: true,
+                ..
+            }
+        ));
+        assert!(matches!(
+            exports[2].item,
+            ExportItem::Memory {
+                min: 1,
+                max: Some(4),
+                ..
+            }
+        ));
+    }
+}
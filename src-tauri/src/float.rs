@@ -0,0 +1,354 @@
+//! Float operations with the exact bits the WebAssembly specification asks for.
+//!
+//! Rust already rounds like IEEE-754, so most of the work is choosing which NaN comes out:
+//! an operation with a NaN operand returns that NaN made quiet, and one that makes a new NaN
+//! returns the canonical NaN. `abs`, `neg` and `copysign` only touch the sign bit, like Rust's.
+
+use std::ops::{Add, Div, Mul, Sub};
+
+/// What the operations here need from `f32` and `f64`
+pub(crate) trait Float:
+    Copy
+    + PartialEq
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+{
+    fn is_nan(self) -> bool;
+    fn is_sign_negative(self) -> bool;
+    /// Positive NaN with only the quiet bit of the payload set
+    fn canonical_nan() -> Self;
+    /// The same NaN with the quiet bit set
+    fn quiet(self) -> Self;
+    /// NaN with only the quiet bit of the payload set, of either sign
+    #[cfg(test)]
+    fn is_canonical_nan(self) -> bool;
+    /// NaN with the quiet bit set, which is what operations on a NaN return
+    #[cfg(test)]
+    fn is_arithmetic_nan(self) -> bool;
+    fn sqrt(self) -> Self;
+    fn ceil(self) -> Self;
+    fn floor(self) -> Self;
+    fn trunc(self) -> Self;
+    /// Nearest integer, the even one when halfway
+    fn round_half_even(self) -> Self;
+}
+
+macro_rules! impl_float {
+    ($float:ty, $bits:ty) => {
+        impl Float for $float {
+            fn is_nan(self) -> bool {
+                <$float>::is_nan(self)
+            }
+
+            fn is_sign_negative(self) -> bool {
+                <$float>::is_sign_negative(self)
+            }
+
+            fn canonical_nan() -> Self {
+                <$float>::from_bits(Self::QUIET_NAN_BITS)
+            }
+
+            fn quiet(self) -> Self {
+                <$float>::from_bits(self.to_bits() | Self::QUIET_BIT)
+            }
+
+            #[cfg(test)]
+            fn is_canonical_nan(self) -> bool {
+                self.to_bits() & !Self::SIGN_BIT == Self::QUIET_NAN_BITS
+            }
+
+            #[cfg(test)]
+            fn is_arithmetic_nan(self) -> bool {
+                self.to_bits() & Self::QUIET_NAN_BITS == Self::QUIET_NAN_BITS
+            }
+
+            fn sqrt(self) -> Self {
+                <$float>::sqrt(self)
+            }
+
+            fn ceil(self) -> Self {
+                <$float>::ceil(self)
+            }
+
+            fn floor(self) -> Self {
+                <$float>::floor(self)
+            }
+
+            fn trunc(self) -> Self {
+                <$float>::trunc(self)
+            }
+
+            fn round_half_even(self) -> Self {
+                let rounded = self.round();
+                // `round` goes away from zero when halfway, one too far if that is odd
+                let even = if (rounded - self).abs() == 0.5 && rounded % 2.0 != 0.0 {
+                    rounded - self.signum()
+                } else {
+                    rounded
+                };
+                even.copysign(self)
+            }
+        }
+
+        impl FloatBits for $float {
+            type Bits = $bits;
+            const SIGN_BIT: $bits = 1 << (<$bits>::BITS - 1);
+            const QUIET_BIT: $bits = 1 << (<$float>::MANTISSA_DIGITS - 2);
+            const QUIET_NAN_BITS: $bits = !Self::SIGN_BIT & !(Self::QUIET_BIT - 1);
+        }
+    };
+}
+
+/// Layout of the bits of a float
+trait FloatBits {
+    type Bits;
+    const SIGN_BIT: Self::Bits;
+    /// Most significant bit of the payload, set for a quiet NaN
+    const QUIET_BIT: Self::Bits;
+    /// Every exponent bit and the quiet bit
+    const QUIET_NAN_BITS: Self::Bits;
+}
+
+impl_float!(f32, u32);
+impl_float!(f64, u64);
+
+/// The result, unless it is NaN, then the first NaN operand made quiet or the canonical NaN
+fn nan_result<F: Float>(operands: &[F], result: F) -> F {
+    if !result.is_nan() {
+        return result;
+    }
+    operands
+        .iter()
+        .find(|operand| operand.is_nan())
+        .map_or_else(F::canonical_nan, |nan| nan.quiet())
+}
+
+pub(crate) fn add<F: Float>(a: F, b: F) -> F {
+    nan_result(&[a, b], a + b)
+}
+
+pub(crate) fn sub<F: Float>(a: F, b: F) -> F {
+    nan_result(&[a, b], a - b)
+}
+
+pub(crate) fn mul<F: Float>(a: F, b: F) -> F {
+    nan_result(&[a, b], a * b)
+}
+
+pub(crate) fn div<F: Float>(a: F, b: F) -> F {
+    nan_result(&[a, b], a / b)
+}
+
+pub(crate) fn sqrt<F: Float>(a: F) -> F {
+    nan_result(&[a], a.sqrt())
+}
+
+pub(crate) fn ceil<F: Float>(a: F) -> F {
+    nan_result(&[a], a.ceil())
+}
+
+pub(crate) fn floor<F: Float>(a: F) -> F {
+    nan_result(&[a], a.floor())
+}
+
+pub(crate) fn trunc<F: Float>(a: F) -> F {
+    nan_result(&[a], a.trunc())
+}
+
+/// Round to the nearest integer, and to the even one when halfway
+pub(crate) fn nearest<F: Float>(a: F) -> F {
+    nan_result(&[a], a.round_half_even())
+}
+
+/// Smaller operand, where -0 is smaller than +0 and NaN wins
+pub(crate) fn min<F: Float>(a: F, b: F) -> F {
+    if a.is_nan() || b.is_nan() {
+        nan_result(&[a, b], F::canonical_nan())
+    } else if a == b {
+        if a.is_sign_negative() {
+            a
+        } else {
+            b
+        }
+    } else if a < b {
+        a
+    } else {
+        b
+    }
+}
+
+/// Larger operand, where +0 is larger than -0 and NaN wins
+pub(crate) fn max<F: Float>(a: F, b: F) -> F {
+    if a.is_nan() || b.is_nan() {
+        nan_result(&[a, b], F::canonical_nan())
+    } else if a == b {
+        if a.is_sign_negative() {
+            b
+        } else {
+            a
+        }
+    } else if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+/// `f32.demote_f64`, keeping the sign and the top of the payload of a NaN
+pub(crate) fn demote(a: f64) -> f32 {
+    if !a.is_nan() {
+        return a as f32;
+    }
+    let bits = a.to_bits();
+    let sign = ((bits >> 63) as u32) << 31;
+    let payload = ((bits & ((1 << 52) - 1)) >> 29) as u32;
+    f32::from_bits(sign | f32::QUIET_NAN_BITS | payload)
+}
+
+/// `f64.promote_f32`, keeping the sign and the payload of a NaN
+pub(crate) fn promote(a: f32) -> f64 {
+    if !a.is_nan() {
+        return a as f64;
+    }
+    let bits = a.to_bits();
+    let sign = ((bits >> 31) as u64) << 63;
+    let payload = ((bits & ((1 << 23) - 1)) as u64) << 29;
+    f64::from_bits(sign | f64::QUIET_NAN_BITS | payload)
+}
+
+#[cfg(test)]
+mod tests {
+    //! Cases from the `f32.wast`, `f64.wast`, `float_misc.wast` and `conversions.wast` spec tests
+    use wast::token::{Float32, Float64};
+
+    use super::*;
+    use crate::{helper::SerializedNumber, interpreter::Value};
+
+    const SIGNALING_NAN_32: f32 = f32::from_bits(0x7fa0_0000);
+    const SIGNALING_NAN_64: f64 = f64::from_bits(0x7ff4_0000_0000_0000);
+
+    #[test]
+    fn new_nan_is_canonical() {
+        assert_eq!(div(0.0f32, 0.0).to_bits(), 0x7fc0_0000);
+        assert_eq!(
+            sub(f64::INFINITY, f64::INFINITY).to_bits(),
+            0x7ff8_0000_0000_0000
+        );
+        assert!(mul(0.0f32, f32::INFINITY).is_canonical_nan());
+        assert!(sqrt(-1.0f64).is_canonical_nan());
+        assert!(f32::from_bits(0xffc0_0000).is_canonical_nan());
+        assert!(!f32::from_bits(0x7fc0_0001).is_canonical_nan());
+    }
+
+    #[test]
+    fn nan_operand_propagates_quiet() {
+        let nan = f32::from_bits(0x7fc0_1234);
+        assert_eq!(add(nan, 1.0).to_bits(), 0x7fc0_1234);
+        assert_eq!(add(1.0, nan).to_bits(), 0x7fc0_1234);
+        assert_eq!(add(SIGNALING_NAN_32, 1.0).to_bits(), 0x7fe0_0000);
+        assert!(add(SIGNALING_NAN_32, 1.0).is_arithmetic_nan());
+        assert!(mul(-SIGNALING_NAN_64, 2.0).is_arithmetic_nan());
+        assert!(div(SIGNALING_NAN_64, 0.0).is_arithmetic_nan());
+        for f in [sqrt, ceil, floor, trunc, nearest] {
+            assert!(f(SIGNALING_NAN_32).is_arithmetic_nan());
+        }
+    }
+
+    #[test]
+    fn min_max_signed_zero() {
+        assert!(min(0.0f32, -0.0).is_sign_negative());
+        assert!(min(-0.0f32, 0.0).is_sign_negative());
+        assert!(max(-0.0f64, 0.0).is_sign_positive());
+        assert!(max(0.0f64, -0.0).is_sign_positive());
+        assert_eq!(min(-1.0f32, 1.0), -1.0);
+        assert_eq!(max(-f64::INFINITY, f64::MIN), f64::MIN);
+    }
+
+    #[test]
+    fn min_max_nan() {
+        assert!(min(f32::NAN, 1.0).is_nan());
+        assert!(min(1.0, f32::NAN).is_nan());
+        assert!(max(-0.0, f64::NAN).is_nan());
+        assert!(max(SIGNALING_NAN_64, f64::INFINITY).is_arithmetic_nan());
+        assert!(min(0.0f32, SIGNALING_NAN_32).is_arithmetic_nan());
+    }
+
+    #[test]
+    fn nearest_ties_to_even() {
+        for (input, expected) in [
+            (0.5f32, 0.0),
+            (1.5, 2.0),
+            (2.5, 2.0),
+            (-3.5, -4.0),
+            (4.5, 4.0),
+            (-0.5, -0.0),
+            (f32::from_bits(0x3eff_ffff), 0.0),
+            (8388609.0, 8388609.0),
+            (f32::INFINITY, f32::INFINITY),
+        ] {
+            assert_eq!(
+                nearest(input).to_bits(),
+                expected.to_bits(),
+                "nearest {input}"
+            );
+        }
+        assert_eq!(nearest(-0.5f64).to_bits(), (-0.0f64).to_bits());
+        assert_eq!(nearest(4503599627370497.0f64), 4503599627370497.0);
+        assert_eq!(nearest(-2.5f64), -2.0);
+    }
+
+    #[test]
+    fn rounding_is_exact() {
+        assert_eq!(add(0.1f64, 0.2).to_bits(), 0x3fd3_3333_3333_3334);
+        assert_eq!(add(f32::from_bits(1), f32::from_bits(1)).to_bits(), 2);
+        assert_eq!(add(1e16f64, 1.0), 1e16);
+        assert_eq!(sub(1e16f64, 1.0), 1e16);
+        assert_eq!(sub(1e16f64, 2.0), 9999999999999998.0);
+        assert_eq!(mul(1e15f64, 1e15), 1e30);
+        assert_eq!(div(1.0f32, 3.0).to_bits(), 0x3eaa_aaab);
+        assert_eq!(sqrt(2.0f64).to_bits(), 0x3ff6_a09e_667f_3bcd);
+    }
+
+    #[test]
+    fn promote_demote() {
+        assert_eq!(
+            demote(f64::from_bits(0x7ff8_0000_0000_0000)).to_bits(),
+            0x7fc0_0000
+        );
+        assert_eq!(
+            demote(f64::from_bits(0xfff4_0000_2000_0000)).to_bits(),
+            0xffe0_0001
+        );
+        assert_eq!(
+            promote(f32::from_bits(0x7fc0_0000)).to_bits(),
+            0x7ff8_0000_0000_0000
+        );
+        assert!(promote(SIGNALING_NAN_32).is_arithmetic_nan());
+        assert!(promote(-f32::NAN).is_sign_negative());
+        assert_eq!(demote(1.0000000596046448), 1.0);
+        assert_eq!(demote(1.0000001788139343), 1.0000002);
+        assert_eq!(demote(f64::MAX), f32::INFINITY);
+        assert_eq!(promote(f32::MIN_POSITIVE), f32::MIN_POSITIVE as f64);
+    }
+
+    #[test]
+    fn serialized_numbers_are_big_endian() {
+        let f64_bits = 0x4009_21fb_5444_2d18;
+        let number = SerializedNumber::from(Float64 { bits: f64_bits });
+        assert_eq!(number.to_bits(), f64_bits);
+        assert_eq!(Value::from(number), Value::F64(std::f64::consts::PI));
+        assert_eq!(
+            serde_json::to_value(number).unwrap()["first_bytes"],
+            serde_json::json!([0x40, 0x09, 0x21, 0xfb])
+        );
+        let nan = SerializedNumber::from(Float32 { bits: 0x7fa0_0001 });
+        assert_eq!(nan.to_bits(), 0x7fa0_0001);
+        assert_eq!(
+            SerializedNumber::from(Value::F64(-0.0)).to_bits(),
+            0x8000_0000_0000_0000
+        );
+    }
+}
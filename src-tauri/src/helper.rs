@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use wast::token::{Float32, Float64};
@@ -23,6 +25,66 @@ pub struct SerializedNumber {
     typ: SerializableWatType,
 }
 
+impl SerializedNumber {
+    /// Bits of a null reference, an index no module can reach
+    const NULL_REFERENCE: u32 = u32::MAX;
+
+    /// The Wat type of this number
+    pub fn typ(&self) -> SerializableWatType {
+        self.typ
+    }
+
+    /// The raw bits of this number, 32-bit numbers only use the lower half
+    pub fn to_bits(&self) -> u64 {
+        let mut bytes = [0; 8];
+        bytes[..4].copy_from_slice(&self.first_bytes);
+        bytes[4..].copy_from_slice(&self.second_bytes.unwrap_or_default());
+        match self.typ {
+            SerializableWatType::I64 | SerializableWatType::F64 | SerializableWatType::V128 => {
+                u64::from_be_bytes(bytes)
+            }
+            SerializableWatType::I32
+            | SerializableWatType::F32
+            | SerializableWatType::FuncRef
+            | SerializableWatType::ExternRef => u32::from_be_bytes(self.first_bytes) as u64,
+        }
+    }
+
+    /// A `funcref` or `externref` to the given index, [None] for null
+    pub fn reference(typ: SerializableWatType, index: Option<u32>) -> Self {
+        Self {
+            first_bytes: index.unwrap_or(Self::NULL_REFERENCE).to_be_bytes(),
+            second_bytes: None,
+            typ,
+        }
+    }
+
+    /// Index a reference points to, [None] for null
+    pub fn to_reference(&self) -> Option<u32> {
+        let index = u32::from_be_bytes(self.first_bytes);
+        (index != Self::NULL_REFERENCE).then_some(index)
+    }
+}
+
+impl Display for SerializedNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bits = self.to_bits();
+        match self.typ {
+            SerializableWatType::I32 => write!(f, "{}", bits as u32 as i32),
+            SerializableWatType::I64 => write!(f, "{}", bits as i64),
+            SerializableWatType::F32 => write!(f, "{}", f32::from_bits(bits as u32)),
+            SerializableWatType::F64 => write!(f, "{}", f64::from_bits(bits)),
+            SerializableWatType::V128 => write!(f, "{bits:#018x}"),
+            SerializableWatType::FuncRef | SerializableWatType::ExternRef => {
+                match self.to_reference() {
+                    Some(index) => write!(f, "{index}"),
+                    None => write!(f, "null"),
+                }
+            }
+        }
+    }
+}
+
 impl From<i32> for SerializedNumber {
     fn from(value: i32) -> Self {
         Self {
@@ -56,7 +118,7 @@ impl From<Float32> for SerializedNumber {
 
 impl From<Float64> for SerializedNumber {
     fn from(value: Float64) -> Self {
-        let bytes = value.bits.to_ne_bytes();
+        let bytes = value.bits.to_be_bytes();
         Self {
             first_bytes: four_byte_array!(bytes, 0),
             second_bytes: Some(four_byte_array!(bytes, 4)),
@@ -98,3 +160,318 @@ impl TryFrom<SerializedNumber> for u32 {
         }
     }
 }
+
+/// Kind of value a float's bits hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum FloatClass {
+    Zero,
+    Subnormal,
+    Normal,
+    Infinity,
+    QuietNan,
+    SignalingNan,
+}
+
+/// The fields of a float, as bits from the most significant
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct FloatFields {
+    pub is_negative: bool,
+    pub exponent_bits: String,
+    pub mantissa_bits: String,
+    /// Exponent without the bias, [None] for infinity and NaN
+    pub exponent: Option<i32>,
+    pub class: FloatClass,
+}
+
+/// Every way the bits of a number can be shown
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct NumberViews {
+    pub signed: String,
+    pub unsigned: String,
+    pub hex: String,
+    /// Bits from the most significant, with a space between bytes
+    pub binary: String,
+    /// The bits as a float of the same width, written exactly in decimal, or like WAT for NaN and infinity
+    pub float: String,
+    pub fields: FloatFields,
+}
+
+/// Unsigned integer of any size, as base 10^9 digits from the least significant
+struct BigDecimal(Vec<u32>);
+
+impl BigDecimal {
+    const BASE: u64 = 1_000_000_000;
+
+    fn new(value: u64) -> Self {
+        let mut digits = vec![(value % Self::BASE) as u32];
+        let mut rest = value / Self::BASE;
+        while rest > 0 {
+            digits.push((rest % Self::BASE) as u32);
+            rest /= Self::BASE;
+        }
+        Self(digits)
+    }
+
+    fn multiply(&mut self, factor: u32) {
+        let mut carry = 0;
+        for digit in &mut self.0 {
+            let product = *digit as u64 * factor as u64 + carry;
+            *digit = (product % Self::BASE) as u32;
+            carry = product / Self::BASE;
+        }
+        if carry > 0 {
+            self.0.extend(BigDecimal::new(carry).0);
+        }
+    }
+}
+
+impl Display for BigDecimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut digits = self.0.iter().rev();
+        write!(f, "{}", digits.next().copied().unwrap_or_default())?;
+        digits.try_for_each(|digit| write!(f, "{digit:09}"))
+    }
+}
+
+/// Split float bits with the given number of exponent and mantissa bits
+fn float_fields(bits: u64, exponent_size: u32, mantissa_size: u32) -> FloatFields {
+    let mantissa = bits & ((1 << mantissa_size) - 1);
+    let biased = ((bits >> mantissa_size) & ((1 << exponent_size) - 1)) as i32;
+    let max = (1 << exponent_size) - 1;
+    let bias = (1 << (exponent_size - 1)) - 1;
+    let class = match (biased, mantissa) {
+        (0, 0) => FloatClass::Zero,
+        (0, _) => FloatClass::Subnormal,
+        (b, 0) if b == max => FloatClass::Infinity,
+        (b, m) if b == max && m >> (mantissa_size - 1) == 1 => FloatClass::QuietNan,
+        (b, _) if b == max => FloatClass::SignalingNan,
+        _ => FloatClass::Normal,
+    };
+    FloatFields {
+        is_negative: bits >> (exponent_size + mantissa_size) & 1 == 1,
+        exponent_bits: format!("{biased:0width$b}", width = exponent_size as usize),
+        mantissa_bits: format!("{mantissa:0width$b}", width = mantissa_size as usize),
+        exponent: match class {
+            FloatClass::Normal => Some(biased - bias),
+            FloatClass::Zero | FloatClass::Subnormal => Some(1 - bias),
+            _ => None,
+        },
+        class,
+    }
+}
+
+/// Float bits written exactly in decimal, NaN and infinity written like WAT
+fn exact_float(bits: u64, exponent_size: u32, mantissa_size: u32) -> String {
+    let fields = float_fields(bits, exponent_size, mantissa_size);
+    let sign = if fields.is_negative { "-" } else { "" };
+    let mantissa = bits & ((1 << mantissa_size) - 1);
+    let (mantissa, exponent) = match (fields.class, fields.exponent) {
+        (FloatClass::Infinity, _) => return format!("{sign}inf"),
+        (FloatClass::QuietNan, _) if mantissa == 1 << (mantissa_size - 1) => {
+            return format!("{sign}nan")
+        }
+        (FloatClass::QuietNan | FloatClass::SignalingNan, _) => {
+            return format!("{sign}nan:{mantissa:#x}")
+        }
+        (FloatClass::Normal, Some(exponent)) => (mantissa | 1 << mantissa_size, exponent),
+        (_, exponent) => (mantissa, exponent.unwrap_or_default()),
+    };
+    // The value is mantissa * 2^shift
+    let shift = exponent - mantissa_size as i32;
+    let mut number = BigDecimal::new(mantissa);
+    if shift >= 0 {
+        (0..shift).for_each(|_| number.multiply(2));
+        return format!("{sign}{number}");
+    }
+    // mantissa / 2^k has the digits of mantissa * 5^k, with k of them after the point
+    let places = shift.unsigned_abs() as usize;
+    (0..places).for_each(|_| number.multiply(5));
+    let digits = format!("{:0>width$}", number.to_string(), width = places + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - places);
+    match fraction.trim_end_matches('0') {
+        "" => format!("{sign}{whole}"),
+        fraction => format!("{sign}{whole}.{fraction}"),
+    }
+}
+
+impl SerializedNumber {
+    /// All the ways to show this number, reading its bits as an integer and as a float
+    pub fn views(&self) -> NumberViews {
+        let bits = self.to_bits();
+        let bytes = match self.typ {
+            SerializableWatType::I64 | SerializableWatType::F64 | SerializableWatType::V128 => 8,
+            SerializableWatType::I32
+            | SerializableWatType::F32
+            | SerializableWatType::FuncRef
+            | SerializableWatType::ExternRef => 4,
+        };
+        let (signed, unsigned, float, fields) = if bytes == 8 {
+            (
+                (bits as i64).to_string(),
+                bits.to_string(),
+                exact_float(bits, 11, 52),
+                float_fields(bits, 11, 52),
+            )
+        } else {
+            (
+                (bits as u32 as i32).to_string(),
+                bits.to_string(),
+                exact_float(bits, 8, 23),
+                float_fields(bits, 8, 23),
+            )
+        };
+        NumberViews {
+            signed,
+            unsigned,
+            hex: format!("{bits:#0width$x}", width = bytes * 2 + 2),
+            binary: (0..bytes)
+                .rev()
+                .map(|byte| format!("{:08b}", (bits >> (byte * 8)) as u8))
+                .collect::<Vec<_>>()
+                .join(" "),
+            float,
+            fields,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn f32_float(bits: u32) -> String {
+        exact_float(bits as u64, 8, 23)
+    }
+
+    fn f64_float(bits: u64) -> String {
+        exact_float(bits, 11, 52)
+    }
+
+    /// Digits after the decimal point
+    fn places(float: &str) -> usize {
+        float
+            .split_once('.')
+            .map_or(0, |(_, fraction)| fraction.len())
+    }
+
+    #[test]
+    fn subnormals_are_written_exactly() {
+        let smallest = f32_float(1);
+        assert_eq!(
+            smallest,
+            format!(
+                "0.{}140129846432481707092372958328991613128026194187651577175706828388979108268586060148663818836212158203125",
+                "0".repeat(44)
+            )
+        );
+        let largest = f32_float(0x007f_ffff);
+        assert_eq!(places(&largest), 149);
+        assert_eq!(largest.parse::<f32>().unwrap(), f32::from_bits(0x007f_ffff));
+
+        let smallest = f64_float(1);
+        assert!(smallest.starts_with(&format!("0.{}4940656458412465", "0".repeat(323))));
+        assert_eq!(places(&smallest), 1074);
+        assert_eq!(smallest.parse::<f64>().unwrap(), f64::from_bits(1));
+        let largest = f64_float(0x000f_ffff_ffff_ffff);
+        assert_eq!(places(&largest), 1074);
+        assert_eq!(
+            largest.parse::<f64>().unwrap(),
+            f64::from_bits(0x000f_ffff_ffff_ffff)
+        );
+
+        let fields = float_fields(1, 8, 23);
+        assert_eq!(fields.class, FloatClass::Subnormal);
+        assert_eq!(fields.exponent, Some(-126));
+        assert_eq!(float_fields(1, 11, 52).exponent, Some(-1022));
+    }
+
+    #[test]
+    fn largest_floats_are_whole_numbers() {
+        assert_eq!(
+            f32_float(f32::MAX.to_bits()),
+            "340282346638528859811704183484516925440"
+        );
+        assert_eq!(
+            f64_float(f64::MAX.to_bits()),
+            "179769313486231570814527423731704356798070567525844996598917476803157260780028538760589558632766878171540458953514382464234321326889464182768467546703537516986049910576551282076245490090389328944075868508455133942304583236903222948165808559332123348274797826204144723168738177180919299881250404026184124858368"
+        );
+        assert_eq!(f32_float((-f32::MAX).to_bits()).chars().next(), Some('-'));
+        let fields = float_fields(f64::MAX.to_bits(), 11, 52);
+        assert_eq!(fields.class, FloatClass::Normal);
+        assert_eq!(fields.exponent, Some(1023));
+        assert_eq!(fields.mantissa_bits, "1".repeat(52));
+    }
+
+    #[test]
+    fn negative_zero_keeps_its_sign() {
+        assert_eq!(f32_float((-0.0_f32).to_bits()), "-0");
+        assert_eq!(f64_float((-0.0_f64).to_bits()), "-0");
+        assert_eq!(f64_float(0), "0");
+        let fields = float_fields((-0.0_f32).to_bits() as u64, 8, 23);
+        assert!(fields.is_negative);
+        assert_eq!(fields.class, FloatClass::Zero);
+        assert_eq!(fields.exponent_bits, "00000000");
+    }
+
+    #[test]
+    fn nan_payloads_are_shown() {
+        assert_eq!(f32_float(0x7fc0_0000), "nan");
+        assert_eq!(f32_float(0xffc0_0000), "-nan");
+        assert_eq!(f32_float(0x7fc0_0001), "nan:0x400001");
+        assert_eq!(f32_float(0x7f80_0001), "nan:0x1");
+        assert_eq!(f64_float(0x7ff8_0000_0000_0000), "nan");
+        assert_eq!(f64_float(0x7ff0_0000_0000_0001), "nan:0x1");
+
+        assert_eq!(float_fields(0x7fc0_0001, 8, 23).class, FloatClass::QuietNan);
+        assert_eq!(
+            float_fields(0x7f80_0001, 8, 23).class,
+            FloatClass::SignalingNan
+        );
+        assert_eq!(
+            float_fields(0x7ff0_0000_0000_0001, 11, 52).class,
+            FloatClass::SignalingNan
+        );
+        assert_eq!(float_fields(0x7fc0_0000, 8, 23).exponent, None);
+    }
+
+    #[test]
+    fn infinities_have_no_exponent() {
+        assert_eq!(f32_float(f32::INFINITY.to_bits()), "inf");
+        assert_eq!(f32_float(f32::NEG_INFINITY.to_bits()), "-inf");
+        assert_eq!(f64_float(f64::INFINITY.to_bits()), "inf");
+        assert_eq!(f64_float(f64::NEG_INFINITY.to_bits()), "-inf");
+        let fields = float_fields(f64::NEG_INFINITY.to_bits(), 11, 52);
+        assert_eq!(fields.class, FloatClass::Infinity);
+        assert!(fields.is_negative);
+        assert_eq!(fields.exponent_bits, "1".repeat(11));
+        assert_eq!(fields.exponent, None);
+    }
+
+    #[test]
+    fn views_read_the_bits_at_the_width_of_the_type() {
+        let views = SerializedNumber::from(Float32 {
+            bits: (-0.0_f32).to_bits(),
+        })
+        .views();
+        assert_eq!(views.signed, "-2147483648");
+        assert_eq!(views.unsigned, "2147483648");
+        assert_eq!(views.hex, "0x80000000");
+        assert_eq!(views.binary, "10000000 00000000 00000000 00000000");
+        assert_eq!(views.float, "-0");
+
+        let views = SerializedNumber::from(-1_i64).views();
+        assert_eq!(views.unsigned, u64::MAX.to_string());
+        assert_eq!(views.hex, "0xffffffffffffffff");
+        assert_eq!(views.float, "-nan:0xfffffffffffff");
+        assert_eq!(views.fields.class, FloatClass::QuietNan);
+
+        let vector = SerializedNumber {
+            first_bytes: [0x01, 0x02, 0x03, 0x04],
+            second_bytes: Some([0x05, 0x06, 0x07, 0x08]),
+            typ: SerializableWatType::V128,
+        };
+        assert_eq!(vector.to_bits(), 0x0102_0304_0506_0708);
+        assert_eq!(vector.views().hex, "0x0102030405060708");
+        assert_eq!(vector.views().hex, vector.to_string());
+    }
+}
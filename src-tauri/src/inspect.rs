@@ -0,0 +1,150 @@
+//! Inspect the instruction written at a position in the WAT text.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    build_structure,
+    error::{WatError, WatResult},
+    instruction::{NodeMark, SerializedInstruction, SerializedInstructionTree},
+    marker::SerializableWatType,
+    source::SourceMap,
+    validator::StackEffect,
+};
+
+/// A block that contains the inspected instruction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct EnclosingBlock {
+    /// Index of the block in [SerializedInstructionTree::root]
+    pub node: u32,
+    pub kind: NodeMark,
+    pub label: String,
+    pub start: u32,
+    pub end: u32,
+}
+
+/// Everything known about one instruction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct Inspection {
+    /// Index of the function the instruction is in
+    pub function: u32,
+    /// Index of the instruction in the function's instruction array
+    pub index: u32,
+    pub span: Range<u32>,
+    pub instruction: SerializedInstruction,
+    pub description: String,
+    /// Types popped and pushed, [None] if the function fails to validate before this instruction
+    pub effect: Option<StackEffect>,
+    pub stack_before: Option<Vec<SerializableWatType>>,
+    pub stack_after: Option<Vec<SerializableWatType>>,
+    /// Validation error caused by this instruction, if any
+    pub error: Option<WatError>,
+    /// Blocks around the instruction, innermost first and ending with the function body
+    pub blocks: Vec<EnclosingBlock>,
+}
+
+/// Blocks of the tree that contain the instruction, innermost first
+pub fn enclosing_blocks(tree: &SerializedInstructionTree, index: u32) -> Vec<EnclosingBlock> {
+    let mut blocks: Vec<_> = tree
+        .root
+        .iter()
+        .enumerate()
+        // The function body (node 0) contains everything, other blocks start after their opening instruction
+        .filter(|(i, node)| *i == 0 || (node.start < index && index <= node.end))
+        .map(|(i, node)| {
+            (
+                node.depth,
+                EnclosingBlock {
+                    node: i as u32,
+                    kind: node.kind,
+                    label: node.label.clone(),
+                    start: node.start,
+                    end: node.end,
+                },
+            )
+        })
+        .collect();
+    blocks.sort_by_key(|(depth, _)| std::cmp::Reverse(*depth));
+    blocks.into_iter().map(|(_, block)| block).collect()
+}
+
+/// Find the instruction covering the offset, returns [None] if the offset is not on an instruction
+pub fn inspect_at(text: &str, offset: u32) -> WatResult<Option<Inspection>> {
+    let source = SourceMap::try_new(text)?;
+    let Some((function_index, function)) = source.function_at(offset) else {
+        return Ok(None);
+    };
+    let Some(index) = function.instruction_at(offset) else {
+        return Ok(None);
+    };
+    let structure = build_structure(text)?;
+    let (Some(func), Some(stack_types)) = (
+        structure.func.get(function_index),
+        structure.stack_types().into_iter().nth(function_index),
+    ) else {
+        return Ok(None);
+    };
+    let Some(instruction) = func.block.array.get(index) else {
+        return Ok(None);
+    };
+    Ok(Some(Inspection {
+        function: function_index as u32,
+        index: index as u32,
+        span: function.instructions[index].clone(),
+        instruction: instruction.clone(),
+        description: func.block.descriptions[index].clone(),
+        effect: stack_types.effects.get(index).cloned(),
+        stack_before: stack_types.before.get(index).cloned(),
+        stack_after: stack_types.after(index).cloned(),
+        error: stack_types
+            .error
+            .filter(|(failed, _)| *failed == index)
+            .map(|(_, err)| err),
+        blocks: enclosing_blocks(&func.block, index as u32),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str =
+        "(module (func (result i32) block $b (result i32) i32.const 1 i32.const 2 i32.add end))";
+
+    fn inspect(needle: &str) -> Inspection {
+        inspect_at(TEXT, TEXT.find(needle).unwrap() as u32)
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn inspection_describes_the_instruction_and_stack() {
+        let add = inspect("i32.add");
+        assert_eq!(add.index, 3);
+        assert_eq!(
+            add.description,
+            "Pop top 2 I32 values from stack, push first + second result to stack."
+        );
+        assert_eq!(
+            add.effect,
+            Some(StackEffect {
+                popped: vec![SerializableWatType::I32; 2],
+                pushed: vec![SerializableWatType::I32],
+            })
+        );
+        assert_eq!(add.stack_before, Some(vec![SerializableWatType::I32; 2]));
+        assert_eq!(add.stack_after, Some(vec![SerializableWatType::I32]));
+        assert_eq!(add.error, None);
+        let labels: Vec<_> = add.blocks.iter().map(|b| b.label.as_str()).collect();
+        assert_eq!(labels[0], "b");
+        assert_eq!(add.blocks.len(), 2);
+    }
+
+    #[test]
+    fn offsets_outside_instructions_have_no_inspection() {
+        assert_eq!(inspect_at(TEXT, 0).unwrap(), None);
+        assert!(inspect_at("(module", 0).is_err());
+    }
+}
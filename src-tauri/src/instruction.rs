@@ -1,6 +1,7 @@
 //! This module holds the data types handling converting from Wat instructions to a unified instruction for the interpreter.
 
 use std::collections::HashMap;
+use std::fmt::Display;
 
 use crate::helper::SerializedNumber;
 use crate::marker::{
@@ -12,6 +13,7 @@ use crate::marker::{
 };
 
 use crate::error::{self, WatError, WatResult};
+use crate::validator::try_name_to_index;
 
 use serde::{Deserialize, Serialize};
 use specta::Type;
@@ -519,9 +521,13 @@ pub fn data_type_of_instruction(instruction: &Instruction) -> Option<Serializabl
 
 pub fn is_64_bit_instruction(instruction: &Instruction) -> Option<bool> {
     match data_type_of_instruction(instruction) {
-        Some(SerializableWatType::I32 | SerializableWatType::F32 | SerializableWatType::V128) => {
-            Some(false)
-        }
+        Some(
+            SerializableWatType::I32
+            | SerializableWatType::F32
+            | SerializableWatType::V128
+            | SerializableWatType::FuncRef
+            | SerializableWatType::ExternRef,
+        ) => Some(false),
         Some(SerializableWatType::I64 | SerializableWatType::F64) => Some(true),
         None => None,
     }
@@ -595,10 +601,17 @@ pub enum SerializedInstruction {
         default_label: String,
         other_labels: Vec<String>,
         is_conditional: bool,
+        /// Where each of `other_labels` goes, then where `default_label` goes.
+        /// Filled in with the block tree, empty if any label does not name an enclosing block.
+        targets: Vec<BranchTarget>,
     },
     Call {
         index: String,
-        inout: InputOutput,
+    },
+    /// `call_indirect`, with the type the called function must have
+    CallIndirect {
+        table: String,
+        type_use: InputOutput,
     },
     Data {
         kind: DataInstruction,
@@ -611,6 +624,8 @@ pub enum SerializedInstruction {
         offset: u32,
         alignment: ByteKind,
         is_storing: bool,
+        /// Sign extend a load of fewer bytes than the type has
+        is_signed: bool,
     },
     Const {
         typ: SerializableWatType,
@@ -666,24 +681,26 @@ impl TryFrom<&Instruction<'_>> for SerializedInstruction {
                 default_label: index_to_string(i),
                 other_labels: Vec::default(),
                 is_conditional: false,
+                targets: Vec::new(),
             },
             Instruction::BrIf(i) => Self::Branch {
                 default_label: index_to_string(i),
                 other_labels: Vec::default(),
                 is_conditional: true,
+                targets: Vec::new(),
             },
             Instruction::BrTable(br_table) => Self::Branch {
                 default_label: index_to_string(&br_table.default),
                 other_labels: br_table.labels.iter().map(index_to_string).collect(),
                 is_conditional: true,
+                targets: Vec::new(),
             },
             Instruction::Call(i) => Self::Call {
                 index: index_to_string(i),
-                inout: InputOutput::default(),
             },
-            Instruction::CallIndirect(ci) => Self::Call {
-                index: index_to_string(&ci.table),
-                inout: (&ci.ty).try_into()?,
+            Instruction::CallIndirect(ci) => Self::CallIndirect {
+                table: index_to_string(&ci.table),
+                type_use: (&ci.ty).try_into()?,
             },
             Instruction::LocalGet(i)
             | Instruction::LocalSet(i)
@@ -719,6 +736,14 @@ impl TryFrom<&Instruction<'_>> for SerializedInstruction {
                 count: try_byte_count_from(value)
                     .ok_or(WatError::invalid_instruction("Memory", value))?,
                 is_storing: false,
+                is_signed: matches!(
+                    value,
+                    Instruction::I32Load8s(_)
+                        | Instruction::I32Load16s(_)
+                        | Instruction::I64Load8s(_)
+                        | Instruction::I64Load16s(_)
+                        | Instruction::I64Load32s(_)
+                ),
             },
             Instruction::I32Store(m)
             | Instruction::I64Store(m)
@@ -736,6 +761,7 @@ impl TryFrom<&Instruction<'_>> for SerializedInstruction {
                 count: try_byte_count_from(value)
                     .ok_or(WatError::invalid_instruction("Memory", value))?,
                 is_storing: true,
+                is_signed: false,
             },
             Instruction::I32Const(i) => Self::Const {
                 typ: SerializableWatType::I32,
@@ -750,7 +776,7 @@ impl TryFrom<&Instruction<'_>> for SerializedInstruction {
                 value: f.into(),
             },
             Instruction::F64Const(f) => Self::Const {
-                typ: SerializableWatType::I64,
+                typ: SerializableWatType::F64,
                 value: f.into(),
             },
             Instruction::I32Add
@@ -903,6 +929,546 @@ impl TryFrom<&Instruction<'_>> for SerializedInstruction {
     }
 }
 
+/// Format a label or index as it would be written in Wat (names are prefixed with `$`)
+pub fn format_index(index: &str) -> String {
+    if index.parse::<usize>().is_ok() {
+        index.to_string()
+    } else {
+        format!("${index}")
+    }
+}
+
+impl Display for SerializedInstruction {
+    /// Write the instruction in the flat Wat text format
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SerializedInstruction::Simple(s) => f.write_str(match s {
+                SimpleInstruction::Unreachable => "unreachable",
+                SimpleInstruction::Nop => "nop",
+                SimpleInstruction::Drop => "drop",
+                SimpleInstruction::Return => "return",
+            }),
+            SerializedInstruction::Block { label, kind, inout } => {
+                f.write_str(match kind {
+                    BlockKind::Block => "block",
+                    BlockKind::If => "if",
+                    BlockKind::Else => "else",
+                    BlockKind::Loop => "loop",
+                    BlockKind::End => "end",
+                })?;
+                if !label.is_empty() {
+                    write!(f, " ${label}")?;
+                }
+                match inout {
+                    Some(inout) => write_type_use(f, inout),
+                    None => Ok(()),
+                }
+            }
+            SerializedInstruction::Branch {
+                default_label,
+                other_labels,
+                is_conditional,
+                ..
+            } => {
+                if !other_labels.is_empty() {
+                    f.write_str("br_table")?;
+                    for label in other_labels {
+                        write!(f, " {}", format_index(label))?;
+                    }
+                } else if *is_conditional {
+                    f.write_str("br_if")?;
+                } else {
+                    f.write_str("br")?;
+                }
+                write!(f, " {}", format_index(default_label))
+            }
+            SerializedInstruction::Call { index } => write!(f, "call {}", format_index(index)),
+            SerializedInstruction::CallIndirect { table, type_use } => {
+                write!(f, "call_indirect {}", format_index(table))?;
+                write_type_use(f, type_use)
+            }
+            SerializedInstruction::Data { kind, location } => {
+                let name = match kind {
+                    DataInstruction::GetLocal => "local.get",
+                    DataInstruction::GetGlobal => "global.get",
+                    DataInstruction::SetLocal => "local.set",
+                    DataInstruction::SetGlobal => "global.set",
+                    DataInstruction::TeeLocal => "local.tee",
+                    DataInstruction::GetMemorySize => "memory.size",
+                    DataInstruction::SetMemorySize => "memory.grow",
+                };
+                write!(f, "{name} {}", format_index(location))
+            }
+            SerializedInstruction::Memory {
+                typ,
+                count,
+                offset,
+                is_storing,
+                is_signed,
+                ..
+            } => {
+                write!(
+                    f,
+                    "{}.{}",
+                    type_prefix(typ),
+                    if *is_storing { "store" } else { "load" }
+                )?;
+                let full_size = match typ {
+                    SerializableWatType::I64 | SerializableWatType::F64 => ByteKind::Bits64,
+                    _ => ByteKind::Bits32,
+                };
+                if *count != full_size {
+                    f.write_str(match count {
+                        ByteKind::Bits8 => "8",
+                        ByteKind::Bits16 => "16",
+                        ByteKind::Bits32 => "32",
+                        ByteKind::Bits64 => "64",
+                    })?;
+                    if !is_storing {
+                        f.write_str(if *is_signed { "_s" } else { "_u" })?;
+                    }
+                }
+                if *offset != 0 {
+                    write!(f, " offset={offset}")?;
+                }
+                Ok(())
+            }
+            SerializedInstruction::Const { typ, value } => {
+                write!(f, "{}.const {value}", type_prefix(typ))
+            }
+            SerializedInstruction::Comparison { kind, typ } => {
+                let is_float = matches!(typ, SerializableWatType::F32 | SerializableWatType::F64);
+                let name = match kind {
+                    ComparisonOperation::EqualZero => "eqz",
+                    ComparisonOperation::Equal => "eq",
+                    ComparisonOperation::NotEqual => "ne",
+                    ComparisonOperation::LessThenSigned if is_float => "lt",
+                    ComparisonOperation::LessThenSigned => "lt_s",
+                    ComparisonOperation::LessThenUnsigned => "lt_u",
+                    ComparisonOperation::GreaterThenSigned if is_float => "gt",
+                    ComparisonOperation::GreaterThenSigned => "gt_s",
+                    ComparisonOperation::GreaterThenUnsigned => "gt_u",
+                    ComparisonOperation::LessThenOrEqualToSigned if is_float => "le",
+                    ComparisonOperation::LessThenOrEqualToSigned => "le_s",
+                    ComparisonOperation::LessThenOrEqualToUnsigned => "le_u",
+                    ComparisonOperation::GreaterThenOrEqualToSigned if is_float => "ge",
+                    ComparisonOperation::GreaterThenOrEqualToSigned => "ge_s",
+                    ComparisonOperation::GreaterThenOrEqualToUnsigned => "ge_u",
+                };
+                write!(f, "{}.{name}", type_prefix(typ))
+            }
+            SerializedInstruction::Arithmetic { kind, typ } => {
+                let is_float = matches!(typ, SerializableWatType::F32 | SerializableWatType::F64);
+                let name = match kind {
+                    ArithmeticOperation::Addition => "add",
+                    ArithmeticOperation::Subtraction => "sub",
+                    ArithmeticOperation::Multiplication => "mul",
+                    ArithmeticOperation::DivisonSigned if is_float => "div",
+                    ArithmeticOperation::DivisonSigned => "div_s",
+                    ArithmeticOperation::DivisonUnsigned => "div_u",
+                    ArithmeticOperation::RemainderSigned => "rem_s",
+                    ArithmeticOperation::RemainderUnsigned => "rem_u",
+                };
+                write!(f, "{}.{name}", type_prefix(typ))
+            }
+            SerializedInstruction::Bitwise { kind, is_64_bit } => {
+                let name = match kind {
+                    BitwiseOperation::CountLeadingZero => "clz",
+                    BitwiseOperation::CountTrailingZero => "ctz",
+                    BitwiseOperation::CountNonZero => "popcnt",
+                    BitwiseOperation::And => "and",
+                    BitwiseOperation::Or => "or",
+                    BitwiseOperation::Xor => "xor",
+                    BitwiseOperation::ShiftLeft => "shl",
+                    BitwiseOperation::ShiftRightSigned => "shr_s",
+                    BitwiseOperation::ShiftRightUnsigned => "shr_u",
+                    BitwiseOperation::RotateLeft => "rotl",
+                    BitwiseOperation::RotateRight => "rotr",
+                };
+                write!(f, "{}.{name}", if *is_64_bit { "i64" } else { "i32" })
+            }
+            SerializedInstruction::Float { kind, is_64_bit } => {
+                let name = match kind {
+                    FloatOperation::AbsoluteValue => "abs",
+                    FloatOperation::Negation => "neg",
+                    FloatOperation::Ceiling => "ceil",
+                    FloatOperation::Floor => "floor",
+                    FloatOperation::Truncate => "trunc",
+                    FloatOperation::Nearest => "nearest",
+                    FloatOperation::SquareRoot => "sqrt",
+                    FloatOperation::Minimum => "min",
+                    FloatOperation::Maximum => "max",
+                    FloatOperation::CopySign => "copysign",
+                };
+                write!(f, "{}.{name}", if *is_64_bit { "f64" } else { "f32" })
+            }
+            SerializedInstruction::Conversion(c) => f.write_str(match c {
+                NumericConversionKind::WrapInt => "i32.wrap_i64",
+                NumericConversionKind::SignedTruncF32ToI32 => "i32.trunc_f32_s",
+                NumericConversionKind::UnsignedTruncF32ToI32 => "i32.trunc_f32_u",
+                NumericConversionKind::SignedTruncF64ToI32 => "i32.trunc_f64_s",
+                NumericConversionKind::UnsignedTruncF64ToI32 => "i32.trunc_f64_u",
+                NumericConversionKind::SignedTruncF32ToI64 => "i64.trunc_f32_s",
+                NumericConversionKind::UnsignedTruncF32ToI64 => "i64.trunc_f32_u",
+                NumericConversionKind::SignedTruncF64ToI64 => "i64.trunc_f64_s",
+                NumericConversionKind::UnsignedTruncF64ToI64 => "i64.trunc_f64_u",
+                NumericConversionKind::SignedExtend => "i64.extend_i32_s",
+                NumericConversionKind::UnsignedExtend => "i64.extend_i32_u",
+                NumericConversionKind::SignedConvertI32ToF32 => "f32.convert_i32_s",
+                NumericConversionKind::UnsignedConvertI32ToF32 => "f32.convert_i32_u",
+                NumericConversionKind::SignedConvertI64ToF32 => "f32.convert_i64_s",
+                NumericConversionKind::UnsignedConvertI64ToF32 => "f32.convert_i64_u",
+                NumericConversionKind::SignedConvertI32ToF64 => "f64.convert_i32_s",
+                NumericConversionKind::UnsignedConvertI32ToF64 => "f64.convert_i32_u",
+                NumericConversionKind::SignedConvertI64ToF64 => "f64.convert_i64_s",
+                NumericConversionKind::UnsignedConvertI64ToF64 => "f64.convert_i64_u",
+                NumericConversionKind::DemoteFloat => "f32.demote_f64",
+                NumericConversionKind::PromoteFloat => "f64.promote_f32",
+                NumericConversionKind::Reinterpret32FToI => "i32.reinterpret_f32",
+                NumericConversionKind::Reinterpret32IToF => "f32.reinterpret_i32",
+                NumericConversionKind::Reinterpret64FToI => "i64.reinterpret_f64",
+                NumericConversionKind::Reinterpret64IToF => "f64.reinterpret_i64",
+            }),
+            SerializedInstruction::DefaultString(s) => f.write_str(s),
+        }
+    }
+}
+
+impl InputOutput {
+    /// Short description, like `: Index(fac), Params(n I64), Results(I64)`
+    fn describe(&self) -> String {
+        let index = self
+            .index
+            .as_ref()
+            .map(|i| format!("Index({i}), "))
+            .unwrap_or_default();
+        let params = self
+            .input
+            .iter()
+            .enumerate()
+            .map(|(i, (name, typ))| format!("{} {typ}", name.clone().unwrap_or(i.to_string())))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let results = format_types(&self.output);
+        format!(": {index}Params({params}), Results({results})")
+    }
+}
+
+impl SerializedInstruction {
+    /// Describe what the instruction does in plain English
+    pub fn plain_english(&self) -> String {
+        match self {
+            SerializedInstruction::Simple(s) => match s {
+                SimpleInstruction::Unreachable => "Unreachable".to_string(),
+                SimpleInstruction::Nop => "Do nothing".to_string(),
+                SimpleInstruction::Return => "Return immediately".to_string(),
+                SimpleInstruction::Drop => "Drop top value from stack".to_string(),
+            },
+            SerializedInstruction::Block { label, kind, inout } => {
+                let inout = inout
+                    .as_ref()
+                    .map(InputOutput::describe)
+                    .unwrap_or_default();
+                match kind {
+                    BlockKind::Block => format!("Start New Block {label}{inout}"),
+                    BlockKind::If => format!("Start If block {label}{inout}"),
+                    BlockKind::Loop => format!("Start Loop block {label}{inout}"),
+                    BlockKind::Else => format!("Start Else block {label}"),
+                    BlockKind::End => format!("End current block {label}"),
+                }
+            }
+            SerializedInstruction::Branch {
+                default_label,
+                other_labels,
+                is_conditional,
+                ..
+            } => {
+                if !other_labels.is_empty() {
+                    format!(
+                        "Branch Table: Cases: {}, default: {default_label}.",
+                        other_labels.join(",")
+                    )
+                } else if *is_conditional {
+                    format!("Branch if value on stack is not 0 to {default_label}.")
+                } else {
+                    format!("Branch unconditionally to {default_label}.")
+                }
+            }
+            SerializedInstruction::Call { index } => {
+                format!("Call function {index} with its parameters from stack, which puts back its results.")
+            }
+            SerializedInstruction::CallIndirect { table, type_use } => {
+                let input = type_use.get_input_types();
+                format!(
+                    "Call the function in table {table} at the index on top of stack, with {} from stack, which puts back on stack: {}.",
+                    if input.is_empty() {
+                        "no input".to_string()
+                    } else {
+                        format_types(&input)
+                    },
+                    if type_use.output.is_empty() {
+                        "nothing".to_string()
+                    } else {
+                        format_types(&type_use.output)
+                    }
+                )
+            }
+            SerializedInstruction::Data { kind, location } => {
+                let location = format_index(location);
+                match kind {
+                    DataInstruction::GetLocal => {
+                        format!("Get value from Local variable {location} and push it onto stack.")
+                    }
+                    DataInstruction::GetGlobal => {
+                        format!("Get value from Global variable {location} and push it onto stack.")
+                    }
+                    DataInstruction::SetLocal => {
+                        format!("Pop value from stack and set Local variable {location} to it.")
+                    }
+                    DataInstruction::SetGlobal => {
+                        format!("Pop value from stack and set Global variable {location} to it.")
+                    }
+                    DataInstruction::TeeLocal => format!(
+                        "Pop value from stack, set Local variable {location} to it, then push value back onto stack."
+                    ),
+                    DataInstruction::GetMemorySize => {
+                        format!("Push size of memory location {location} onto stack.")
+                    }
+                    DataInstruction::SetMemorySize => format!(
+                        "Pop value from stack, attempt to grow memory location {location} by it, and push old memory size if successful or else -1 to stack."
+                    ),
+                }
+            }
+            SerializedInstruction::Memory {
+                location,
+                typ,
+                count,
+                offset,
+                alignment,
+                is_storing,
+                is_signed,
+            } => {
+                let location = format_index(location);
+                let extend = if *is_signed { "sign" } else { "zero" };
+                if *is_storing {
+                    format!("Storing {count:?} of {typ} to offset {offset} (alignment: {alignment:?}) at {location}.")
+                } else {
+                    format!("Loading from {location} at offset {offset} (alignment: {alignment:?}) {count:?} of type {typ}, {extend} extended if smaller.")
+                }
+            }
+            SerializedInstruction::Const { typ, value } => {
+                format!("Push constant {value} of type {typ} to stack.")
+            }
+            SerializedInstruction::Comparison { kind, typ } => {
+                let (signedness, message) = match kind {
+                    ComparisonOperation::EqualZero => {
+                        return format!(
+                            "Pop {typ} value from stack, push 1 if value equals 0 otherwise 0."
+                        )
+                    }
+                    ComparisonOperation::Equal => ("", "first value equals second"),
+                    ComparisonOperation::NotEqual => ("", "first value does not equal second"),
+                    ComparisonOperation::LessThenSigned => {
+                        ("signed ", "first value less than second")
+                    }
+                    ComparisonOperation::LessThenUnsigned => {
+                        ("unsigned ", "first value less than second")
+                    }
+                    ComparisonOperation::GreaterThenSigned => {
+                        ("signed ", "first value is greater than second")
+                    }
+                    ComparisonOperation::GreaterThenUnsigned => {
+                        ("unsigned ", "first value is greater than second")
+                    }
+                    ComparisonOperation::LessThenOrEqualToSigned => {
+                        ("signed ", "first value is less than or equals second")
+                    }
+                    ComparisonOperation::LessThenOrEqualToUnsigned => {
+                        ("unsigned ", "first value is less than or equals second")
+                    }
+                    ComparisonOperation::GreaterThenOrEqualToSigned => {
+                        ("signed ", "first value is greater than or equals second")
+                    }
+                    ComparisonOperation::GreaterThenOrEqualToUnsigned => {
+                        ("unsigned ", "first value is greater than or equals second")
+                    }
+                };
+                // Floats have no signedness
+                let signedness = if is_float(typ) { "" } else { signedness };
+                format!("Pop top 2 {signedness}{typ} values from stack, push 1 if {message} otherwise push 0.")
+            }
+            SerializedInstruction::Arithmetic { kind, typ } => {
+                let (signedness, operation) = match kind {
+                    ArithmeticOperation::Addition => ("", "first + second"),
+                    ArithmeticOperation::Subtraction => ("", "first - second"),
+                    ArithmeticOperation::Multiplication => ("", "first * second"),
+                    ArithmeticOperation::DivisonSigned => ("signed ", "first / second"),
+                    ArithmeticOperation::DivisonUnsigned => ("unsigned ", "first / second"),
+                    ArithmeticOperation::RemainderSigned => ("signed ", "first % second"),
+                    ArithmeticOperation::RemainderUnsigned => ("unsigned ", "first % second"),
+                };
+                let signedness = if is_float(typ) { "" } else { signedness };
+                format!("Pop top 2 {signedness}{typ} values from stack, push {operation} result to stack.")
+            }
+            SerializedInstruction::Bitwise { kind, is_64_bit } => {
+                let (number_to_pop, operation) = match kind {
+                    BitwiseOperation::CountLeadingZero => {
+                        (1, "number of leading zero bits of value")
+                    }
+                    BitwiseOperation::CountTrailingZero => {
+                        (1, "number of trailing zero bits of value")
+                    }
+                    BitwiseOperation::CountNonZero => (1, "number of non-zero bits of value"),
+                    BitwiseOperation::And => (2, "bitwise and result of two values"),
+                    BitwiseOperation::Or => (2, "bitwise or result of two values"),
+                    BitwiseOperation::Xor => (2, "bitwise xor result of two values"),
+                    BitwiseOperation::ShiftLeft => (2, "left shift first by second value result"),
+                    BitwiseOperation::ShiftRightSigned => (
+                        2,
+                        "sign preserving right shift first by second values result",
+                    ),
+                    BitwiseOperation::ShiftRightUnsigned => {
+                        (2, "sign ignoring right shift first by second values result")
+                    }
+                    BitwiseOperation::RotateLeft => {
+                        (2, "left bit rotation of first by second values result")
+                    }
+                    BitwiseOperation::RotateRight => {
+                        (2, "right bit rotation of first by second values result")
+                    }
+                };
+                format!(
+                    "Pop {number_to_pop} {} {} from stack, push {operation} to stack.",
+                    if *is_64_bit { "I64" } else { "I32" },
+                    if number_to_pop == 1 {
+                        "value"
+                    } else {
+                        "values"
+                    }
+                )
+            }
+            SerializedInstruction::Float { kind, is_64_bit } => {
+                let (number_to_pop, operation) = match kind {
+                    FloatOperation::AbsoluteValue => (1, "absolute value of value"),
+                    FloatOperation::Negation => (1, "negation of value"),
+                    FloatOperation::Ceiling => (1, "ceiling of value"),
+                    FloatOperation::Floor => (1, "floor of value"),
+                    FloatOperation::Truncate => (1, "truncation towards 0 of value"),
+                    FloatOperation::Nearest => (1, "nearest even integer of value"),
+                    FloatOperation::SquareRoot => (1, "square root of value"),
+                    FloatOperation::Minimum => (2, "minimum of the two values"),
+                    FloatOperation::Maximum => (2, "maximum of the two values"),
+                    FloatOperation::CopySign => {
+                        (2, "first value with the sign of the second value")
+                    }
+                };
+                format!(
+                    "Pop top {number_to_pop} {} {} from stack, push {operation} to stack.",
+                    if *is_64_bit { "F64" } else { "F32" },
+                    if number_to_pop == 1 {
+                        "value"
+                    } else {
+                        "values"
+                    }
+                )
+            }
+            SerializedInstruction::Conversion(c) => match c {
+                NumericConversionKind::WrapInt => "Wrap I64 to I32.",
+                NumericConversionKind::SignedTruncF32ToI32 => "Truncate an F32 to a signed I32.",
+                NumericConversionKind::UnsignedTruncF32ToI32 => {
+                    "Truncate an F32 to an unsigned I32."
+                }
+                NumericConversionKind::SignedTruncF64ToI32 => "Truncate an F64 to a signed I32.",
+                NumericConversionKind::UnsignedTruncF64ToI32 => {
+                    "Truncate an F64 to an unsigned I32."
+                }
+                NumericConversionKind::SignedTruncF32ToI64 => "Truncate an F32 to a signed I64.",
+                NumericConversionKind::UnsignedTruncF32ToI64 => {
+                    "Truncate an F32 to an unsigned I64."
+                }
+                NumericConversionKind::SignedTruncF64ToI64 => "Truncate an F64 to a signed I64.",
+                NumericConversionKind::UnsignedTruncF64ToI64 => {
+                    "Truncate an F64 to an unsigned I64."
+                }
+                NumericConversionKind::SignedExtend => "Extend a signed I32 to an I64.",
+                NumericConversionKind::UnsignedExtend => "Extend an unsigned I32 to an I64.",
+                NumericConversionKind::SignedConvertI32ToF32 => "Convert a signed I32 to an F32.",
+                NumericConversionKind::UnsignedConvertI32ToF32 => {
+                    "Convert an unsigned I32 to an F32."
+                }
+                NumericConversionKind::SignedConvertI64ToF32 => "Convert a signed I64 to an F32.",
+                NumericConversionKind::UnsignedConvertI64ToF32 => {
+                    "Convert an unsigned I64 to an F32."
+                }
+                NumericConversionKind::SignedConvertI32ToF64 => "Convert a signed I32 to an F64.",
+                NumericConversionKind::UnsignedConvertI32ToF64 => {
+                    "Convert an unsigned I32 to an F64."
+                }
+                NumericConversionKind::SignedConvertI64ToF64 => "Convert a signed I64 to an F64.",
+                NumericConversionKind::UnsignedConvertI64ToF64 => {
+                    "Convert an unsigned I64 to an F64."
+                }
+                NumericConversionKind::DemoteFloat => "Demote an F64 to an F32.",
+                NumericConversionKind::PromoteFloat => "Promote an F32 to an F64.",
+                NumericConversionKind::Reinterpret32FToI => {
+                    "Reinterpret a 32-bit value from float to int."
+                }
+                NumericConversionKind::Reinterpret32IToF => {
+                    "Reinterpret a 32-bit value from int to float."
+                }
+                NumericConversionKind::Reinterpret64FToI => {
+                    "Reinterpret a 64-bit value from float to int."
+                }
+                NumericConversionKind::Reinterpret64IToF => {
+                    "Reinterpret a 64-bit value from int to float."
+                }
+            }
+            .to_string(),
+            SerializedInstruction::DefaultString(s) => format!("Unsupported instruction: {s}"),
+        }
+    }
+}
+
+/// Join types for descriptions, like `I32, F64`
+pub fn format_types(types: &[SerializableWatType]) -> String {
+    types
+        .iter()
+        .map(|typ| typ.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn is_float(typ: &SerializableWatType) -> bool {
+    matches!(typ, SerializableWatType::F32 | SerializableWatType::F64)
+}
+
+/// Write a type use the way the text format does, e.g. ` (param i32) (result i64)`
+fn write_type_use(f: &mut std::fmt::Formatter<'_>, type_use: &InputOutput) -> std::fmt::Result {
+    if let Some(index) = &type_use.index {
+        write!(f, " (type {})", format_index(index))?;
+    }
+    for (_, typ) in &type_use.input {
+        write!(f, " (param {})", type_prefix(typ))?;
+    }
+    for typ in &type_use.output {
+        write!(f, " (result {})", type_prefix(typ))?;
+    }
+    Ok(())
+}
+
+/// Lowercase type name used as an instruction prefix (e.g. `i32` in `i32.add`)
+fn type_prefix(typ: &SerializableWatType) -> &'static str {
+    match typ {
+        SerializableWatType::I32 => "i32",
+        SerializableWatType::I64 => "i64",
+        SerializableWatType::F32 => "f32",
+        SerializableWatType::F64 => "f64",
+        SerializableWatType::V128 => "v128",
+        SerializableWatType::FuncRef => "funcref",
+        SerializableWatType::ExternRef => "externref",
+    }
+}
+
 pub(crate) fn index_to_string(index: &Index) -> String {
     match index {
         Index::Num(idx, _) => idx.to_string(),
@@ -933,16 +1499,30 @@ impl From<&BlockKind> for NodeMark {
     }
 }
 
+/// Where a branch goes, resolved while building the block tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct BranchTarget {
+    /// Index of the targeted node in [SerializedInstructionTree::root], 0 is the function body
+    pub node: u32,
+    /// Relative depth of the target, 0 is the innermost enclosing block
+    pub depth: u32,
+    /// Instruction to continue from: after a loop's start, after a block's end,
+    /// or the instruction count when leaving the function
+    pub continuation: u32,
+}
+
 /// A node representing the instruction block.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
 pub struct SerializedInstructionNode {
-    kind: NodeMark,
-    label: String,
-    depth: u32,
+    pub(crate) kind: NodeMark,
+    pub(crate) label: String,
+    pub(crate) depth: u32,
     pub(crate) start: u32,
     pub(crate) end: u32,
-    parent: u32,
+    pub(crate) parent: u32,
     children: HashMap<u32, u32>,
+    /// Branch instructions that target this block
+    pub(crate) branches: Vec<u32>,
 }
 
 impl SerializedInstructionNode {
@@ -955,6 +1535,7 @@ impl SerializedInstructionNode {
             end: start,
             parent,
             children: HashMap::new(),
+            branches: Vec::new(),
         }
     }
 
@@ -982,15 +1563,12 @@ impl SerializedInstructionNode {
 }
 
 pub fn conditional_else_not_assigned(node: &SerializedInstructionNode) -> bool {
-    match node.kind {
-        NodeMark::Conditional(0) => true,
-        _ => false,
-    }
+    matches!(node.kind, NodeMark::Conditional(0))
 }
 
 pub fn linear_instructions_to_tree(
     func_label: &str,
-    linear_instructions: &Vec<SerializedInstruction>,
+    linear_instructions: &mut [SerializedInstruction],
 ) -> WatResult<Vec<SerializedInstructionNode>> {
     let mut nodes = vec![SerializedInstructionNode::func_block(
         func_label.to_string(),
@@ -999,6 +1577,8 @@ pub fn linear_instructions_to_tree(
     // References index for `nodes`.
     // Last item is the current working node.
     let mut node_start_stack = vec![0];
+    // Branches with their target nodes, resolved to instructions once every block has its end
+    let mut pending = Vec::new();
     for (index, instruction) in linear_instructions.iter().enumerate() {
         // println!("Before #{index}: {:?}, {:?}", &nodes, &node_start_stack);
         match instruction {
@@ -1022,7 +1602,7 @@ pub fn linear_instructions_to_tree(
                             .iter()
                             .rev()
                             .position(conditional_else_not_assigned)
-                            .unwrap();
+                            .ok_or(WatError::else_without_if_error())?;
 
                         let if_node_position = nodes.len() - 1 - conditional_stack_number;
                         nodes
@@ -1037,6 +1617,10 @@ pub fn linear_instructions_to_tree(
                             });
                     }
                     BlockKind::End => {
+                        // Only the function body is open, which has no `end` of its own
+                        if node_start_stack.len() == 1 {
+                            return Err(WatError::end_without_block_error());
+                        }
                         // nodes.last_mut().map(|node| node.set_end(index as u32));
                         if let Some(child) = node_start_stack.pop() {
                             let start = nodes
@@ -1055,12 +1639,39 @@ pub fn linear_instructions_to_tree(
                 }
             }
             SerializedInstruction::Branch {
-                default_label: _,
-                other_labels: _,
-                is_conditional: _,
+                default_label,
+                other_labels,
+                ..
             } => {
-                // May do something special in future, right now just `continue`
-                continue;
+                // Innermost open block first, so depth 0 is the last on the stack
+                let resolve = |label: &String| match try_name_to_index(label) {
+                    Ok(depth) => node_start_stack
+                        .iter()
+                        .rev()
+                        .nth(depth)
+                        .map(|node| (*node, depth as u32)),
+                    // The function body has no label of its own
+                    Err(name) => node_start_stack
+                        .iter()
+                        .rev()
+                        .enumerate()
+                        .find(|(_, node)| **node != 0 && nodes[**node as usize].label == name)
+                        .map(|(depth, node)| (*node, depth as u32)),
+                };
+                let resolved: Option<Vec<_>> = other_labels
+                    .iter()
+                    .chain([default_label])
+                    .map(resolve)
+                    .collect();
+                if let Some(resolved) = resolved {
+                    for (node, _) in &resolved {
+                        let branches = &mut nodes[*node as usize].branches;
+                        if branches.last() != Some(&(index as u32)) {
+                            branches.push(index as u32);
+                        }
+                    }
+                    pending.push((index, resolved));
+                }
             }
             _ => {
                 continue;
@@ -1072,7 +1683,24 @@ pub fn linear_instructions_to_tree(
         .expect("Node start stack to have starting node") as usize;
     nodes
         .get_mut(expected_first)
-        .map(|node| node.set_end(linear_instructions.len() as u32 - 1));
+        .map(|node| node.set_end((linear_instructions.len() as u32).saturating_sub(1)));
+    let exit = linear_instructions.len() as u32;
+    for (index, resolved) in pending {
+        if let SerializedInstruction::Branch { targets, .. } = &mut linear_instructions[index] {
+            *targets = resolved
+                .into_iter()
+                .map(|(node, depth)| BranchTarget {
+                    node,
+                    depth,
+                    continuation: match nodes[node as usize].kind {
+                        _ if node == 0 => exit,
+                        NodeMark::Loop => nodes[node as usize].start + 1,
+                        _ => nodes[node as usize].end + 1,
+                    },
+                })
+                .collect();
+        }
+    }
     Ok(nodes)
 }
 
@@ -1083,17 +1711,30 @@ pub struct SerializedInstructionTree {
     pub root: Vec<SerializedInstructionNode>,
     /// Linear set of instructions
     pub array: Vec<SerializedInstruction>,
+    /// Plain English description of each instruction in `array`
+    pub descriptions: Vec<String>,
 }
 
 impl SerializedInstructionTree {
     pub fn try_from_instruction(name: &str, value: &[Instruction]) -> WatResult<Self> {
-        let linear_instrctions = (*value)
+        let linear_instrctions: Vec<SerializedInstruction> = (*value)
             .iter()
             .map(|ins| ins.try_into())
             .collect::<Result<_, _>>()?;
+        Self::try_from_serialized(name, linear_instrctions)
+    }
 
+    /// Build the tree for instructions that were already converted, resolving their branches again
+    pub fn try_from_serialized(
+        name: &str,
+        mut linear_instrctions: Vec<SerializedInstruction>,
+    ) -> WatResult<Self> {
         Ok(Self {
-            root: linear_instructions_to_tree(name, &linear_instrctions)?,
+            root: linear_instructions_to_tree(name, &mut linear_instrctions)?,
+            descriptions: linear_instrctions
+                .iter()
+                .map(SerializedInstruction::plain_english)
+                .collect(),
             array: linear_instrctions,
         })
     }
@@ -1199,3 +1840,72 @@ impl SerializedInstructionTree {
 // Ok(Self { root: current })
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::module_with_func;
+
+    /// Targets of each branch in the body of a function with an `i32` parameter
+    fn targets(body: &str) -> Vec<Vec<BranchTarget>> {
+        let structure = crate::build_structure(&module_with_func("", "(param i32)", body)).unwrap();
+        structure.func[0]
+            .block
+            .array
+            .iter()
+            .filter_map(|instruction| match instruction {
+                SerializedInstruction::Branch { targets, .. } => Some(targets.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn target(node: u32, depth: u32, continuation: u32) -> BranchTarget {
+        BranchTarget {
+            node,
+            depth,
+            continuation,
+        }
+    }
+
+    #[test]
+    fn indices_and_labels_resolve_to_the_same_block() {
+        // Nodes are the function body, then $a and $b
+        let targets = targets("block $a block $b br 1 br $a end end nop");
+        assert_eq!(targets, [vec![target(1, 1, 6)], vec![target(1, 1, 6)]]);
+    }
+
+    #[test]
+    fn loops_continue_at_their_start_and_blocks_after_their_end() {
+        let targets = targets("block $out loop $in local.get 0 br_if $in br $out end end nop");
+        assert_eq!(targets, [vec![target(2, 0, 2)], vec![target(1, 1, 7)]]);
+    }
+
+    #[test]
+    fn br_table_has_the_default_target_last() {
+        let targets = targets("block $a block $b local.get 0 br_table $b 1 $a end end nop");
+        assert_eq!(
+            targets,
+            [vec![target(2, 0, 5), target(1, 1, 6), target(1, 1, 6)]]
+        );
+    }
+
+    #[test]
+    fn unmatched_else_and_end_are_errors() {
+        for body in [
+            "else",
+            "end",
+            "i32.const 0 if else else end",
+            "block end end",
+        ] {
+            let text = module_with_func("", "", body);
+            assert!(crate::build_structure(&text).is_err(), "{body}");
+        }
+    }
+
+    #[test]
+    fn branches_out_of_the_function_leave_it() {
+        let targets = targets("block br 1 end local.get 0 drop");
+        assert_eq!(targets, [vec![target(0, 1, 5)]]);
+    }
+}
@@ -0,0 +1,1561 @@
+//! Execution engine for an [InterpreterStructure].
+//!
+//! Runs the linear instructions of a function directly,
+//! using the block tree from [crate::instruction::linear_instructions_to_tree] to find where blocks start and end.
+
+use std::{collections::HashMap, fmt::Display};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use wast::token::{Float32, Float64};
+
+use crate::{
+    error::{TrapKind, WatError, WatResult},
+    float,
+    helper::SerializedNumber,
+    instruction::{BranchTarget, NodeMark, SerializedInstruction},
+    marker::{
+        ArithmeticOperation, BitwiseOperation, BlockKind, ByteKind, ComparisonOperation,
+        DataInstruction, FloatOperation, NumericConversionKind, SerializableWatType,
+        SimpleInstruction,
+    },
+    memory::{self, LinearMemory, MemoryAccess, SegmentRegion},
+    validator::ValueMapping,
+    watch::{Watch, WatchScope, WatchValue},
+    GlobalData, InterpreterStructure, NumLocationKind,
+};
+
+/// A value on the stack, in a local or in a global at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Value {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    /// Reference to a function by its index, [None] for null
+    FuncRef(Option<u32>),
+    /// Reference to a host value, [None] for null
+    ExternRef(Option<u32>),
+}
+
+impl Value {
+    /// The zero value of the given type, used for initializing locals
+    pub fn default_of(typ: &SerializableWatType) -> WatResult<Self> {
+        match typ {
+            SerializableWatType::I32 => Ok(Value::I32(0)),
+            SerializableWatType::I64 => Ok(Value::I64(0)),
+            SerializableWatType::F32 => Ok(Value::F32(0.0)),
+            SerializableWatType::F64 => Ok(Value::F64(0.0)),
+            SerializableWatType::V128 => Err(WatError::unimplemented_error(
+                "V128 values are not supported yet.",
+            )),
+            SerializableWatType::FuncRef => Ok(Value::FuncRef(None)),
+            SerializableWatType::ExternRef => Ok(Value::ExternRef(None)),
+        }
+    }
+
+    /// Parse a value of the given type from text.
+    ///
+    /// Integers can be written either signed or unsigned (e.g. `-1` or `4294967295` for i32),
+    /// and references as `null` or the index they point to.
+    pub fn parse(typ: &SerializableWatType, text: &str) -> WatResult<Self> {
+        let error = || WatError::invalid_argument_error(text, typ);
+        match typ {
+            SerializableWatType::I32 => text
+                .parse::<i32>()
+                .or_else(|_| text.parse::<u32>().map(|n| n as i32))
+                .map(Value::I32)
+                .map_err(|_| error()),
+            SerializableWatType::I64 => text
+                .parse::<i64>()
+                .or_else(|_| text.parse::<u64>().map(|n| n as i64))
+                .map(Value::I64)
+                .map_err(|_| error()),
+            SerializableWatType::F32 => text.parse().map(Value::F32).map_err(|_| error()),
+            SerializableWatType::F64 => text.parse().map(Value::F64).map_err(|_| error()),
+            SerializableWatType::V128 => Err(error()),
+            SerializableWatType::FuncRef | SerializableWatType::ExternRef => {
+                let index = match text {
+                    "null" => None,
+                    text => Some(text.parse::<u32>().map_err(|_| error())?),
+                };
+                Ok(Value::from(SerializedNumber::reference(*typ, index)))
+            }
+        }
+    }
+
+    /// Bits stored in memory for this value, 32-bit values only use the lower half
+    pub fn to_bits(&self) -> u64 {
+        match self {
+            Value::I32(n) => *n as u32 as u64,
+            Value::I64(n) => *n as u64,
+            Value::F32(n) => n.to_bits() as u64,
+            Value::F64(n) => n.to_bits(),
+            Value::FuncRef(_) | Value::ExternRef(_) => SerializedNumber::from(*self).to_bits(),
+        }
+    }
+
+    /// Value of the given type from bits loaded from memory, 32-bit types use the lower half
+    pub fn from_bits(typ: &SerializableWatType, bits: u64) -> WatResult<Self> {
+        match typ {
+            SerializableWatType::I32 => Ok(Value::I32(bits as u32 as i32)),
+            SerializableWatType::I64 => Ok(Value::I64(bits as i64)),
+            SerializableWatType::F32 => Ok(Value::F32(f32::from_bits(bits as u32))),
+            SerializableWatType::F64 => Ok(Value::F64(f64::from_bits(bits))),
+            SerializableWatType::V128 => Err(WatError::unimplemented_error(
+                "V128 values are not supported yet.",
+            )),
+            SerializableWatType::FuncRef | SerializableWatType::ExternRef => Err(
+                WatError::unimplemented_error("References cannot be stored in memory."),
+            ),
+        }
+    }
+
+    pub fn typ(&self) -> SerializableWatType {
+        match self {
+            Value::I32(_) => SerializableWatType::I32,
+            Value::I64(_) => SerializableWatType::I64,
+            Value::F32(_) => SerializableWatType::F32,
+            Value::F64(_) => SerializableWatType::F64,
+            Value::FuncRef(_) => SerializableWatType::FuncRef,
+            Value::ExternRef(_) => SerializableWatType::ExternRef,
+        }
+    }
+
+    /// Check that the value is zero, used for conditions
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Value::I32(n) => *n == 0,
+            Value::I64(n) => *n == 0,
+            Value::F32(n) => *n == 0.0,
+            Value::F64(n) => *n == 0.0,
+            Value::FuncRef(index) | Value::ExternRef(index) => index.is_none(),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::I32(n) => write!(f, "{n}"),
+            Value::I64(n) => write!(f, "{n}"),
+            Value::F32(n) => write!(f, "{n}"),
+            Value::F64(n) => write!(f, "{n}"),
+            Value::FuncRef(_) | Value::ExternRef(_) => {
+                write!(f, "{}", SerializedNumber::from(*self))
+            }
+        }
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::I32(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::I64(value)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(value: f32) -> Self {
+        Value::F32(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::F64(value)
+    }
+}
+
+impl From<SerializedNumber> for Value {
+    fn from(value: SerializedNumber) -> Self {
+        let bits = value.to_bits();
+        match value.typ() {
+            SerializableWatType::I64 => Value::I64(bits as i64),
+            SerializableWatType::F32 => Value::F32(f32::from_bits(bits as u32)),
+            SerializableWatType::F64 => Value::F64(f64::from_bits(bits)),
+            SerializableWatType::I32 | SerializableWatType::V128 => Value::I32(bits as u32 as i32),
+            SerializableWatType::FuncRef => Value::FuncRef(value.to_reference()),
+            SerializableWatType::ExternRef => Value::ExternRef(value.to_reference()),
+        }
+    }
+}
+
+impl From<Value> for SerializedNumber {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::I32(n) => n.into(),
+            Value::I64(n) => n.into(),
+            Value::F32(n) => Float32 { bits: n.to_bits() }.into(),
+            Value::F64(n) => Float64 { bits: n.to_bits() }.into(),
+            Value::FuncRef(index) => {
+                SerializedNumber::reference(SerializableWatType::FuncRef, index)
+            }
+            Value::ExternRef(index) => {
+                SerializedNumber::reference(SerializableWatType::ExternRef, index)
+            }
+        }
+    }
+}
+
+/// A single executed instruction, recorded when tracing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct TraceStep {
+    /// Number of instructions executed before this one
+    pub step: u32,
+    /// Name of the function being run
+    pub function: String,
+    /// Number of calls below the current function
+    pub call_depth: u32,
+    /// Position of the instruction in the function's instruction array
+    pub index: u32,
+    pub instruction: SerializedInstruction,
+    pub stack_before: Vec<SerializedNumber>,
+    pub stack_after: Vec<SerializedNumber>,
+    /// Loads and stores done by the instruction
+    pub memory: Vec<MemoryAccess>,
+    /// Value of each watch after the instruction, in the order they were added
+    pub watches: Vec<WatchValue>,
+    /// Why the instruction trapped, if it did, in which case it is the last step
+    pub trap: Option<TrapKind>,
+}
+
+/// A call that was running when a trap happened
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct CallSite {
+    pub function: String,
+    /// Instruction running in the function, the `call` for every function but the last
+    pub index: u32,
+}
+
+/// Where and why execution trapped
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct Trap {
+    pub kind: TrapKind,
+    pub message: String,
+    /// Calls from the invoked function to the one that trapped, which is last
+    pub call_stack: Vec<CallSite>,
+}
+
+impl Trap {
+    /// Function and instruction that trapped
+    pub fn location(&self) -> Option<&CallSite> {
+        self.call_stack.last()
+    }
+}
+
+/// Data segment copied into memory while instantiating
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct DataWrite {
+    /// Index of the memory in the module
+    pub memory: u32,
+    pub segment: SegmentRegion,
+}
+
+/// What instantiating a module did, before any export is called
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct Instantiation {
+    /// Globals with the values of their constant expressions
+    pub globals: Vec<GlobalData>,
+    /// Active data segments copied into memory, in address order
+    pub data: Vec<DataWrite>,
+    /// Name of the start function, if the module has one
+    pub start: Option<String>,
+    /// Stores done by the start function
+    pub start_writes: Vec<MemoryAccess>,
+    /// Trap in a data segment or the start function, after which the module cannot be used
+    pub trap: Option<Trap>,
+}
+
+/// A block that has been entered but not exited yet
+#[derive(Debug, Clone)]
+struct Label {
+    kind: NodeMark,
+    end: u32,
+    /// Number of values carried by a branch to this label
+    arity: usize,
+    /// Stack height when the block was entered (without its parameters)
+    height: usize,
+}
+
+/// A function call being executed
+#[derive(Debug, Clone)]
+struct Frame {
+    function: usize,
+    pc: u32,
+    locals: ValueMapping<Value>,
+    stack: Vec<Value>,
+    labels: Vec<Label>,
+}
+
+/// What the machine should do after an instruction
+enum Flow {
+    Next,
+    Jump(u32),
+    Call(usize),
+    Return,
+}
+
+/// Executes functions of an [InterpreterStructure]
+#[derive(Debug, Clone)]
+pub struct Machine<'a> {
+    structure: &'a InterpreterStructure,
+    globals: ValueMapping<Value>,
+    memories: Vec<LinearMemory>,
+    functions: ValueMapping<usize>,
+    /// For each function, maps the index of a block instruction to its node in the block tree
+    blocks: Vec<HashMap<u32, usize>>,
+    max_steps: Option<u64>,
+    max_call_depth: usize,
+    is_tracing: bool,
+    watches: Vec<Watch>,
+    trace: Vec<TraceStep>,
+    /// Memory accessed by the current instruction, only recorded when tracing
+    accesses: Vec<MemoryAccess>,
+    /// Every store since recording started, used for the start function
+    writes: Option<Vec<MemoryAccess>>,
+    trap: Option<Trap>,
+}
+
+/// Instructions [invoke_function] runs before giving up, so a loop that never ends cannot hang the app
+pub(crate) const INVOKE_MAX_STEPS: u64 = 10_000_000;
+/// Instructions [trace_function] runs before giving up, fewer than [INVOKE_MAX_STEPS]
+/// since every step keeps copies of the stack
+const TRACE_MAX_STEPS: u64 = 100_000;
+
+impl<'a> Machine<'a> {
+    const DEFAULT_MAX_CALL_DEPTH: usize = 1024;
+
+    /// Machine with globals and empty memories as the module declares them,
+    /// see [Machine::instantiate] to write data segments and run the start function
+    pub fn new(structure: &'a InterpreterStructure) -> WatResult<Self> {
+        Ok(Self {
+            structure,
+            globals: structure
+                .globals
+                .iter()
+                .map(|g| {
+                    (
+                        (!g.name.is_empty()).then(|| g.name.clone()),
+                        Value::from(g.val),
+                    )
+                })
+                .collect(),
+            memories: structure
+                .memory
+                .iter()
+                .map(LinearMemory::from_limits)
+                .collect::<WatResult<_>>()?,
+            functions: structure
+                .func
+                .iter()
+                .enumerate()
+                .map(|(i, f)| (f.name(), i))
+                .collect(),
+            blocks: structure
+                .func
+                .iter()
+                .map(|f| {
+                    f.block
+                        .root
+                        .iter()
+                        .enumerate()
+                        .skip(1)
+                        .map(|(i, node)| (node.start, i))
+                        .collect()
+                })
+                .collect(),
+            max_steps: None,
+            max_call_depth: Self::DEFAULT_MAX_CALL_DEPTH,
+            is_tracing: false,
+            watches: Vec::new(),
+            trace: Vec::new(),
+            accesses: Vec::new(),
+            writes: None,
+            trap: None,
+        })
+    }
+
+    /// Stop execution with an error after the given number of instructions
+    pub fn with_max_steps(mut self, max_steps: u64) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    /// Record every executed instruction, see [Machine::trace]
+    pub fn with_tracing(mut self) -> Self {
+        self.is_tracing = true;
+        self
+    }
+
+    /// Evaluate watch expressions after every instruction while tracing
+    pub fn with_watches(mut self, watches: Vec<Watch>) -> Self {
+        self.watches = watches;
+        self
+    }
+
+    /// Instructions recorded while tracing, kept even if execution failed
+    pub fn trace(&self) -> &[TraceStep] {
+        &self.trace
+    }
+
+    /// Trap that stopped the last call to [Machine::invoke], if it trapped
+    pub fn trap(&self) -> Option<&Trap> {
+        self.trap.as_ref()
+    }
+
+    /// Copy active data segments into memory and run the start function,
+    /// which a module does once before its exports can be called
+    ///
+    /// Tables are not supported yet, so there are no element segments to apply.
+    pub fn instantiate(&mut self) -> WatResult<Instantiation> {
+        let structure = self.structure;
+        let mut report = Instantiation {
+            globals: structure.globals.clone(),
+            data: Vec::new(),
+            start: structure.start.clone(),
+            start_writes: Vec::new(),
+            trap: None,
+        };
+        for (index, memory) in structure.memory.iter().enumerate() {
+            for (segment, bytes) in memory::segments(memory) {
+                if let Err(err) = self.memories[index].write(segment.start as u64, bytes) {
+                    let kind = err.trap().ok_or(err.clone())?;
+                    report.trap = Some(Trap {
+                        kind,
+                        message: err.message().unwrap_or_default().to_string(),
+                        call_stack: Vec::new(),
+                    });
+                    return Ok(report);
+                }
+                report.data.push(DataWrite {
+                    memory: index as u32,
+                    segment,
+                });
+            }
+        }
+        if let Some(start) = &structure.start {
+            let function =
+                self.functions
+                    .get(start)
+                    .copied()
+                    .ok_or(WatError::name_resolution_error(
+                        start,
+                        NumLocationKind::Function,
+                    ))?;
+            self.writes = Some(Vec::new());
+            let outcome = self.invoke(function, &[]);
+            report.start_writes = self.writes.take().unwrap_or_default();
+            if let Err(err) = outcome {
+                report.trap = Some(self.trap.clone().ok_or(err)?);
+            }
+        }
+        Ok(report)
+    }
+
+    /// Memory by its index in the module
+    pub fn memory(&self, index: usize) -> Option<&LinearMemory> {
+        self.memories.get(index)
+    }
+
+    /// Index of a memory from an instruction's location, which is an index or a name
+    fn memory_index(&self, location: &str) -> WatResult<usize> {
+        self.structure
+            .memory_index(location)
+            .ok_or(WatError::name_resolution_error(
+                location,
+                NumLocationKind::Memory,
+            ))
+    }
+
+    /// Find a function by export name, or otherwise by its name or index
+    pub fn find_function(&self, name: &str) -> WatResult<usize> {
+        if let Some((NumLocationKind::Function, index)) = self.structure.exported.get(name) {
+            return Ok(*index as usize);
+        }
+        self.functions
+            .get(name.strip_prefix('$').unwrap_or(name))
+            .copied()
+            .ok_or(WatError::name_resolution_error(
+                name,
+                NumLocationKind::Function,
+            ))
+    }
+
+    /// Parameter types of a function, used to read arguments
+    pub fn param_types(&self, function: usize) -> Vec<SerializableWatType> {
+        self.structure.func[function].info.get_input_types()
+    }
+
+    /// Run a function to completion, returning its results
+    pub fn invoke(&mut self, function: usize, args: &[Value]) -> WatResult<Vec<Value>> {
+        let params = self.param_types(function);
+        let arg_types: Vec<_> = args.iter().map(Value::typ).collect();
+        if params != arg_types {
+            return Err(WatError::mismatched_inout(&params, &arg_types, false));
+        }
+        self.trace.clear();
+        self.trap = None;
+        let mut call_stack = vec![self.new_frame(function, args.to_vec())?];
+        let outcome = self.run(&mut call_stack);
+        if let Err(err) = &outcome {
+            self.trap = err.trap().map(|kind| Trap {
+                kind,
+                message: err.message().unwrap_or_default().to_string(),
+                call_stack: call_stack
+                    .iter()
+                    .map(|frame| CallSite {
+                        function: self.structure.func[frame.function]
+                            .name()
+                            .unwrap_or_default(),
+                        index: frame.pc,
+                    })
+                    .collect(),
+            });
+        }
+        outcome
+    }
+
+    /// Run until the call stack is empty, leaving the frames where they stopped if something fails
+    fn run(&mut self, call_stack: &mut Vec<Frame>) -> WatResult<Vec<Value>> {
+        let structure = self.structure;
+        let mut step = 0;
+        loop {
+            let call_depth = call_stack.len().saturating_sub(1);
+            let Some(frame) = call_stack.last_mut() else {
+                return Ok(Vec::new());
+            };
+            let func = &structure.func[frame.function];
+            let Some(instruction) = func.block.array.get(frame.pc as usize) else {
+                // Fell off the end of the function
+                let results = Self::take_results(frame, func.info.output.len())?;
+                call_stack.pop();
+                match call_stack.last_mut() {
+                    Some(caller) => {
+                        caller.stack.extend(results);
+                        caller.pc += 1;
+                    }
+                    None => return Ok(results),
+                }
+                continue;
+            };
+            if self.max_steps.is_some_and(|max| step >= max) {
+                return Err(WatError::step_limit_error(step));
+            }
+            let stack_before = self.is_tracing.then(|| numbers(&frame.stack));
+            let outcome = self.execute(frame, instruction);
+            if let Some(stack_before) = stack_before {
+                self.trace.push(TraceStep {
+                    step: u32::try_from(step).unwrap_or(u32::MAX),
+                    function: func.name().unwrap_or_default(),
+                    call_depth: call_depth as u32,
+                    index: frame.pc,
+                    instruction: instruction.clone(),
+                    stack_before,
+                    stack_after: numbers(&frame.stack),
+                    memory: std::mem::take(&mut self.accesses),
+                    watches: self.watch_values(&frame.locals),
+                    trap: outcome.as_ref().err().and_then(WatError::trap),
+                });
+            }
+            let flow = outcome?;
+            step += 1;
+            match flow {
+                Flow::Next => frame.pc += 1,
+                Flow::Jump(pc) => frame.pc = pc,
+                Flow::Call(callee) => {
+                    let param_count = structure.func[callee].info.input.len();
+                    if frame.stack.len() < param_count {
+                        return Err(WatError::not_enough_on_stack(
+                            param_count,
+                            frame.stack.len(),
+                        ));
+                    }
+                    if call_depth + 1 >= self.max_call_depth {
+                        return Err(WatError::call_stack_exhausted_error(call_depth + 1));
+                    }
+                    // Callers point at their `call` until it returns
+                    let args = frame.stack.split_off(frame.stack.len() - param_count);
+                    call_stack.push(self.new_frame(callee, args)?);
+                }
+                Flow::Return => {
+                    let results = Self::take_results(frame, func.info.output.len())?;
+                    call_stack.pop();
+                    match call_stack.last_mut() {
+                        Some(caller) => {
+                            caller.stack.extend(results);
+                            caller.pc += 1;
+                        }
+                        None => return Ok(results),
+                    }
+                }
+            }
+        }
+    }
+
+    fn watch_values(&self, locals: &ValueMapping<Value>) -> Vec<WatchValue> {
+        let scope = WatchScope {
+            locals,
+            globals: &self.globals,
+            memory: |location: &str| Ok(&self.memories[self.memory_index(location)?]),
+        };
+        self.watches
+            .iter()
+            .map(|watch| watch.evaluate(&scope))
+            .collect()
+    }
+
+    fn new_frame(&self, function: usize, args: Vec<Value>) -> WatResult<Frame> {
+        let func = &self.structure.func[function];
+        let locals = func
+            .info
+            .input
+            .iter()
+            .map(|(name, _)| name.clone())
+            .zip(args)
+            .map(Ok)
+            .chain(
+                func.locals
+                    .iter()
+                    .map(|(name, typ)| Value::default_of(typ).map(|val| (name.clone(), val))),
+            )
+            .collect::<WatResult<_>>()?;
+        Ok(Frame {
+            function,
+            pc: 0,
+            locals,
+            stack: Vec::new(),
+            labels: Vec::new(),
+        })
+    }
+
+    /// Take the top `count` values of the frame's stack as the function results
+    fn take_results(frame: &mut Frame, count: usize) -> WatResult<Vec<Value>> {
+        if frame.stack.len() < count {
+            return Err(WatError::not_enough_on_stack(count, frame.stack.len()));
+        }
+        Ok(frame.stack.split_off(frame.stack.len() - count))
+    }
+
+    fn execute(
+        &mut self,
+        frame: &mut Frame,
+        instruction: &SerializedInstruction,
+    ) -> WatResult<Flow> {
+        match instruction {
+            SerializedInstruction::Simple(s) => match s {
+                SimpleInstruction::Unreachable => Err(WatError::unreachable_error()),
+                SimpleInstruction::Nop => Ok(Flow::Next),
+                SimpleInstruction::Drop => {
+                    pop(frame)?;
+                    Ok(Flow::Next)
+                }
+                SimpleInstruction::Return => Ok(Flow::Return),
+            },
+            SerializedInstruction::Block { kind, inout, label } => match kind {
+                BlockKind::Block | BlockKind::Loop | BlockKind::If => {
+                    let condition = if matches!(kind, BlockKind::If) {
+                        Some(pop(frame)?)
+                    } else {
+                        None
+                    };
+                    let node_index = self.blocks[frame.function]
+                        .get(&frame.pc)
+                        .copied()
+                        .ok_or(WatError::label_resolution_error(label))?;
+                    let node = &self.structure.func[frame.function].block.root[node_index];
+                    let (params, results) = inout
+                        .as_ref()
+                        .map(|io| (io.input.len(), io.output.len()))
+                        .unwrap_or_default();
+                    if frame.stack.len() < params {
+                        return Err(WatError::not_enough_on_stack(params, frame.stack.len()));
+                    }
+                    frame.labels.push(Label {
+                        kind: node.kind,
+                        end: node.end,
+                        arity: if matches!(kind, BlockKind::Loop) {
+                            params
+                        } else {
+                            results
+                        },
+                        height: frame.stack.len() - params,
+                    });
+                    match (condition, node.kind) {
+                        (Some(cond), _) if !cond.is_zero() => Ok(Flow::Next),
+                        // Skip to the instruction after else
+                        (Some(_), NodeMark::Conditional(else_index)) if else_index != 0 => {
+                            Ok(Flow::Jump(else_index + 1))
+                        }
+                        // No else, so go straight to end
+                        (Some(_), _) => Ok(Flow::Jump(node.end)),
+                        (None, _) => Ok(Flow::Next),
+                    }
+                }
+                // Reaching else means the then-branch finished, so skip to the end
+                BlockKind::Else => frame
+                    .labels
+                    .last()
+                    .map(|label| Flow::Jump(label.end))
+                    .ok_or(WatError::else_without_if_error()),
+                BlockKind::End => {
+                    frame.labels.pop();
+                    Ok(Flow::Next)
+                }
+            },
+            SerializedInstruction::Branch {
+                default_label,
+                other_labels,
+                is_conditional,
+                targets,
+            } => {
+                // Labels that do not name an enclosing block leave the targets empty
+                let target = |index: usize| {
+                    targets
+                        .get(index)
+                        .ok_or_else(|| WatError::label_resolution_error(default_label))
+                };
+                if !other_labels.is_empty() {
+                    let index = pop_i32(frame)? as u32 as usize;
+                    branch(frame, target(index.min(other_labels.len()))?)
+                } else if *is_conditional {
+                    if pop_i32(frame)? == 0 {
+                        Ok(Flow::Next)
+                    } else {
+                        branch(frame, target(0)?)
+                    }
+                } else {
+                    branch(frame, target(0)?)
+                }
+            }
+            // Tables are not loaded yet, so every element is out of bounds
+            SerializedInstruction::CallIndirect { table, .. } => {
+                let element = pop_i32(frame)?;
+                Err(WatError::out_of_bounds_table_error(table, element as u32))
+            }
+            SerializedInstruction::Call { index } => self
+                .functions
+                .get(index)
+                .map(|function| Flow::Call(*function))
+                .ok_or(WatError::name_resolution_error(
+                    index,
+                    NumLocationKind::Function,
+                )),
+            SerializedInstruction::Data { kind, location } => {
+                match kind {
+                    DataInstruction::GetLocal => {
+                        let val = *frame
+                            .locals
+                            .get(location)
+                            .ok_or(WatError::local_resolution_error(location))?;
+                        frame.stack.push(val);
+                    }
+                    DataInstruction::SetLocal => {
+                        let val = pop(frame)?;
+                        *frame
+                            .locals
+                            .get_mut(location)
+                            .ok_or(WatError::local_resolution_error(location))? = val;
+                    }
+                    DataInstruction::TeeLocal => {
+                        let val = *frame.stack.last().ok_or(WatError::empty_stack(1))?;
+                        *frame
+                            .locals
+                            .get_mut(location)
+                            .ok_or(WatError::local_resolution_error(location))? = val;
+                    }
+                    DataInstruction::GetGlobal => {
+                        let val =
+                            *self
+                                .globals
+                                .get(location)
+                                .ok_or(WatError::name_resolution_error(
+                                    location,
+                                    NumLocationKind::Global,
+                                ))?;
+                        frame.stack.push(val);
+                    }
+                    DataInstruction::SetGlobal => {
+                        let val = pop(frame)?;
+                        *self.globals.get_mut(location).ok_or(
+                            WatError::name_resolution_error(location, NumLocationKind::Global),
+                        )? = val;
+                    }
+                    DataInstruction::GetMemorySize => {
+                        let index = self.memory_index(location)?;
+                        let size = self.memories[index].size() as i64;
+                        frame
+                            .stack
+                            .push(address_value(self.structure.memory[index].is_32, size));
+                    }
+                    DataInstruction::SetMemorySize => {
+                        let index = self.memory_index(location)?;
+                        let delta = address(pop(frame)?)?;
+                        // Growing too much is not a trap, it gives -1
+                        let old = u32::try_from(delta)
+                            .ok()
+                            .and_then(|delta| self.memories[index].grow(delta))
+                            .map_or(-1, |old| old as i64);
+                        frame
+                            .stack
+                            .push(address_value(self.structure.memory[index].is_32, old));
+                    }
+                }
+                Ok(Flow::Next)
+            }
+            SerializedInstruction::Memory {
+                location,
+                typ,
+                count,
+                offset,
+                is_storing,
+                is_signed,
+                ..
+            } => {
+                let index = self.memory_index(location)?;
+                let memory = &mut self.memories[index];
+                let (at, old, new) = if *is_storing {
+                    let bits = Value::to_bits(&pop(frame)?);
+                    let at = address(pop(frame)?)?;
+                    let old = memory.store(at, *offset, *count, bits)?;
+                    let new = memory.load(at, *offset, *count)?.1;
+                    (at, old, Some(new))
+                } else {
+                    let at = address(pop(frame)?)?;
+                    let (bits, old) = memory.load(at, *offset, *count)?;
+                    frame
+                        .stack
+                        .push(Value::from_bits(typ, extend(bits, *count, *is_signed))?);
+                    (at, old, None)
+                };
+                let access = MemoryAccess {
+                    memory: index as u32,
+                    // Any access that did not trap ends below 4 GiB
+                    address: (at + *offset as u64) as u32,
+                    old,
+                    new,
+                };
+                if let (Some(writes), true) = (&mut self.writes, *is_storing) {
+                    writes.push(access.clone());
+                }
+                if self.is_tracing {
+                    self.accesses.push(access);
+                }
+                Ok(Flow::Next)
+            }
+            SerializedInstruction::Const { value, .. } => {
+                frame.stack.push(Value::from(*value));
+                Ok(Flow::Next)
+            }
+            SerializedInstruction::Comparison { .. }
+            | SerializedInstruction::Arithmetic { .. }
+            | SerializedInstruction::Bitwise { .. }
+            | SerializedInstruction::Float { .. }
+            | SerializedInstruction::Conversion(_) => {
+                let count = operand_count(instruction).unwrap_or_default();
+                if frame.stack.len() < count {
+                    return Err(WatError::not_enough_on_stack(count, frame.stack.len()));
+                }
+                let operands = frame.stack.split_off(frame.stack.len() - count);
+                frame.stack.push(evaluate(instruction, &operands)?);
+                Ok(Flow::Next)
+            }
+            SerializedInstruction::DefaultString(msg) => Err(WatError::unimplemented_error(
+                &format!("Instruction not supported: {msg}"),
+            )),
+        }
+    }
+}
+
+/// Results of calling a function, or the trap that stopped it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum Invocation {
+    Returned(Vec<SerializedNumber>),
+    Trapped(Trap),
+}
+
+/// Parse arguments written as text for the function with the given name
+fn parse_args(machine: &Machine, name: &str, args: &[String]) -> WatResult<(usize, Vec<Value>)> {
+    let function = machine.find_function(name)?;
+    let params = machine.param_types(function);
+    if params.len() != args.len() {
+        return Err(WatError::wrong_arity_error(params.len(), args.len()));
+    }
+    let args = params
+        .iter()
+        .zip(args)
+        .map(|(typ, arg)| Value::parse(typ, arg))
+        .collect::<WatResult<Vec<_>>>()?;
+    Ok((function, args))
+}
+
+/// Call a function with arguments written as text, the way the command line does
+pub fn invoke_function(text: &str, name: &str, args: &[String]) -> WatResult<Invocation> {
+    let structure = crate::inner_transform(text)?;
+    let mut machine = Machine::new(&structure)?.with_max_steps(INVOKE_MAX_STEPS);
+    if let Some(trap) = machine.instantiate()?.trap {
+        return Ok(Invocation::Trapped(trap));
+    }
+    let (function, args) = parse_args(&machine, name, args)?;
+    match machine.invoke(function, &args) {
+        Ok(results) => Ok(Invocation::Returned(
+            results.into_iter().map(SerializedNumber::from).collect(),
+        )),
+        Err(err) => match machine.trap() {
+            Some(trap) => Ok(Invocation::Trapped(trap.clone())),
+            None => Err(err),
+        },
+    }
+}
+
+/// Call a function like [invoke_function], recording every step with the value of each watch after it
+///
+/// A trap ends the trace with the step that trapped, including one in the start function.
+pub fn trace_function(
+    text: &str,
+    name: &str,
+    args: &[String],
+    watches: &[String],
+) -> WatResult<Vec<TraceStep>> {
+    let watches = watches
+        .iter()
+        .map(|watch| watch.parse())
+        .collect::<WatResult<Vec<Watch>>>()?;
+    let structure = crate::inner_transform(text)?;
+    let mut machine = Machine::new(&structure)?
+        .with_max_steps(TRACE_MAX_STEPS)
+        .with_tracing()
+        .with_watches(watches);
+    if machine.instantiate()?.trap.is_none() {
+        let (function, args) = parse_args(&machine, name, args)?;
+        if let Err(err) = machine.invoke(function, &args) {
+            if machine.trap().is_none() {
+                return Err(err);
+            }
+        }
+    }
+    Ok(machine.trace().to_vec())
+}
+
+/// Instantiate a module, the phase before any export is called
+pub fn instantiate_module(text: &str) -> WatResult<Instantiation> {
+    let structure = crate::inner_transform(text)?;
+    Machine::new(&structure)?
+        .with_max_steps(INVOKE_MAX_STEPS)
+        .instantiate()
+}
+
+fn numbers(values: &[Value]) -> Vec<SerializedNumber> {
+    values.iter().copied().map(SerializedNumber::from).collect()
+}
+
+fn pop(frame: &mut Frame) -> WatResult<Value> {
+    frame.stack.pop().ok_or(WatError::empty_stack(1))
+}
+
+/// Address or page count popped for a memory instruction, i64 for 64-bit memories
+fn address(value: Value) -> WatResult<u64> {
+    match value {
+        Value::I32(n) => Ok(n as u32 as u64),
+        Value::I64(n) => Ok(n as u64),
+        other => Err(WatError::unexpected_type(
+            &SerializableWatType::I32,
+            &other.typ(),
+        )),
+    }
+}
+
+/// Page count pushed by a memory instruction, in the memory's address type
+fn address_value(is_32: bool, n: i64) -> Value {
+    if is_32 {
+        Value::I32(n as i32)
+    } else {
+        Value::I64(n)
+    }
+}
+
+/// Sign or zero extend the loaded bytes to 64 bits
+fn extend(bits: u64, count: ByteKind, is_signed: bool) -> u64 {
+    let unused = 64 - 8 * count.byte_count();
+    if is_signed && unused > 0 {
+        (((bits << unused) as i64) >> unused) as u64
+    } else {
+        bits
+    }
+}
+
+fn pop_i32(frame: &mut Frame) -> WatResult<i32> {
+    match pop(frame)? {
+        Value::I32(n) => Ok(n),
+        other => Err(WatError::unexpected_type(
+            &SerializableWatType::I32,
+            &other.typ(),
+        )),
+    }
+}
+
+/// Number of values taken by an instruction that only computes a new value from them,
+/// like `i32.add`, or [None] for every other instruction
+pub(crate) fn operand_count(instruction: &SerializedInstruction) -> Option<usize> {
+    match instruction {
+        SerializedInstruction::Comparison {
+            kind: ComparisonOperation::EqualZero,
+            ..
+        }
+        | SerializedInstruction::Bitwise {
+            kind:
+                BitwiseOperation::CountLeadingZero
+                | BitwiseOperation::CountTrailingZero
+                | BitwiseOperation::CountNonZero,
+            ..
+        }
+        | SerializedInstruction::Float {
+            kind:
+                FloatOperation::AbsoluteValue
+                | FloatOperation::Negation
+                | FloatOperation::Ceiling
+                | FloatOperation::Floor
+                | FloatOperation::Truncate
+                | FloatOperation::Nearest
+                | FloatOperation::SquareRoot,
+            ..
+        }
+        | SerializedInstruction::Conversion(_) => Some(1),
+        SerializedInstruction::Comparison { .. }
+        | SerializedInstruction::Arithmetic { .. }
+        | SerializedInstruction::Bitwise { .. }
+        | SerializedInstruction::Float { .. } => Some(2),
+        _ => None,
+    }
+}
+
+/// Value computed by an instruction counted by [operand_count], with its operands in stack order
+pub(crate) fn evaluate(
+    instruction: &SerializedInstruction,
+    operands: &[Value],
+) -> WatResult<Value> {
+    match (instruction, operands) {
+        (SerializedInstruction::Comparison { kind, .. }, &[a]) => Ok(Value::I32(
+            (kind == &ComparisonOperation::EqualZero && a.is_zero()) as i32,
+        )),
+        (SerializedInstruction::Comparison { kind, .. }, &[a, b]) => {
+            Ok(Value::I32(compare(kind, a, b)? as i32))
+        }
+        (SerializedInstruction::Arithmetic { kind, .. }, &[a, b]) => arithmetic(kind, a, b),
+        (SerializedInstruction::Bitwise { kind, .. }, &[a]) => count_bits(kind, a),
+        (SerializedInstruction::Bitwise { kind, .. }, &[a, b]) => bitwise(kind, a, b),
+        (SerializedInstruction::Float { kind, .. }, &[a]) => float_unary(kind, a),
+        (SerializedInstruction::Float { kind, .. }, &[a, b]) => float_binary(kind, a, b),
+        (SerializedInstruction::Conversion(kind), &[a]) => convert(kind, a),
+        _ => Err(WatError::unimplemented_error(&format!(
+            "Cannot evaluate {instruction} from its operands."
+        ))),
+    }
+}
+
+/// Branch to the block resolved when the tree was built
+fn branch(frame: &mut Frame, target: &BranchTarget) -> WatResult<Flow> {
+    let depth = target.depth as usize;
+    // One past the innermost label is the function body itself
+    if depth == frame.labels.len() {
+        return Ok(Flow::Return);
+    }
+    let position =
+        frame
+            .labels
+            .len()
+            .checked_sub(depth + 1)
+            .ok_or(WatError::index_out_of_range_range(
+                frame.labels.len(),
+                depth,
+            ))?;
+    let label = frame.labels[position].clone();
+    if frame.stack.len() < label.height + label.arity {
+        return Err(WatError::not_enough_on_stack(
+            label.height + label.arity,
+            frame.stack.len(),
+        ));
+    }
+    let carried = frame.stack.split_off(frame.stack.len() - label.arity);
+    frame.stack.truncate(label.height);
+    frame.stack.extend(carried);
+    // Loops continue from the start, so the loop stays entered
+    let is_loop = matches!(label.kind, NodeMark::Loop);
+    frame.labels.truncate(position + usize::from(is_loop));
+    Ok(Flow::Jump(target.continuation))
+}
+
+fn mismatched(a: &Value, b: &Value) -> WatError {
+    WatError::unexpected_type(&a.typ(), &b.typ())
+}
+
+fn compare(kind: &ComparisonOperation, a: Value, b: Value) -> WatResult<bool> {
+    use ComparisonOperation::*;
+    macro_rules! int_compare {
+        ($a:expr, $b:expr, $unsigned:ty) => {
+            match kind {
+                EqualZero => $a == 0,
+                Equal => $a == $b,
+                NotEqual => $a != $b,
+                LessThenSigned => $a < $b,
+                LessThenUnsigned => ($a as $unsigned) < ($b as $unsigned),
+                GreaterThenSigned => $a > $b,
+                GreaterThenUnsigned => ($a as $unsigned) > ($b as $unsigned),
+                LessThenOrEqualToSigned => $a <= $b,
+                LessThenOrEqualToUnsigned => ($a as $unsigned) <= ($b as $unsigned),
+                GreaterThenOrEqualToSigned => $a >= $b,
+                GreaterThenOrEqualToUnsigned => ($a as $unsigned) >= ($b as $unsigned),
+            }
+        };
+    }
+    macro_rules! float_compare {
+        ($a:expr, $b:expr) => {
+            match kind {
+                EqualZero => $a == 0.0,
+                Equal => $a == $b,
+                NotEqual => $a != $b,
+                LessThenSigned | LessThenUnsigned => $a < $b,
+                GreaterThenSigned | GreaterThenUnsigned => $a > $b,
+                LessThenOrEqualToSigned | LessThenOrEqualToUnsigned => $a <= $b,
+                GreaterThenOrEqualToSigned | GreaterThenOrEqualToUnsigned => $a >= $b,
+            }
+        };
+    }
+    Ok(match (a, b) {
+        (Value::I32(a), Value::I32(b)) => int_compare!(a, b, u32),
+        (Value::I64(a), Value::I64(b)) => int_compare!(a, b, u64),
+        (Value::F32(a), Value::F32(b)) => float_compare!(a, b),
+        (Value::F64(a), Value::F64(b)) => float_compare!(a, b),
+        (a, b) => return Err(mismatched(&a, &b)),
+    })
+}
+
+fn arithmetic(kind: &ArithmeticOperation, a: Value, b: Value) -> WatResult<Value> {
+    use ArithmeticOperation::*;
+    macro_rules! int_arithmetic {
+        ($a:expr, $b:expr, $signed:ty, $unsigned:ty) => {
+            match kind {
+                Addition => $a.wrapping_add($b),
+                Subtraction => $a.wrapping_sub($b),
+                Multiplication => $a.wrapping_mul($b),
+                DivisonSigned | RemainderSigned if $b == 0 => {
+                    return Err(WatError::divide_by_zero_error())
+                }
+                DivisonUnsigned | RemainderUnsigned if $b == 0 => {
+                    return Err(WatError::divide_by_zero_error())
+                }
+                DivisonSigned if $a == <$signed>::MIN && $b == -1 => {
+                    return Err(WatError::integer_overflow_error())
+                }
+                DivisonSigned => $a / $b,
+                DivisonUnsigned => (($a as $unsigned) / ($b as $unsigned)) as $signed,
+                RemainderSigned => $a.wrapping_rem($b),
+                RemainderUnsigned => (($a as $unsigned) % ($b as $unsigned)) as $signed,
+            }
+        };
+    }
+    macro_rules! float_arithmetic {
+        ($a:expr, $b:expr) => {
+            match kind {
+                Addition => float::add($a, $b),
+                Subtraction => float::sub($a, $b),
+                Multiplication => float::mul($a, $b),
+                DivisonSigned | DivisonUnsigned => float::div($a, $b),
+                RemainderSigned | RemainderUnsigned => {
+                    return Err(WatError::unimplemented_error(
+                        "Floating point remainder is not an instruction.",
+                    ))
+                }
+            }
+        };
+    }
+    Ok(match (a, b) {
+        (Value::I32(a), Value::I32(b)) => Value::I32(int_arithmetic!(a, b, i32, u32)),
+        (Value::I64(a), Value::I64(b)) => Value::I64(int_arithmetic!(a, b, i64, u64)),
+        (Value::F32(a), Value::F32(b)) => Value::F32(float_arithmetic!(a, b)),
+        (Value::F64(a), Value::F64(b)) => Value::F64(float_arithmetic!(a, b)),
+        (a, b) => return Err(mismatched(&a, &b)),
+    })
+}
+
+fn count_bits(kind: &BitwiseOperation, a: Value) -> WatResult<Value> {
+    macro_rules! count {
+        ($a:expr) => {
+            match kind {
+                BitwiseOperation::CountLeadingZero => $a.leading_zeros(),
+                BitwiseOperation::CountTrailingZero => $a.trailing_zeros(),
+                _ => $a.count_ones(),
+            }
+        };
+    }
+    match a {
+        Value::I32(a) => Ok(Value::I32(count!(a) as i32)),
+        Value::I64(a) => Ok(Value::I64(count!(a) as i64)),
+        other => Err(WatError::unexpected_type(
+            &SerializableWatType::I32,
+            &other.typ(),
+        )),
+    }
+}
+
+fn bitwise(kind: &BitwiseOperation, a: Value, b: Value) -> WatResult<Value> {
+    use BitwiseOperation::*;
+    macro_rules! int_bitwise {
+        ($a:expr, $b:expr, $signed:ty, $unsigned:ty) => {
+            match kind {
+                And => $a & $b,
+                Or => $a | $b,
+                Xor => $a ^ $b,
+                ShiftLeft => $a.wrapping_shl($b as u32),
+                ShiftRightSigned => $a.wrapping_shr($b as u32),
+                ShiftRightUnsigned => ($a as $unsigned).wrapping_shr($b as u32) as $signed,
+                RotateLeft => $a.rotate_left($b as u32 % <$signed>::BITS),
+                RotateRight => $a.rotate_right($b as u32 % <$signed>::BITS),
+                CountLeadingZero | CountTrailingZero | CountNonZero => {
+                    return count_bits(kind, Value::from($a))
+                }
+            }
+        };
+    }
+    Ok(match (a, b) {
+        (Value::I32(a), Value::I32(b)) => Value::I32(int_bitwise!(a, b, i32, u32)),
+        (Value::I64(a), Value::I64(b)) => Value::I64(int_bitwise!(a, b, i64, u64)),
+        (a, b) => return Err(mismatched(&a, &b)),
+    })
+}
+
+fn float_unary(kind: &FloatOperation, a: Value) -> WatResult<Value> {
+    use FloatOperation::*;
+    macro_rules! unary {
+        ($a:expr) => {
+            match kind {
+                AbsoluteValue => $a.abs(),
+                Negation => -$a,
+                Ceiling => float::ceil($a),
+                Floor => float::floor($a),
+                Truncate => float::trunc($a),
+                Nearest => float::nearest($a),
+                SquareRoot => float::sqrt($a),
+                Minimum | Maximum | CopySign => {
+                    unreachable!("Binary float operations are handled by float_binary")
+                }
+            }
+        };
+    }
+    match a {
+        Value::F32(a) => Ok(Value::F32(unary!(a))),
+        Value::F64(a) => Ok(Value::F64(unary!(a))),
+        other => Err(WatError::unexpected_type(
+            &SerializableWatType::F32,
+            &other.typ(),
+        )),
+    }
+}
+
+fn float_binary(kind: &FloatOperation, a: Value, b: Value) -> WatResult<Value> {
+    macro_rules! binary {
+        ($a:expr, $b:expr) => {
+            match kind {
+                FloatOperation::Minimum => float::min($a, $b),
+                FloatOperation::Maximum => float::max($a, $b),
+                FloatOperation::CopySign => $a.copysign($b),
+                _ => unreachable!("Unary float operations are handled by float_unary"),
+            }
+        };
+    }
+    Ok(match (a, b) {
+        (Value::F32(a), Value::F32(b)) => Value::F32(binary!(a, b)),
+        (Value::F64(a), Value::F64(b)) => Value::F64(binary!(a, b)),
+        (a, b) => return Err(mismatched(&a, &b)),
+    })
+}
+
+/// Truncate a float towards zero, trapping if it does not fit in `[min, max)`
+fn truncate(value: f64, min: f64, max: f64) -> WatResult<f64> {
+    if value.is_nan() {
+        return Err(WatError::invalid_conversion_error());
+    }
+    let truncated = value.trunc();
+    if truncated < min || truncated >= max {
+        Err(WatError::integer_overflow_error())
+    } else {
+        Ok(truncated)
+    }
+}
+
+fn convert(kind: &NumericConversionKind, a: Value) -> WatResult<Value> {
+    use NumericConversionKind::*;
+    const I32_RANGE: (f64, f64) = (-2147483648.0, 2147483648.0);
+    const U32_RANGE: (f64, f64) = (-0.0, 4294967296.0);
+    const I64_RANGE: (f64, f64) = (-9223372036854775808.0, 9223372036854775808.0);
+    const U64_RANGE: (f64, f64) = (-0.0, 18446744073709551616.0);
+    let float = match a {
+        Value::F32(f) => f as f64,
+        Value::F64(f) => f,
+        _ => 0.0,
+    };
+    let expected = match kind {
+        WrapInt
+        | SignedConvertI64ToF32
+        | UnsignedConvertI64ToF32
+        | SignedConvertI64ToF64
+        | UnsignedConvertI64ToF64
+        | Reinterpret64IToF => SerializableWatType::I64,
+        SignedExtend
+        | UnsignedExtend
+        | SignedConvertI32ToF32
+        | UnsignedConvertI32ToF32
+        | SignedConvertI32ToF64
+        | UnsignedConvertI32ToF64
+        | Reinterpret32IToF => SerializableWatType::I32,
+        SignedTruncF32ToI32
+        | UnsignedTruncF32ToI32
+        | SignedTruncF32ToI64
+        | UnsignedTruncF32ToI64
+        | PromoteFloat
+        | Reinterpret32FToI => SerializableWatType::F32,
+        SignedTruncF64ToI32
+        | UnsignedTruncF64ToI32
+        | SignedTruncF64ToI64
+        | UnsignedTruncF64ToI64
+        | DemoteFloat
+        | Reinterpret64FToI => SerializableWatType::F64,
+    };
+    if a.typ() != expected {
+        return Err(WatError::unexpected_type(&expected, &a.typ()));
+    }
+    Ok(match (kind, a) {
+        (WrapInt, Value::I64(n)) => Value::I32(n as i32),
+        (SignedTruncF32ToI32 | SignedTruncF64ToI32, _) => {
+            Value::I32(truncate(float, I32_RANGE.0, I32_RANGE.1)? as i32)
+        }
+        (UnsignedTruncF32ToI32 | UnsignedTruncF64ToI32, _) => {
+            Value::I32(truncate(float, U32_RANGE.0, U32_RANGE.1)? as u32 as i32)
+        }
+        (SignedTruncF32ToI64 | SignedTruncF64ToI64, _) => {
+            Value::I64(truncate(float, I64_RANGE.0, I64_RANGE.1)? as i64)
+        }
+        (UnsignedTruncF32ToI64 | UnsignedTruncF64ToI64, _) => {
+            Value::I64(truncate(float, U64_RANGE.0, U64_RANGE.1)? as u64 as i64)
+        }
+        (SignedExtend, Value::I32(n)) => Value::I64(n as i64),
+        (UnsignedExtend, Value::I32(n)) => Value::I64(n as u32 as i64),
+        (SignedConvertI32ToF32, Value::I32(n)) => Value::F32(n as f32),
+        (UnsignedConvertI32ToF32, Value::I32(n)) => Value::F32(n as u32 as f32),
+        (SignedConvertI64ToF32, Value::I64(n)) => Value::F32(n as f32),
+        (UnsignedConvertI64ToF32, Value::I64(n)) => Value::F32(n as u64 as f32),
+        (SignedConvertI32ToF64, Value::I32(n)) => Value::F64(n as f64),
+        (UnsignedConvertI32ToF64, Value::I32(n)) => Value::F64(n as u32 as f64),
+        (SignedConvertI64ToF64, Value::I64(n)) => Value::F64(n as f64),
+        (UnsignedConvertI64ToF64, Value::I64(n)) => Value::F64(n as u64 as f64),
+        (DemoteFloat, Value::F64(f)) => Value::F32(float::demote(f)),
+        (PromoteFloat, Value::F32(f)) => Value::F64(float::promote(f)),
+        (Reinterpret32FToI, Value::F32(f)) => Value::I32(f.to_bits() as i32),
+        (Reinterpret32IToF, Value::I32(n)) => Value::F32(f32::from_bits(n as u32)),
+        (Reinterpret64FToI, Value::F64(f)) => Value::I64(f.to_bits() as i64),
+        (Reinterpret64IToF, Value::I64(n)) => Value::F64(f64::from_bits(n as u64)),
+        (_, other) => return Err(WatError::unexpected_type(&expected, &other.typ())),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn returned(text: &str, name: &str, args: &[Value]) -> Vec<Value> {
+        let structure = crate::inner_transform(text).unwrap();
+        let mut machine = Machine::new(&structure).unwrap();
+        let function = machine.find_function(name).unwrap();
+        machine.invoke(function, args).unwrap()
+    }
+
+    #[test]
+    fn br_table_takes_the_chosen_label() {
+        let text = r#"(module (func (export "pick") (param i32) (result i32)
+            block $default
+                block $one
+                    block $zero
+                        local.get 0
+                        br_table $zero $one $default
+                    end
+                    i32.const 10
+                    return
+                end
+                i32.const 11
+                return
+            end
+            i32.const 12))"#;
+        for (arg, expected) in [(0, 10), (1, 11), (2, 12), (-1, 12)] {
+            assert_eq!(
+                returned(text, "pick", &[Value::I32(arg)]),
+                [Value::I32(expected)],
+                "{arg}"
+            );
+        }
+    }
+
+    #[test]
+    fn loops_and_calls_run_to_completion() {
+        let text = r#"(module
+            (func $double (param i32) (result i32) local.get 0 i32.const 2 i32.mul)
+            (func (export "power") (param $n i32) (result i32) (local $acc i32)
+                i32.const 1
+                local.set $acc
+                block $done
+                    loop $next
+                        local.get $n
+                        i32.eqz
+                        br_if $done
+                        local.get $acc
+                        call $double
+                        local.set $acc
+                        local.get $n
+                        i32.const 1
+                        i32.sub
+                        local.set $n
+                        br $next
+                    end
+                end
+                local.get $acc))"#;
+        assert_eq!(returned(text, "power", &[Value::I32(5)]), [Value::I32(32)]);
+    }
+
+    #[test]
+    fn traps_report_the_call_stack() {
+        let text = r#"(module
+            (func $inner unreachable)
+            (func (export "outer") nop call $inner))"#;
+        let Invocation::Trapped(trap) = invoke_function(text, "outer", &[]).unwrap() else {
+            panic!("unreachable traps");
+        };
+        assert_eq!(trap.kind, TrapKind::Unreachable);
+        let calls: Vec<_> = trap
+            .call_stack
+            .iter()
+            .map(|call| (call.function.as_str(), call.index))
+            .collect();
+        assert_eq!(calls, [("1", 1), ("inner", 0)]);
+    }
+
+    #[test]
+    fn instantiation_writes_data_then_runs_start() {
+        let report = instantiate_module(
+            r#"(module (memory 1)
+                (data $text (i32.const 4) "ab")
+                (start $init)
+                (func $init i32.const 4 i32.const 67 i32.store8))"#,
+        )
+        .unwrap();
+        assert_eq!(report.data[0].segment.id, "text");
+        assert_eq!(report.start.as_deref(), Some("init"));
+        assert_eq!(report.start_writes[0].old, b"a");
+        assert_eq!(report.start_writes[0].new.as_deref(), Some(&b"C"[..]));
+        assert_eq!(report.trap, None);
+
+        let report =
+            instantiate_module(r#"(module (memory 1) (data (i32.const 65535) "ab"))"#).unwrap();
+        assert!(report.data.is_empty());
+        assert_eq!(report.trap.unwrap().kind, TrapKind::OutOfBoundsMemory);
+    }
+
+    #[test]
+    fn call_indirect_is_not_a_direct_call() {
+        // Without the type use, this used to run function 0 instead
+        let text = r#"(module
+            (func $first (result i32) i32.const 7)
+            (func (export "run") (result i32)
+                i32.const 0
+                call_indirect (result i32)))"#;
+        let Invocation::Trapped(trap) = invoke_function(text, "run", &[]).unwrap() else {
+            panic!("no table to call through");
+        };
+        assert_eq!(trap.kind, TrapKind::OutOfBoundsTable);
+    }
+
+    #[test]
+    fn trapping_step_is_traced() {
+        let structure = crate::inner_transform(
+            r#"(module (func (param i32) (result i32)
+                i32.const 1
+                local.get 0
+                i32.div_u))"#,
+        )
+        .unwrap();
+        let mut machine = Machine::new(&structure).unwrap().with_tracing();
+        let err = machine.invoke(0, &[Value::I32(0)]).unwrap_err();
+        assert_eq!(err.trap(), Some(TrapKind::DivideByZero));
+        let last = machine.trace().last().unwrap();
+        assert_eq!(last.index, 2);
+        assert_eq!(last.trap, Some(TrapKind::DivideByZero));
+        assert_eq!(machine.trap().unwrap().call_stack[0].function, "0");
+    }
+
+    #[test]
+    fn traces_end_with_the_step_that_traps() {
+        let text = r#"(module (func (export "f") (param $n i32) (result i32)
+            i32.const 10 local.get $n i32.div_u))"#;
+        let steps = trace_function(text, "f", &["0".to_string()], &["$n".to_string()]).unwrap();
+        assert_eq!(steps.len(), 3);
+        assert_eq!(
+            steps[2].stack_before,
+            [SerializedNumber::from(10), SerializedNumber::from(0)]
+        );
+        assert_eq!(steps[2].trap, Some(TrapKind::DivideByZero));
+        assert_eq!(steps[0].watches, [WatchValue::Number(0.into())]);
+        // Wrong arguments and watches are errors, not traces
+        assert!(trace_function(text, "f", &[], &[]).is_err());
+        assert!(trace_function(text, "f", &["0".to_string()], &["i32[".to_string()]).is_err());
+    }
+
+    #[test]
+    fn traced_steps_log_their_memory_accesses() {
+        let text = r#"(module (memory 1) (data (i32.const 8) "\01\02")
+            (func (export "swap") (result i32)
+                i32.const 8 i32.const 7 i32.store16
+                i32.const 8 i32.load8_u))"#;
+        let steps = trace_function(text, "swap", &[], &[]).unwrap();
+        assert_eq!(
+            steps[2].memory,
+            [MemoryAccess {
+                memory: 0,
+                address: 8,
+                old: vec![1, 2],
+                new: Some(vec![7, 0]),
+            }]
+        );
+        assert_eq!(
+            steps[4].memory,
+            [MemoryAccess {
+                memory: 0,
+                address: 8,
+                old: vec![7],
+                new: None,
+            }]
+        );
+        assert!(steps[0].memory.is_empty());
+    }
+
+    #[test]
+    fn reference_globals_can_be_read_and_set() {
+        let text = r#"(module
+            (global $callback (mut funcref) (ref.null func))
+            (global $target funcref (ref.func $target))
+            (func $target)
+            (func (export "register") (result funcref)
+                global.get $target
+                global.set $callback
+                global.get $callback))"#;
+        assert_eq!(returned(text, "register", &[]), [Value::FuncRef(Some(0))]);
+
+        let typ = SerializableWatType::FuncRef;
+        assert_eq!(Value::parse(&typ, "null").unwrap(), Value::FuncRef(None));
+        assert_eq!(Value::parse(&typ, "2").unwrap(), Value::FuncRef(Some(2)));
+        assert!(Value::parse(&typ, "f").is_err());
+        assert_eq!(Value::default_of(&typ).unwrap().to_string(), "null");
+    }
+}
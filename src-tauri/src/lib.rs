@@ -0,0 +1,877 @@
+//! Core of WASVD: converts WAT text into an [InterpreterStructure],
+//! validates it, and executes it.
+//!
+//! Shared by the Tauri app and the `wasvd` command line tool.
+
+use helper::SerializedNumber;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use std::collections::HashMap;
+use wast::{
+    self,
+    core::{DataVal, Expression, Func, Local, ModuleField},
+    parser::{self, ParseBuffer},
+    token::Id,
+    Wat,
+};
+
+pub mod callgraph;
+pub mod cfg;
+pub mod completion;
+pub mod cost;
+pub mod dead_code;
+pub mod diagnostic;
+pub mod error;
+pub mod exports;
+pub mod float;
+pub mod helper;
+pub mod inspect;
+pub mod instruction;
+pub mod interpreter;
+pub mod lint;
+pub mod marker;
+pub mod memory;
+pub mod optimize;
+pub mod provenance;
+pub mod ranges;
+pub mod rename;
+pub mod source;
+pub mod symbolic;
+pub mod validator;
+pub mod watch;
+
+#[cfg(test)]
+mod test_util;
+
+use error::{WatError, WatResult};
+use instruction::{index_to_string, InputOutput, SerializedInstruction, SerializedInstructionTree};
+use source::SourceMap;
+use validator::{StackTypes, Validator};
+
+use marker::SerializableWatType;
+
+/// A basic Wa(s)t Function
+///
+/// ## Note:
+/// Does not work with imported functions, as it assumes nothing about other modules
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct WastFunc {
+    info: instruction::InputOutput,
+    locals: Vec<(Option<String>, SerializableWatType)>,
+    block: SerializedInstructionTree,
+}
+
+impl WastFunc {
+    pub fn try_new(
+        info: instruction::InputOutput,
+        locals: &[Local],
+        expression: &Expression,
+    ) -> WatResult<Self> {
+        let locals = locals
+            .iter()
+            .map(|l| match SerializableWatType::try_from(l.ty) {
+                Ok(ty) => Ok((l.id.map(|i| i.name().to_string()), ty)),
+                Err(err) => Err(err),
+            })
+            .collect::<Result<Vec<_>, error::WatError>>()?;
+        let func_name = info.index.clone().unwrap_or_default();
+        Ok(WastFunc {
+            info,
+            locals,
+            block: SerializedInstructionTree::try_from_instruction(&func_name, &expression.instrs)?,
+        })
+    }
+
+    pub fn set_name_from_number(&mut self, index: usize) {
+        self.info.index = Some(index.to_string());
+    }
+
+    pub fn name(&self) -> Option<String> {
+        self.info.index.clone()
+    }
+
+    pub fn instructions(&self) -> &[SerializedInstruction] {
+        &self.block.array
+    }
+
+    pub fn tree(&self) -> &SerializedInstructionTree {
+        &self.block
+    }
+}
+
+impl TryFrom<&Func<'_>> for WastFunc {
+    type Error = error::WatError;
+
+    fn try_from(value: &Func<'_>) -> Result<Self, Self::Error> {
+        // dbg!(value.id);
+        // dbg!(value.ty.index);
+        let mut info = InputOutput::try_from(&value.ty)?;
+        if let Some(id) = value.id {
+            info.set_name_if_none(id.name());
+        }
+
+        match &value.kind {
+            wast::core::FuncKind::Import(_) => Err(error::WatError::unimplemented_error(
+                "Import functions are not supported yet.",
+            )),
+            wast::core::FuncKind::Inline { locals, expression } => {
+                WastFunc::try_new(info, locals, expression)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Type, derive_more::Display)]
+pub enum NumLocationKind {
+    Function,
+    Global,
+    Memory,
+    Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct GlobalData {
+    name: String,
+    typ: SerializableWatType,
+    is_mutable: bool,
+    val: SerializedNumber,
+}
+
+/// Evaluate a constant expression, as used to initialize globals and place data segments
+///
+/// Besides `*.const`, it can read an earlier immutable global with `global.get`,
+/// make references with `ref.null` and `ref.func`, and use `i32` or `i64` `add`, `sub` and `mul`
+/// from the extended constant expressions proposal.
+/// `functions` are the names of every function in the module, for `ref.func` to point to.
+pub fn const_eval_expr(
+    instrs: &[wast::core::Instruction],
+    expected_type: SerializableWatType,
+    globals: &[GlobalData],
+    functions: &[String],
+) -> WatResult<SerializedNumber> {
+    use wast::core::{HeapType, Instruction};
+    let mut stack = Vec::new();
+    for instr in instrs {
+        match instr {
+            Instruction::RefNull(HeapType::Func) => stack.push(interpreter::Value::FuncRef(None)),
+            Instruction::RefNull(HeapType::Extern) => {
+                stack.push(interpreter::Value::ExternRef(None))
+            }
+            Instruction::RefNull(_) => Err(WatError::unimplemented_error(
+                "Only funcref and externref references are supported.",
+            ))?,
+            Instruction::RefFunc(index) => {
+                let location = index_to_string(index);
+                let index = resolve_index(functions, &location).ok_or(
+                    WatError::name_resolution_error(&location, NumLocationKind::Function),
+                )?;
+                stack.push(interpreter::Value::FuncRef(Some(index as u32)));
+            }
+            _ => match SerializedInstruction::try_from(instr)? {
+                SerializedInstruction::Const { value, .. } => stack.push(value.into()),
+                SerializedInstruction::Data {
+                    kind: marker::DataInstruction::GetGlobal,
+                    location,
+                } => {
+                    let names: Vec<_> = globals.iter().map(|g| g.name.clone()).collect();
+                    // Only globals declared before this one are in `globals`
+                    let global = &globals[resolve_index(&names, &location).ok_or(
+                        WatError::name_resolution_error(&location, NumLocationKind::Global),
+                    )?];
+                    if global.is_mutable {
+                        return Err(WatError::mutable_global_in_constant_error(&location));
+                    }
+                    stack.push(global.val.into());
+                }
+                instruction @ SerializedInstruction::Arithmetic {
+                    kind:
+                        marker::ArithmeticOperation::Addition
+                        | marker::ArithmeticOperation::Subtraction
+                        | marker::ArithmeticOperation::Multiplication,
+                    typ: typ @ (SerializableWatType::I32 | SerializableWatType::I64),
+                } => {
+                    let right = pop_const(&mut stack, typ)?;
+                    let left = pop_const(&mut stack, typ)?;
+                    stack.push(interpreter::evaluate(&instruction, &[left, right])?);
+                }
+                _ => return Err(WatError::non_initializer_expression()),
+            },
+        }
+    }
+    let value = match stack.len() {
+        0 => return Err(WatError::no_instruction_provided("Const")),
+        1 => pop_const(&mut stack, expected_type)?,
+        _ => return Err(WatError::non_initializer_expression()),
+    };
+    Ok(value.into())
+}
+
+/// Pop a value of the given type from a constant expression's stack
+fn pop_const(
+    stack: &mut Vec<interpreter::Value>,
+    expected: SerializableWatType,
+) -> WatResult<interpreter::Value> {
+    let value = stack.pop().ok_or(WatError::empty_stack(1))?;
+    expected.try_type_match(&value.typ())?;
+    Ok(value)
+}
+
+impl GlobalData {
+    /// Global initialized by a constant expression, which can read the `globals` declared before it
+    /// and point to any of the `functions`
+    pub fn try_new(
+        name: String,
+        gtyp: SerializableWatType,
+        is_mutable: bool,
+        instructions: &[wast::core::Instruction],
+        globals: &[GlobalData],
+        functions: &[String],
+    ) -> WatResult<Self> {
+        Ok(Self {
+            name,
+            typ: gtyp,
+            is_mutable,
+            val: const_eval_expr(instructions, gtyp, globals, functions)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct DataValue {
+    id: String,
+    is_string: bool,
+    data: Vec<u8>,
+}
+
+impl From<DataVal<'_>> for DataValue {
+    fn from(value: DataVal) -> Self {
+        match value {
+            DataVal::String(s) => Self {
+                id: String::default(),
+                is_string: true,
+                data: s.to_vec(),
+            },
+            DataVal::Integral(i) => Self {
+                id: String::default(),
+                is_string: false,
+                data: i,
+            },
+        }
+    }
+}
+
+impl DataValue {
+    pub fn clone_from(value: &DataVal) -> Self {
+        match value {
+            DataVal::String(s) => Self {
+                id: String::default(),
+                is_string: true,
+                data: s.to_vec(),
+            },
+            DataVal::Integral(i) => Self {
+                id: String::default(),
+                is_string: false,
+                data: i.to_vec(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct MemoryData {
+    name: String,
+    min: SerializedNumber,
+    max: SerializedNumber,
+    is_32: bool,
+    is_shared: bool,
+    data: HashMap<u32, DataValue>,
+}
+
+impl MemoryData {
+    pub fn new(
+        name: String,
+        min: i64,
+        max: Option<i64>,
+        is_32: bool,
+        is_shared: bool,
+        data: HashMap<u32, DataValue>,
+    ) -> Self {
+        let min = min.into();
+        let max = max.into();
+        Self {
+            name,
+            min,
+            max,
+            is_32,
+            is_shared,
+            data,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct InterpreterStructure {
+    pub(crate) name: String,
+    pub(crate) exported: HashMap<String, (NumLocationKind, u32)>,
+    pub(crate) globals: Vec<GlobalData>,
+    pub(crate) memory: Vec<MemoryData>,
+    // Data not currently bound to a specific memory location
+    pub(crate) free_data: Vec<DataValue>,
+    pub(crate) func: Vec<WastFunc>,
+    /// Optional start function for initalization
+    pub(crate) start: Option<String>,
+}
+
+/// Position of an item by index or name, in a list of names where unnamed items are empty
+fn resolve_index(names: &[String], location: &str) -> Option<usize> {
+    match validator::try_name_to_index(location) {
+        Ok(index) => (index < names.len()).then_some(index),
+        Err(name) => names.iter().position(|n| !n.is_empty() && n == name),
+    }
+}
+
+impl InterpreterStructure {
+    const PAGE_SIZE_AS_BYTES: u32 = 65536;
+
+    /// Try to create a new interpreter structure, without validating it
+    pub fn try_new(_text: &str, fields: &[ModuleField], name: &Option<Id>) -> WatResult<Self> {
+        let mut exported: HashMap<String, (NumLocationKind, u32)> = HashMap::new();
+        let mut globals: Vec<GlobalData> = Vec::new();
+        let mut memory: Vec<MemoryData> = Vec::new();
+        let mut free_data: Vec<DataValue> = Vec::new();
+        let mut func: Vec<WastFunc> = Vec::new();
+        let mut start = None;
+        // Globals can point to functions declared after them with `ref.func`
+        let function_names: Vec<String> = fields
+            .iter()
+            .filter_map(|field| match field {
+                ModuleField::Func(f) => {
+                    Some(f.id.map(|id| id.name().to_string()).unwrap_or_default())
+                }
+                _ => None,
+            })
+            .collect();
+        for field in fields.iter() {
+            match field {
+                ModuleField::Import(_) => Err(WatError::unimplemented_error(
+                    "Imports are not supported currently.",
+                ))?,
+                // Resolved once everything they refer to is declared
+                ModuleField::Export(_) | ModuleField::Start(_) | ModuleField::Data(_) => {}
+                ModuleField::Global(g) => {
+                    for name in &g.exports.names {
+                        exported
+                            .insert(
+                                name.to_string(),
+                                (NumLocationKind::Global, globals.len() as u32),
+                            )
+                            .map_or(Ok(()), |_| Err(WatError::duplicate_name_error(name)))?;
+                    }
+                    match &g.kind {
+                        wast::core::GlobalKind::Import(_) => {
+                            Err(error::WatError::unimplemented_error(
+                                "Imported globals not yet implemented.",
+                            ))?
+                        }
+                        wast::core::GlobalKind::Inline(e) => {
+                            globals.push(GlobalData::try_new(
+                                g.id.map(|id| id.name().to_string()).unwrap_or_default(),
+                                g.ty.ty.try_into()?,
+                                g.ty.mutable,
+                                &e.instrs,
+                                &globals,
+                                &function_names,
+                            )?);
+                        }
+                    }
+                }
+                ModuleField::Func(f) => {
+                    for name in &f.exports.names {
+                        exported
+                            .insert(
+                                name.to_string(),
+                                (NumLocationKind::Function, func.len() as u32),
+                            )
+                            .map_or(Ok(()), |_| Err(WatError::duplicate_name_error(name)))?;
+                    }
+                    let mut function = WastFunc::try_from(f)?;
+                    if function.name().is_none() {
+                        function.set_name_from_number(func.len())
+                    };
+                    func.push(function);
+                }
+                ModuleField::Memory(m) => {
+                    let mem_name = m.id.map(|id| id.name().to_string()).unwrap_or_default();
+                    for name in &m.exports.names {
+                        exported
+                            .insert(
+                                name.to_string(),
+                                (NumLocationKind::Memory, memory.len() as u32),
+                            )
+                            .map_or(Ok(()), |_| Err(WatError::duplicate_name_error(name)))?;
+                    }
+                    match &m.kind {
+                        wast::core::MemoryKind::Import { import: _, ty: _ } => {
+                            Err(error::WatError::unimplemented_error(
+                                "Imported memory not yet implemented.",
+                            ))?
+                        }
+                        wast::core::MemoryKind::Normal(mt) => match mt {
+                            wast::core::MemoryType::B32 { limits, shared } => {
+                                memory.push(MemoryData::new(
+                                    mem_name,
+                                    limits.min as i64,
+                                    limits.max.map(|n| n as i64),
+                                    true,
+                                    *shared,
+                                    HashMap::new(),
+                                ));
+                            }
+                            wast::core::MemoryType::B64 { limits, shared } => {
+                                memory.push(MemoryData::new(
+                                    mem_name,
+                                    limits.min as i64,
+                                    limits.max.map(|i| i as i64),
+                                    false,
+                                    *shared,
+                                    HashMap::new(),
+                                ));
+                            }
+                        },
+                        wast::core::MemoryKind::Inline { is_32, data } => {
+                            // Size calculated from data
+                            let (final_offset, data) = data
+                                .iter()
+                                .map(|val| DataValue::clone_from(val))
+                                .fold((0_u32, HashMap::new()), |(offset, mut map), val| {
+                                    let next_offset = offset + val.data.len() as u32;
+                                    map.insert(offset, val);
+                                    // Next offset = prev offset + len of curr val
+                                    (next_offset, map)
+                                });
+                            // Mem size gives exact size by page size, rounded up
+                            let mem_size = final_offset / Self::PAGE_SIZE_AS_BYTES
+                                + if final_offset % Self::PAGE_SIZE_AS_BYTES != 0 {
+                                    1
+                                } else {
+                                    0
+                                };
+                            memory.push(MemoryData::new(
+                                mem_name,
+                                mem_size as i64,
+                                Some(mem_size as i64),
+                                *is_32,
+                                false,
+                                data,
+                            ));
+                        }
+                    }
+                }
+                ModuleField::Type(_) | ModuleField::Rec(_) => Err(WatError::unimplemented_error(
+                    "Type definitions are not supported currently.",
+                ))?,
+                ModuleField::Table(_) | ModuleField::Elem(_) => Err(
+                    WatError::unimplemented_error("Tables are not supported currently."),
+                )?,
+                ModuleField::Tag(_) => Err(WatError::unimplemented_error(
+                    "Tags are not supported currently.",
+                ))?,
+                ModuleField::Custom(_) => Err(WatError::unimplemented_error(
+                    "Custom sections are not supported currently.",
+                ))?,
+            }
+        }
+        // Every function, global and memory is declared, so references to them can be resolved
+        let function_names: Vec<_> = func.iter().map(|f| f.name().unwrap_or_default()).collect();
+        let memory_names: Vec<_> = memory.iter().map(|m| m.name.clone()).collect();
+        let global_names: Vec<_> = globals.iter().map(|g| g.name.clone()).collect();
+        for field in fields.iter() {
+            match field {
+                ModuleField::Export(e) => {
+                    let item = index_to_string(&e.item);
+                    let (kind, names) = match e.kind {
+                        wast::core::ExportKind::Func => {
+                            (NumLocationKind::Function, &function_names)
+                        }
+                        wast::core::ExportKind::Memory => (NumLocationKind::Memory, &memory_names),
+                        wast::core::ExportKind::Global => (NumLocationKind::Global, &global_names),
+                        wast::core::ExportKind::Table | wast::core::ExportKind::Tag => {
+                            Err(WatError::unimplemented_error(
+                                "Only functions, memories and globals can be exported currently.",
+                            ))?
+                        }
+                    };
+                    let index = resolve_index(names, &item)
+                        .ok_or(WatError::name_resolution_error(&item, kind))?;
+                    exported
+                        .insert(e.name.to_string(), (kind, index as u32))
+                        .map_or(Ok(()), |_| Err(WatError::duplicate_name_error(e.name)))?;
+                }
+                ModuleField::Start(s) => {
+                    // Parsing gaurentees only one start
+                    let name = index_to_string(s);
+                    resolve_index(&function_names, &name).ok_or(
+                        WatError::name_resolution_error(&name, NumLocationKind::Function),
+                    )?;
+                    start = Some(name);
+                }
+                ModuleField::Data(d) => {
+                    // d.data
+                    // d.id
+                    let id = d.id.map_or(String::default(), |id| id.name().to_string());
+                    let data = d
+                        .data
+                        .iter()
+                        .flat_map(|val| match val {
+                            DataVal::String(s) => s.to_vec(),
+                            DataVal::Integral(i) => i.to_vec(),
+                        })
+                        .collect();
+                    match &d.kind {
+                        // Passive = exist but not yet loaded to memory -> put in free data
+                        wast::core::DataKind::Passive => free_data.push(DataValue {
+                            id,
+                            is_string: d.data.iter().all(|v| matches!(v, DataVal::String(_))),
+                            data,
+                        }),
+                        // Active = load directly to memory -> put in memory
+                        wast::core::DataKind::Active {
+                            memory: idx,
+                            offset,
+                        } => {
+                            let mem_name = index_to_string(idx);
+                            if let Some(index) = resolve_index(&memory_names, &mem_name) {
+                                let mem = &mut memory[index];
+                                let address_type = if mem.is_32 {
+                                    SerializableWatType::I32
+                                } else {
+                                    SerializableWatType::I64
+                                };
+                                let start = const_eval_expr(
+                                    &offset.instrs,
+                                    address_type,
+                                    &globals,
+                                    &function_names,
+                                )?;
+                                mem.data.insert(
+                                    u32::try_from(start.to_bits())
+                                        .map_err(|_| WatError::number_to_large(&start))?,
+                                    DataValue {
+                                        id,
+                                        is_string: d
+                                            .data
+                                            .iter()
+                                            .all(|dv| matches!(dv, DataVal::String(_))),
+                                        data,
+                                    },
+                                );
+                            } else {
+                                Err(WatError::name_resolution_error(
+                                    &mem_name,
+                                    NumLocationKind::Memory,
+                                ))?
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(InterpreterStructure {
+            name: name.map(|id| id.name().to_string()).unwrap_or_default(),
+            exported,
+            globals,
+            memory,
+            free_data,
+            func,
+            start,
+        })
+    }
+
+    pub fn functions(&self) -> &[WastFunc] {
+        &self.func
+    }
+
+    /// Index of a memory given by index or name, with or without its `$`
+    pub(crate) fn memory_index(&self, location: &str) -> Option<usize> {
+        match validator::try_name_to_index(location.strip_prefix('$').unwrap_or(location)) {
+            Ok(index) => (index < self.memory.len()).then_some(index),
+            Err(name) => self.memory.iter().position(|m| m.name == name),
+        }
+    }
+
+    /// Bytes in a memory, by index or name, when the module starts
+    pub(crate) fn initial_memory_size(&self, location: &str) -> Option<u64> {
+        let memory = &self.memory[self.memory_index(location)?];
+        Some(memory.min.to_bits() * Self::PAGE_SIZE_AS_BYTES as u64)
+    }
+
+    /// Types on the stack at each instruction of every function,
+    /// keeps going after a function fails to validate
+    pub fn stack_types(&self) -> Vec<StackTypes> {
+        let mut validator = Validator::new(self);
+        self.func
+            .iter()
+            .map(|func| {
+                validator.infer_stack_types(
+                    &func.block.array,
+                    &func.info.input,
+                    &func.locals,
+                    &func.info.output,
+                )
+            })
+            .collect()
+    }
+
+    /// Validate that the structure is correct, check all types match, and stack flow is correct.
+    ///
+    /// Errors point to the failing instruction if the [SourceMap] of the text is given.
+    pub fn validate(&self, source: Option<&SourceMap>) -> WatResult<()> {
+        let mut validator = Validator::new(self);
+        for (i, func) in self.func.iter().enumerate() {
+            let stack_types = validator.infer_stack_types(
+                &func.block.array,
+                &func.info.input,
+                &func.locals,
+                &func.info.output,
+            );
+            if let Some((index, err)) = stack_types.error {
+                return Err(match source.and_then(|s| s.functions.get(i)) {
+                    // Falls back to the closing parenthesis when the end of the function is wrong
+                    Some(f) => err.or_span(
+                        f.instructions
+                            .get(index)
+                            .cloned()
+                            .unwrap_or(f.span.end.saturating_sub(1)..f.span.end),
+                    ),
+                    None => err,
+                });
+            }
+        }
+        Ok(())
+
+        // // TODO: Remove the need for .clone()
+        // // Functions with parameter and result types
+        // let funcs: HashMap<_, _> = self
+        //     .func
+        //     .iter()
+        //     .enumerate()
+        //     .flat_map(|(i, f)| {
+        //         let params: Vec<_> = f.info.input.iter().map(|(_, t)| *t).collect();
+        //         let results = &f.info.output;
+        //         if let Some(name) = f.name() {
+        //             [
+        //                 (i.to_string(), (params.clone(), results.clone())),
+        //                 (name, (params, results.clone())),
+        //             ]
+        //         } else {
+        //             [
+        //                 (i.to_string(), (params.clone(), results.clone())),
+        //                 (i.to_string(), (params, results.clone())),
+        //             ]
+        //         }
+        //     })
+        //     .collect();
+        // for func in &self.func {
+        //     let mut validator = Validator::new(
+        //         self.globals
+        //             .iter()
+        //             .enumerate()
+        //             .flat_map(|(i, g)| {
+        //                 [
+        //                     (i.to_string(), (g.is_mutable, g.typ)),
+        //                     (g.name.clone(), (g.is_mutable, g.typ)),
+        //                 ]
+        //             })
+        //             .collect(),
+        //         func.info
+        //             .input
+        //             .iter()
+        //             .chain(func.locals.iter())
+        //             .enumerate()
+        //             .flat_map(|(i, l)| {
+        //                 if let Some(name) = l.0.clone() {
+        //                     [(i.to_string(), l.1), (name, l.1)]
+        //                 } else {
+        //                     [(i.to_string(), l.1), (i.to_string(), l.1)]
+        //                 }
+        //             })
+        //             .collect(),
+        //         funcs.clone(),
+        //         self.memory.iter().map(|m| m.name.clone()).collect(),
+        //         func.info.output.clone(),
+        //     );
+        //     dbg!(&func.block);
+        //     // for instruction in func.block.get_root() {
+        //     //     validator.process(instruction)?;
+        //     // }
+        // }
+        // Ok(())
+    }
+}
+
+/// Primary transformation function
+pub fn inner_transform(text: &str) -> error::WatResult<InterpreterStructure> {
+    let structure = build_structure(text)?;
+    structure.validate(SourceMap::try_new(text).ok().as_ref())?;
+    Ok(structure)
+}
+
+/// Parse and convert the text into an [InterpreterStructure], without validating the functions.
+///
+/// Useful for tools that still want to look at a module with type errors.
+pub fn build_structure(text: &str) -> error::WatResult<InterpreterStructure> {
+    // Note: New only builds the buffer and is currently infallible
+    let buffer = ParseBuffer::new(text).map_err(WatError::parsing_error)?;
+    // Combined lexing and parsing step
+    let mut module = match parser::parse::<Wat>(&buffer).map_err(WatError::parsing_error)? {
+        Wat::Module(m) => m,
+        Wat::Component(_) => {
+            return Err(error::WatError::unimplemented_error(
+                "Cannot compile components currently.",
+            ));
+        }
+    };
+    // dbg!(&module);
+    let final_result = match module.kind {
+        wast::core::ModuleKind::Text(ref fields) => {
+            InterpreterStructure::try_new(text, fields, &module.id)
+        }
+        wast::core::ModuleKind::Binary(_) => Err(error::WatError::unimplemented_error(
+            "Unimplemented Error: Cannot binary type currently.",
+        )),
+    }?;
+    // Resolve and immediately throw-away
+    let _ = module.resolve().map_err(WatError::resolution_error)?;
+    // Print for debug purposes, it does change module, so need to resolve name separately.
+    // println!("{}", wasmprinter::print_bytes(module.encode().unwrap()).unwrap());
+    Ok(final_result)
+}
+
+/// Result of a command that always succeeds, so the TypeScript side knows about [WatError]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub enum CommandResult<T> {
+    Ok(T),
+    Err(WatError),
+}
+
+impl<T> From<error::WatResult<T>> for CommandResult<T> {
+    fn from(value: error::WatResult<T>) -> Self {
+        match value {
+            Ok(val) => CommandResult::Ok(val),
+            Err(err) => CommandResult::Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_resolve_in_any_order() {
+        let structure = inner_transform(
+            r#"(module
+                (export "later" (func $later))
+                (export "by_index" (func 1))
+                (start $later)
+                (data (memory $m) (i32.const 4) "hi")
+                (func)
+                (func $later)
+                (memory $m 1))"#,
+        )
+        .unwrap();
+        assert_eq!(structure.exported["later"], (NumLocationKind::Function, 1));
+        assert_eq!(
+            structure.exported["by_index"],
+            (NumLocationKind::Function, 1)
+        );
+        assert_eq!(structure.start.as_deref(), Some("later"));
+        assert_eq!(structure.memory[0].data[&4].data, b"hi");
+    }
+
+    #[test]
+    fn extended_constant_expressions() {
+        let structure = inner_transform(
+            r#"(module
+                (global $base i32 (i32.const 16))
+                (global $end i32 (i32.add (global.get $base) (i32.mul (i32.const 4) (i32.const 3))))
+                (global $wide i64 (i64.sub (i64.const 0) (i64.const 1)))
+                (memory 1)
+                (data (i32.sub (global.get $end) (i32.const 2)) "x"))"#,
+        )
+        .unwrap();
+        assert_eq!(structure.globals[1].val, 28.into());
+        assert_eq!(structure.globals[2].val, (-1_i64).into());
+        assert!(structure.memory[0].data.contains_key(&26));
+
+        let invalid = [
+            "(global i32 (i64.const 1))",
+            "(global $g (mut i32) (i32.const 1)) (global i32 (global.get $g))",
+            "(global i32 (global.get $later)) (global $later i32 (i32.const 1))",
+            "(global i32 (i32.div_s (i32.const 1) (i32.const 1)))",
+            "(global i32 (i32.add (i32.const 1) (i64.const 1)))",
+            "(global i32 (i32.const 1) (i32.const 2))",
+            "(func $f) (global i32 (ref.func $f))",
+            "(memory 1) (data (i64.const 0) \"x\")",
+        ];
+        for fields in invalid {
+            assert!(
+                inner_transform(&format!("(module {fields})")).is_err(),
+                "{fields}"
+            );
+        }
+    }
+
+    #[test]
+    fn reference_globals_hold_null_or_a_function() {
+        let structure = inner_transform(
+            r#"(module
+                (global $none funcref (ref.null func))
+                (global $later funcref (ref.func $second))
+                (global $copy funcref (global.get $later))
+                (global $host (mut externref) (ref.null extern))
+                (func $first)
+                (func $second))"#,
+        )
+        .unwrap();
+        assert_eq!(structure.globals[0].val.to_reference(), None);
+        assert_eq!(
+            structure.globals[1].val,
+            SerializedNumber::reference(SerializableWatType::FuncRef, Some(1))
+        );
+        assert_eq!(structure.globals[2].val, structure.globals[1].val);
+        assert_eq!(structure.globals[3].typ, SerializableWatType::ExternRef);
+        assert_eq!(structure.globals[3].val.to_string(), "null");
+
+        let invalid = [
+            "(global funcref (ref.null extern))",
+            "(global externref (ref.func 0)) (func)",
+            "(global funcref (ref.func $missing))",
+            "(global funcref (i32.const 0))",
+        ];
+        for fields in invalid {
+            assert!(
+                inner_transform(&format!("(module {fields})")).is_err(),
+                "{fields}"
+            );
+        }
+    }
+
+    #[test]
+    fn unsupported_fields_are_errors() {
+        let unsupported = [
+            "(type $t (func))",
+            "(rec (type (func)))",
+            "(import \"env\" \"f\" (func))",
+            "(table 1 funcref)",
+            "(func $f) (table funcref (elem $f))",
+            "(tag)",
+            "(table $t 1 funcref) (export \"t\" (table $t))",
+        ];
+        for fields in unsupported {
+            let err = build_structure(&format!("(module {fields})")).unwrap_err();
+            assert_eq!(err.stage(), error::ErrorStage::Unimplemented, "{fields}");
+        }
+    }
+}
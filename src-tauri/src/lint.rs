@@ -0,0 +1,403 @@
+//! Style and correctness rules run over each function's [SerializedInstructionTree].
+//!
+//! Every rule can be turned off with [LintConfig], and most come with edits that fix the problem.
+
+use std::{fmt::Display, ops::Range};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    build_structure,
+    dead_code::local_index,
+    diagnostic::Diagnostic,
+    error::WatResult,
+    instruction::{SerializedInstruction, SerializedInstructionTree},
+    marker::{ComparisonOperation, DataInstruction, SerializableWatType, SimpleInstruction},
+    rename::{rename_at, TextEdit},
+    source::{is_id_char, FunctionSource, SourceMap},
+    InterpreterStructure, NumLocationKind, WastFunc,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum Rule {
+    /// `local.set` right before `local.get` of the same local
+    PreferTee,
+    /// `i32.const 0` right before `i32.eq`, and the same for `i64`
+    PreferEqz,
+    RedundantNop,
+    /// Exported function without a `$name`
+    UnnamedExport,
+    /// Block label that hides the label of a block around it
+    ShadowedLabel,
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Rule::PreferTee => "prefer-tee",
+            Rule::PreferEqz => "prefer-eqz",
+            Rule::RedundantNop => "redundant-nop",
+            Rule::UnnamedExport => "unnamed-export",
+            Rule::ShadowedLabel => "shadowed-label",
+        })
+    }
+}
+
+/// Which rules to run, all of them by default
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, Type)]
+pub struct LintConfig {
+    pub disabled: Vec<Rule>,
+}
+
+impl LintConfig {
+    pub fn is_enabled(&self, rule: Rule) -> bool {
+        !self.disabled.contains(&rule)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct Lint {
+    pub rule: Rule,
+    pub diagnostic: Diagnostic,
+    /// Edits that fix the problem, if it can be fixed without asking
+    pub fix: Option<Vec<TextEdit>>,
+}
+
+impl Lint {
+    fn new(rule: Rule, span: Range<u32>, message: String, fix: Option<Vec<TextEdit>>) -> Self {
+        Self {
+            rule,
+            diagnostic: Diagnostic::warning(span, format!("{message} ({rule})")),
+            fix,
+        }
+    }
+}
+
+/// What a rule looks at for one function
+struct Context<'a> {
+    text: &'a str,
+    func: &'a WastFunc,
+    tree: &'a SerializedInstructionTree,
+    source: &'a FunctionSource,
+}
+
+/// A rule checking one function at a time
+type FunctionRule = fn(&Context) -> Vec<Lint>;
+
+impl Context<'_> {
+    /// Replace the first word of the instruction, like `i32.eq`
+    fn replace_mnemonic(&self, index: usize, mnemonic: &str) -> Option<TextEdit> {
+        let start = self.source.instructions.get(index)?.start;
+        let written = self.source.mnemonic(self.text, index);
+        Some(TextEdit {
+            span: start..start + written.len() as u32,
+            text: mnemonic.to_string(),
+        })
+    }
+
+    /// Remove an instruction with the space before it,
+    /// along with its parentheses when it is folded without operands
+    fn remove(&self, index: usize) -> Option<TextEdit> {
+        let bytes = self.text.as_bytes();
+        let span = self.source.instructions.get(index)?;
+        let before = bytes[..span.start as usize]
+            .iter()
+            .rposition(|b| !b.is_ascii_whitespace());
+        let after = bytes[span.end as usize..]
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .map(|i| i + span.end as usize);
+        let (start, end) = match before.map(|i| bytes[i]) {
+            Some(b'(') => match after.map(|i| (i, bytes[i])) {
+                Some((close, b')')) => (before? as u32, close as u32 + 1),
+                // Other instructions are folded inside this one
+                _ => return None,
+            },
+            _ => (span.start, span.end),
+        };
+        let start = bytes[..start as usize]
+            .iter()
+            .rposition(|b| !b.is_ascii_whitespace())
+            .map_or(0, |i| i as u32 + 1);
+        Some(TextEdit {
+            span: start..end,
+            text: String::new(),
+        })
+    }
+
+    fn span(&self, index: usize) -> Range<u32> {
+        self.source
+            .instructions
+            .get(index)
+            .cloned()
+            .unwrap_or(self.source.keyword.clone())
+    }
+}
+
+fn prefer_tee(cx: &Context) -> Vec<Lint> {
+    let array = &cx.tree.array;
+    (0..array.len().saturating_sub(1))
+        .filter(|&i| match (&array[i], &array[i + 1]) {
+            (
+                SerializedInstruction::Data {
+                    kind: DataInstruction::SetLocal,
+                    location: set,
+                },
+                SerializedInstruction::Data {
+                    kind: DataInstruction::GetLocal,
+                    location: get,
+                },
+            ) => local_index(cx.func, set).is_some_and(|l| Some(l) == local_index(cx.func, get)),
+            _ => false,
+        })
+        .map(|i| {
+            let fix = [cx.replace_mnemonic(i, "local.tee"), cx.remove(i + 1)]
+                .into_iter()
+                .collect();
+            Lint::new(
+                Rule::PreferTee,
+                cx.span(i),
+                "`local.set` then `local.get` of the same local can be one `local.tee`".to_string(),
+                fix,
+            )
+        })
+        .collect()
+}
+
+fn prefer_eqz(cx: &Context) -> Vec<Lint> {
+    let array = &cx.tree.array;
+    (0..array.len().saturating_sub(1))
+        .filter_map(|i| match (&array[i], &array[i + 1]) {
+            (
+                SerializedInstruction::Const { typ, value },
+                SerializedInstruction::Comparison {
+                    kind: ComparisonOperation::Equal,
+                    typ: compared,
+                },
+            ) if typ == compared
+                && matches!(typ, SerializableWatType::I32 | SerializableWatType::I64)
+                && value.to_bits() == 0 =>
+            {
+                Some((i, typ.to_string().to_lowercase()))
+            }
+            _ => None,
+        })
+        .map(|(i, prefix)| {
+            let fix = [
+                cx.remove(i),
+                cx.replace_mnemonic(i + 1, &format!("{prefix}.eqz")),
+            ]
+            .into_iter()
+            .collect();
+            Lint::new(
+                Rule::PreferEqz,
+                // Folded comparisons are written before the constant
+                cx.span(i).start.min(cx.span(i + 1).start)..cx.span(i).end.max(cx.span(i + 1).end),
+                format!("Comparing with 0 can be `{prefix}.eqz`"),
+                fix,
+            )
+        })
+        .collect()
+}
+
+fn redundant_nop(cx: &Context) -> Vec<Lint> {
+    cx.tree
+        .array
+        .iter()
+        .enumerate()
+        .filter(|(_, instruction)| {
+            matches!(
+                instruction,
+                SerializedInstruction::Simple(SimpleInstruction::Nop)
+            )
+        })
+        .map(|(i, _)| {
+            Lint::new(
+                Rule::RedundantNop,
+                cx.span(i),
+                "`nop` does nothing".to_string(),
+                cx.remove(i).map(|edit| vec![edit]),
+            )
+        })
+        .collect()
+}
+
+fn shadowed_label(cx: &Context) -> Vec<Lint> {
+    let root = &cx.tree.root;
+    root.iter()
+        .skip(1)
+        .filter(|node| !node.label.is_empty())
+        .filter(|node| {
+            let mut parent = node.parent;
+            // The function body has the function's name, not a label
+            while parent != 0 {
+                if root[parent as usize].label == node.label {
+                    return true;
+                }
+                parent = root[parent as usize].parent;
+            }
+            false
+        })
+        .filter_map(|node| {
+            let def = cx.source.labels.get(&(node.start as usize))?;
+            // Rename to the first name that does not collide with anything
+            let fix = (2..100)
+                .find_map(|n| rename_at(cx.text, def.span.start, &format!("{}{n}", def.name)).ok());
+            Some(Lint::new(
+                Rule::ShadowedLabel,
+                def.span.clone(),
+                format!("Label ${} hides a label of an enclosing block", def.name),
+                fix,
+            ))
+        })
+        .collect()
+}
+
+fn unnamed_export(structure: &InterpreterStructure, source: &SourceMap) -> Vec<Lint> {
+    let mut exports: Vec<(&String, usize)> = structure
+        .exported
+        .iter()
+        .filter(|(_, (kind, _))| *kind == NumLocationKind::Function)
+        .map(|(name, (_, index))| (name, *index as usize))
+        .collect();
+    exports.sort();
+    let mut lints: Vec<Lint> = Vec::new();
+    for (name, index) in exports {
+        let Some(function) = source.functions.get(index) else {
+            continue;
+        };
+        if function.name.is_some() || lints.iter().any(|l| l.diagnostic.span == function.keyword) {
+            continue;
+        }
+        let is_taken = source
+            .functions
+            .iter()
+            .any(|f| f.name.as_ref().is_some_and(|def| &def.name == name));
+        let fix =
+            (!is_taken && !name.is_empty() && name.bytes().all(|b| is_id_char(&b))).then(|| {
+                vec![TextEdit {
+                    span: function.keyword.end..function.keyword.end,
+                    text: format!(" ${name}"),
+                }]
+            });
+        lints.push(Lint::new(
+            Rule::UnnamedExport,
+            function.keyword.clone(),
+            format!("Exported function \"{name}\" has no $name"),
+            fix,
+        ));
+    }
+    lints
+}
+
+/// Run the enabled rules over every function, giving lints in text order
+pub fn lint(text: &str, config: &LintConfig) -> WatResult<Vec<Lint>> {
+    let structure = build_structure(text)?;
+    let source = SourceMap::try_new(text)?;
+    let function_rules: [(Rule, FunctionRule); 4] = [
+        (Rule::PreferTee, prefer_tee),
+        (Rule::PreferEqz, prefer_eqz),
+        (Rule::RedundantNop, redundant_nop),
+        (Rule::ShadowedLabel, shadowed_label),
+    ];
+    let mut lints = Vec::new();
+    for (func, func_source) in structure.func.iter().zip(&source.functions) {
+        let cx = Context {
+            text,
+            func,
+            tree: func.tree(),
+            source: func_source,
+        };
+        for (rule, check) in function_rules {
+            if config.is_enabled(rule) {
+                lints.extend(check(&cx));
+            }
+        }
+    }
+    if config.is_enabled(Rule::UnnamedExport) {
+        lints.extend(unnamed_export(&structure, &source));
+    }
+    lints.sort_by_key(|lint| lint.diagnostic.span.start);
+    Ok(lints)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(text: &str, config: &LintConfig) -> Vec<Rule> {
+        lint(text, config)
+            .unwrap()
+            .into_iter()
+            .map(|lint| lint.rule)
+            .collect()
+    }
+
+    /// Text after applying the fix of the only lint
+    fn fixed(text: &str) -> String {
+        let mut lints = lint(text, &LintConfig::default()).unwrap();
+        assert_eq!(lints.len(), 1);
+        let mut edits = lints.remove(0).fix.unwrap();
+        edits.sort_by_key(|edit| std::cmp::Reverse(edit.span.start));
+        let mut text = text.to_string();
+        for edit in edits {
+            text.replace_range(edit.span.start as usize..edit.span.end as usize, &edit.text);
+        }
+        text
+    }
+
+    #[test]
+    fn rules_can_be_disabled() {
+        let text = "(module (func (export \"f\") nop))";
+        assert_eq!(
+            rules(text, &LintConfig::default()),
+            [Rule::UnnamedExport, Rule::RedundantNop]
+        );
+        let config = LintConfig {
+            disabled: vec![Rule::UnnamedExport],
+        };
+        assert_eq!(rules(text, &config), [Rule::RedundantNop]);
+    }
+
+    #[test]
+    fn fixes_rewrite_the_text() {
+        assert_eq!(
+            fixed("(module (func $f (local $x i32) i32.const 1 local.set $x local.get $x drop))"),
+            "(module (func $f (local $x i32) i32.const 1 local.tee $x drop))"
+        );
+        assert_eq!(
+            fixed(
+                "(module (func $f (param i32) (result i32) (i32.eq (local.get 0) (i32.const 0))))"
+            ),
+            "(module (func $f (param i32) (result i32) (i32.eqz (local.get 0))))"
+        );
+        assert_eq!(
+            fixed("(module (func $f (nop) i32.const 0 drop))"),
+            "(module (func $f i32.const 0 drop))"
+        );
+        assert_eq!(
+            fixed("(module (func (export \"run\")))"),
+            "(module (func $run (export \"run\")))"
+        );
+    }
+
+    #[test]
+    fn shadowed_labels_are_renamed() {
+        assert_eq!(
+            fixed("(module (func $f block $b block $b br $b end end))"),
+            "(module (func $f block $b block $b2 br $b2 end end))"
+        );
+    }
+
+    #[test]
+    fn exports_with_unusable_names_have_no_fix() {
+        let lints = lint(
+            "(module (func $run) (func (export \"run\")))",
+            &LintConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].fix, None);
+    }
+}
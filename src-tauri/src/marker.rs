@@ -12,7 +12,7 @@ use crate::error::{self, WatError, WatResult};
 /// All Wat types that can be (currently) serialized.
 ///
 /// ## Limitations
-/// All except [ValType::Ref] are supported, but must explicity convert.
+/// Of the reference types, only `funcref` and `externref` are supported, but must explicity convert.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type, derive_more::Display)]
 pub enum SerializableWatType {
     I32,
@@ -20,6 +20,8 @@ pub enum SerializableWatType {
     F32,
     F64,
     V128,
+    FuncRef,
+    ExternRef,
 }
 
 impl SerializableWatType {
@@ -37,14 +39,24 @@ impl<'a> TryFrom<wast::core::ValType<'a>> for SerializableWatType {
 
     /// Try to go from [ValType] to [SerializableWatType]
     fn try_from(value: wast::core::ValType) -> Result<Self, Self::Error> {
-        use wast::core::ValType;
+        use wast::core::{HeapType, RefType, ValType};
         match value {
             ValType::I32 => Ok(SerializableWatType::I32),
             ValType::I64 => Ok(SerializableWatType::I64),
             ValType::F32 => Ok(SerializableWatType::F32),
             ValType::F64 => Ok(SerializableWatType::F64),
             ValType::V128 => Ok(SerializableWatType::V128),
-            ValType::Ref(_) => Err(error::WatError::unimplemented_error("Cannot use Ref type")),
+            ValType::Ref(RefType {
+                nullable: true,
+                heap: HeapType::Func,
+            }) => Ok(SerializableWatType::FuncRef),
+            ValType::Ref(RefType {
+                nullable: true,
+                heap: HeapType::Extern,
+            }) => Ok(SerializableWatType::ExternRef),
+            ValType::Ref(_) => Err(error::WatError::unimplemented_error(
+                "Only funcref and externref references are supported.",
+            )),
         }
     }
 }
@@ -92,6 +104,16 @@ impl ByteKind {
         }
     }
 
+    /// Number of bytes read or written
+    pub fn byte_count(&self) -> u32 {
+        match self {
+            ByteKind::Bits8 => 1,
+            ByteKind::Bits16 => 2,
+            ByteKind::Bits32 => 4,
+            ByteKind::Bits64 => 8,
+        }
+    }
+
     /// Produce [ByteKind] from a number of byte
     pub fn from_byte_count(value: u32) -> Self {
         match value {
@@ -432,8 +454,8 @@ pub fn try_data_instruction_from(instruction: &Instruction) -> Option<DataInstru
         Instruction::LocalGet(_) => Some(DataInstruction::GetLocal),
         Instruction::LocalSet(_) => Some(DataInstruction::SetLocal),
         Instruction::LocalTee(_) => Some(DataInstruction::TeeLocal),
-        Instruction::GlobalGet(_) => Some(DataInstruction::SetGlobal),
-        Instruction::GlobalSet(_) => Some(DataInstruction::GetGlobal),
+        Instruction::GlobalGet(_) => Some(DataInstruction::GetGlobal),
+        Instruction::GlobalSet(_) => Some(DataInstruction::SetGlobal),
         Instruction::MemorySize(_) => Some(DataInstruction::GetMemorySize),
         Instruction::MemoryGrow(_) => Some(DataInstruction::SetMemorySize),
         _ => None,
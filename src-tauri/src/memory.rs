@@ -0,0 +1,457 @@
+//! Linear memory used while running a module.
+//!
+//! Memory is split into 64 KiB pages, and a page only takes space once something is written to it,
+//! so a large `min` costs nothing until it is used. Every read and write can be logged
+//! with the bytes before and after, for the UI to highlight.
+//!
+//! The inspector reads a memory after instantiation, once the start function has run,
+//! marking which bytes came from which data segment.
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    error::{WatError, WatResult},
+    helper::SerializedNumber,
+    interpreter::{Machine, Value, INVOKE_MAX_STEPS},
+    marker::ByteKind,
+    InterpreterStructure, MemoryData, NumLocationKind, SerializableWatType,
+};
+
+pub const PAGE_SIZE: u32 = InterpreterStructure::PAGE_SIZE_AS_BYTES;
+/// Most pages a 32-bit memory can have, 4 GiB in total
+const MAX_PAGES: u32 = 65536;
+
+/// One load or store, at the address after adding the offset
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct MemoryAccess {
+    /// Index of the memory in the module
+    pub memory: u32,
+    pub address: u32,
+    /// Bytes before the access, from the lowest address
+    pub old: Vec<u8>,
+    /// Bytes written, [None] for a load
+    pub new: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LinearMemory {
+    /// [None] for a page that has not been written to, so it is all zeros
+    pages: Vec<Option<Box<[u8]>>>,
+    max: Option<u32>,
+}
+
+impl LinearMemory {
+    pub fn new(min: u32, max: Option<u32>) -> Self {
+        Self {
+            pages: vec![None; min as usize],
+            max,
+        }
+    }
+
+    /// Empty memory with the limits the module declares
+    pub fn from_limits(memory: &MemoryData) -> WatResult<Self> {
+        let (min, max) = limits(memory)?;
+        Ok(Self::new(min, max))
+    }
+
+    /// Size in pages
+    pub fn size(&self) -> u32 {
+        self.pages.len() as u32
+    }
+
+    pub fn byte_size(&self) -> u64 {
+        self.pages.len() as u64 * PAGE_SIZE as u64
+    }
+
+    /// Add pages, returning the old size in pages, or [None] if the memory cannot get that large
+    pub fn grow(&mut self, delta: u32) -> Option<u32> {
+        let old = self.size();
+        let new = old.checked_add(delta)?;
+        if new > self.max.unwrap_or(MAX_PAGES).min(MAX_PAGES) {
+            return None;
+        }
+        self.pages.resize(new as usize, None);
+        Some(old)
+    }
+
+    fn check(&self, address: u64, length: u64) -> WatResult<()> {
+        match address.checked_add(length) {
+            Some(end) if end <= self.byte_size() => Ok(()),
+            _ => Err(WatError::out_of_bounds_memory_error(
+                address,
+                self.byte_size(),
+            )),
+        }
+    }
+
+    /// Copy bytes out of memory, trapping if any of them is out of bounds
+    pub fn read(&self, address: u64, length: u64) -> WatResult<Vec<u8>> {
+        self.check(address, length)?;
+        Ok((address..address + length)
+            .map(|at| {
+                self.pages[(at / PAGE_SIZE as u64) as usize]
+                    .as_ref()
+                    .map_or(0, |page| page[(at % PAGE_SIZE as u64) as usize])
+            })
+            .collect())
+    }
+
+    /// Copy bytes into memory, trapping before writing anything if any of them is out of bounds
+    pub fn write(&mut self, address: u64, bytes: &[u8]) -> WatResult<()> {
+        self.check(address, bytes.len() as u64)?;
+        for (at, byte) in (address..).zip(bytes) {
+            let page = self.pages[(at / PAGE_SIZE as u64) as usize]
+                .get_or_insert_with(|| vec![0; PAGE_SIZE as usize].into_boxed_slice());
+            page[(at % PAGE_SIZE as u64) as usize] = *byte;
+        }
+        Ok(())
+    }
+
+    /// Little-endian load of `count` bytes at `address + offset`, zero extended to 64 bits
+    pub fn load(&self, address: u64, offset: u32, count: ByteKind) -> WatResult<(u64, Vec<u8>)> {
+        let bytes = self.read(address + offset as u64, count.byte_count() as u64)?;
+        let mut full = [0; 8];
+        full[..bytes.len()].copy_from_slice(&bytes);
+        Ok((u64::from_le_bytes(full), bytes))
+    }
+
+    /// Little-endian store of the low `count` bytes of the value at `address + offset`,
+    /// returning the bytes that were there
+    pub fn store(
+        &mut self,
+        address: u64,
+        offset: u32,
+        count: ByteKind,
+        value: u64,
+    ) -> WatResult<Vec<u8>> {
+        let address = address + offset as u64;
+        let bytes = &value.to_le_bytes()[..count.byte_count() as usize];
+        let old = self.read(address, bytes.len() as u64)?;
+        self.write(address, bytes)?;
+        Ok(old)
+    }
+}
+
+/// Most bytes the inspector returns at once
+pub(crate) const MAX_INSPECT_BYTES: u32 = 64 * 1024;
+/// Bytes shown on each row of a hex dump
+const ROW_BYTES: u32 = 16;
+
+/// Bytes written by a data segment when the module is instantiated
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct SegmentRegion {
+    /// Name of the segment, empty for an unnamed one
+    pub id: String,
+    pub start: u32,
+    pub length: u32,
+    pub is_string: bool,
+}
+
+impl SegmentRegion {
+    fn overlaps(&self, start: u32, length: u32) -> bool {
+        (self.start as u64) < start as u64 + length as u64
+            && (start as u64) < self.start as u64 + self.length as u64
+    }
+}
+
+/// One row of a hex dump
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct HexRow {
+    pub address: u32,
+    pub bytes: Vec<u8>,
+    /// Bytes as ASCII, with `.` for anything not printable
+    pub ascii: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct HexDump {
+    pub rows: Vec<HexRow>,
+    /// Data segments overlapping the dumped bytes
+    pub segments: Vec<SegmentRegion>,
+}
+
+/// Consecutive numbers of one type read from memory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct TypedValues {
+    pub typ: SerializableWatType,
+    pub values: Vec<SerializedNumber>,
+    /// Data segments overlapping the values
+    pub segments: Vec<SegmentRegion>,
+}
+
+/// How a string is laid out in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum StringLayout {
+    /// Bytes up to the first zero byte
+    NullTerminated,
+    /// A little-endian u32 byte length, followed by the bytes
+    LengthPrefixed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct DecodedString {
+    /// Address of the first byte of text
+    pub address: u32,
+    pub bytes: Vec<u8>,
+    /// Text with invalid UTF-8 replaced by `U+FFFD`
+    pub text: String,
+    pub is_valid_utf8: bool,
+    /// The text went past [MAX_INSPECT_BYTES] or the end of memory without finding its end
+    pub is_truncated: bool,
+    /// Data segment the string starts in
+    pub segment: Option<SegmentRegion>,
+}
+
+/// Minimum and maximum pages a module declares for a memory
+pub(crate) fn limits(memory: &MemoryData) -> WatResult<(u32, Option<u32>)> {
+    let pages = |number: SerializedNumber| {
+        u32::try_from(number.to_bits()).map_err(|_| WatError::number_to_large(&number))
+    };
+    // A missing max is stored as an i32 zero, a given one as an i64
+    let max = (memory.max.typ() == SerializableWatType::I64)
+        .then(|| pages(memory.max))
+        .transpose()?;
+    Ok((pages(memory.min)?, max))
+}
+
+/// Active data segments of a memory with their bytes, in address order
+pub(crate) fn segments(memory: &MemoryData) -> Vec<(SegmentRegion, &[u8])> {
+    let mut segments: Vec<_> = memory
+        .data
+        .iter()
+        .map(|(start, segment)| {
+            let region = SegmentRegion {
+                id: segment.id.clone(),
+                start: *start,
+                length: segment.data.len() as u32,
+                is_string: segment.is_string,
+            };
+            (region, segment.data.as_slice())
+        })
+        .collect();
+    segments.sort_by_key(|(region, _)| region.start);
+    segments
+}
+
+/// A memory of a module after instantiation, once data is copied and the start function has run
+///
+/// If instantiation traps, this is the memory as far as it got.
+struct InstantiatedMemory {
+    linear: LinearMemory,
+    segments: Vec<SegmentRegion>,
+}
+
+impl InstantiatedMemory {
+    fn new(text: &str, location: &str) -> WatResult<Self> {
+        let structure = crate::inner_transform(text)?;
+        let index = structure
+            .memory_index(location)
+            .ok_or(WatError::name_resolution_error(
+                location,
+                NumLocationKind::Memory,
+            ))?;
+        let mut machine = Machine::new(&structure)?.with_max_steps(INVOKE_MAX_STEPS);
+        let report = machine.instantiate()?;
+        Ok(Self {
+            linear: machine.memory(index).cloned().unwrap_or_default(),
+            segments: report
+                .data
+                .into_iter()
+                .filter(|write| write.memory as usize == index)
+                .map(|write| write.segment)
+                .collect(),
+        })
+    }
+
+    fn segments_in(&self, start: u32, length: u32) -> Vec<SegmentRegion> {
+        self.segments
+            .iter()
+            .filter(|segment| segment.overlaps(start, length))
+            .cloned()
+            .collect()
+    }
+}
+
+fn check_length(length: u64) -> WatResult<()> {
+    if length > MAX_INSPECT_BYTES as u64 {
+        Err(WatError::number_to_large(&SerializedNumber::from(
+            length as i64,
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Hex dump of the bytes of a memory, given by index or name, after instantiation
+pub fn memory_dump(text: &str, memory: &str, address: u32, length: u32) -> WatResult<HexDump> {
+    check_length(length as u64)?;
+    let memory = InstantiatedMemory::new(text, memory)?;
+    let bytes = memory.linear.read(address as u64, length as u64)?;
+    let rows = bytes
+        .chunks(ROW_BYTES as usize)
+        .zip((address..).step_by(ROW_BYTES as usize))
+        .map(|(bytes, address)| HexRow {
+            address,
+            bytes: bytes.to_vec(),
+            ascii: bytes
+                .iter()
+                .map(|b| {
+                    if b.is_ascii_graphic() || *b == b' ' {
+                        *b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+    Ok(HexDump {
+        rows,
+        segments: memory.segments_in(address, length),
+    })
+}
+
+/// Read `count` little-endian numbers of the given type from a memory after instantiation
+pub fn memory_values(
+    text: &str,
+    memory: &str,
+    address: u32,
+    typ: SerializableWatType,
+    count: u32,
+) -> WatResult<TypedValues> {
+    let size = match typ {
+        SerializableWatType::I32 | SerializableWatType::F32 => ByteKind::Bits32,
+        SerializableWatType::I64 | SerializableWatType::F64 => ByteKind::Bits64,
+        SerializableWatType::V128 => Err(WatError::unimplemented_error(
+            "V128 values are not supported yet.",
+        ))?,
+        SerializableWatType::FuncRef | SerializableWatType::ExternRef => Err(
+            WatError::unimplemented_error("References cannot be stored in memory."),
+        )?,
+    };
+    let length = count as u64 * size.byte_count() as u64;
+    check_length(length)?;
+    let memory = InstantiatedMemory::new(text, memory)?;
+    let values = (0..count as u64)
+        .map(|i| {
+            let (bits, _) =
+                memory
+                    .linear
+                    .load(address as u64 + i * size.byte_count() as u64, 0, size)?;
+            Value::from_bits(&typ, bits).map(SerializedNumber::from)
+        })
+        .collect::<WatResult<_>>()?;
+    Ok(TypedValues {
+        typ,
+        values,
+        segments: memory.segments_in(address, length as u32),
+    })
+}
+
+/// Decode a UTF-8 string at an address of a memory after instantiation
+pub fn memory_string(
+    text: &str,
+    memory: &str,
+    address: u32,
+    layout: StringLayout,
+) -> WatResult<DecodedString> {
+    let memory = InstantiatedMemory::new(text, memory)?;
+    let (start, bytes, is_truncated) = match layout {
+        StringLayout::NullTerminated => {
+            let available = (memory.linear.byte_size().saturating_sub(address as u64))
+                .min(MAX_INSPECT_BYTES as u64);
+            let mut bytes = memory.linear.read(address as u64, available)?;
+            let end = bytes.iter().position(|b| *b == 0);
+            bytes.truncate(end.unwrap_or(bytes.len()));
+            (address as u64, bytes, end.is_none())
+        }
+        StringLayout::LengthPrefixed => {
+            let (length, _) = memory.linear.load(address as u64, 0, ByteKind::Bits32)?;
+            check_length(length)?;
+            let start = address as u64 + 4;
+            (start, memory.linear.read(start, length)?, false)
+        }
+    };
+    // Reads past 4 GiB trap above
+    let start = start as u32;
+    let decoded = String::from_utf8_lossy(&bytes);
+    Ok(DecodedString {
+        address: start,
+        text: decoded.to_string(),
+        is_valid_utf8: matches!(decoded, std::borrow::Cow::Borrowed(_)),
+        is_truncated,
+        segment: memory
+            .segments
+            .iter()
+            .find(|segment| segment.overlaps(start, 1))
+            .cloned(),
+        bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn store_then_load_is_little_endian() {
+        let mut memory = LinearMemory::new(1, None);
+        let old = memory.store(8, 4, ByteKind::Bits32, 0x1234_5678).unwrap();
+        assert_eq!(old, vec![0; 4]);
+        assert_eq!(memory.read(12, 4).unwrap(), vec![0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(memory.load(12, 0, ByteKind::Bits16).unwrap().0, 0x5678);
+    }
+
+    #[test]
+    fn access_past_the_end_traps_without_writing() {
+        let mut memory = LinearMemory::new(1, None);
+        let last = PAGE_SIZE as u64 - 2;
+        let err = memory
+            .store(last, 0, ByteKind::Bits32, u64::MAX)
+            .unwrap_err();
+        assert_eq!(err.trap(), Some(crate::error::TrapKind::OutOfBoundsMemory));
+        assert_eq!(memory.read(last, 2).unwrap(), vec![0; 2]);
+        assert!(memory.load(u32::MAX as u64, 1, ByteKind::Bits8).is_err());
+    }
+
+    #[test]
+    fn strings_are_found_in_their_segment() {
+        let text = r#"(module (memory $m 1)
+            (data $greeting (i32.const 16) "Hi!\00")
+            (data $name (i32.const 32) "\05\00\00\00caf\c3\a9"))"#;
+        let greeting = memory_string(text, "$m", 16, StringLayout::NullTerminated).unwrap();
+        assert_eq!(greeting.text, "Hi!");
+        assert_eq!(greeting.segment.unwrap().id, "greeting");
+        let name = memory_string(text, "0", 32, StringLayout::LengthPrefixed).unwrap();
+        assert_eq!((name.address, name.text.as_str()), (36, "café"));
+        let dump = memory_dump(text, "m", 0, 64).unwrap();
+        assert_eq!(dump.rows.len(), 4);
+        assert_eq!(dump.rows[1].ascii, "Hi!.............");
+        assert_eq!(dump.segments.len(), 2);
+    }
+
+    #[test]
+    fn grow_stops_at_max() {
+        let mut memory = LinearMemory::new(1, Some(2));
+        assert_eq!(memory.grow(1), Some(1));
+        assert_eq!(memory.grow(1), None);
+        assert_eq!(memory.grow(0), Some(2));
+        assert_eq!(memory.byte_size(), 2 * PAGE_SIZE as u64);
+    }
+
+    #[test]
+    fn inspector_sees_what_the_start_function_wrote() {
+        let text = r#"(module (memory 1)
+            (data (i32.const 0) "abc\00")
+            (start $init)
+            (func $init
+                i32.const 1 i32.const 66 i32.store8
+                i32.const 8 i32.const 7 i32.store))"#;
+        let string = memory_string(text, "0", 0, StringLayout::NullTerminated).unwrap();
+        assert_eq!(string.text, "aBc");
+        let values = memory_values(text, "0", 8, SerializableWatType::I32, 1).unwrap();
+        assert_eq!(values.values, [SerializedNumber::from(7)]);
+        assert!(values.segments.is_empty());
+    }
+}
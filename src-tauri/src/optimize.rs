@@ -0,0 +1,514 @@
+//! Constant folding and peephole rewrites, showing what an optimizing compiler would do.
+//!
+//! Each function is rewritten until nothing changes: unreachable code is removed,
+//! operations on constants are computed ahead of time and instructions that cancel out are dropped.
+//! Operations that would trap, like dividing by 0, are left for the program to run into.
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    dead_code::local_index,
+    error::WatResult,
+    helper::SerializedNumber,
+    inner_transform,
+    instruction::{linear_instructions_to_tree, SerializedInstruction, SerializedInstructionTree},
+    interpreter::{evaluate, operand_count, Value},
+    marker::{
+        ArithmeticOperation, BitwiseOperation, BlockKind, ComparisonOperation, DataInstruction,
+        SimpleInstruction,
+    },
+    validator::{StackTypes, Validator},
+    WastFunc,
+};
+
+/// Rewriting stops after this many passes, even if more could be done
+const MAX_PASSES: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum DiffKind {
+    Same,
+    Removed,
+    Added,
+}
+
+/// One line of the difference between the instructions before and after optimizing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct DiffLine {
+    pub kind: DiffKind,
+    /// Index in the original instructions, for kept and removed lines
+    pub before: Option<u32>,
+    /// Index in the optimized instructions, for kept and added lines
+    pub after: Option<u32>,
+    pub instruction: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct OptimizedFunction {
+    pub name: String,
+    pub tree: SerializedInstructionTree,
+    /// What was changed and why, in the order the rewrites happened
+    pub rewrites: Vec<String>,
+    pub diff: Vec<DiffLine>,
+}
+
+fn constant(instruction: &SerializedInstruction) -> Option<Value> {
+    match instruction {
+        SerializedInstruction::Const { value, .. } => Some(Value::from(*value)),
+        _ => None,
+    }
+}
+
+fn const_instruction(value: Value) -> SerializedInstruction {
+    let value = SerializedNumber::from(value);
+    SerializedInstruction::Const {
+        typ: value.typ(),
+        value,
+    }
+}
+
+/// Instructions in backticks, like `` `i32.const 1`, `i32.const 2` ``
+fn show(instructions: &[SerializedInstruction]) -> String {
+    instructions
+        .iter()
+        .map(|instruction| format!("`{instruction}`"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Whether applying the instruction with the constant as its second operand
+/// gives back the first operand, like adding 0
+fn is_identity(instruction: &SerializedInstruction, constant: Value) -> bool {
+    let n = match constant {
+        Value::I32(n) => n as i64,
+        Value::I64(n) => n,
+        // Floats have -0 and NaN to worry about
+        _ => return false,
+    };
+    match instruction {
+        SerializedInstruction::Arithmetic { kind, .. } => match kind {
+            ArithmeticOperation::Addition | ArithmeticOperation::Subtraction => n == 0,
+            ArithmeticOperation::Multiplication
+            | ArithmeticOperation::DivisonSigned
+            | ArithmeticOperation::DivisonUnsigned => n == 1,
+            _ => false,
+        },
+        SerializedInstruction::Bitwise { kind, .. } => match kind {
+            BitwiseOperation::And => n == -1,
+            BitwiseOperation::Or
+            | BitwiseOperation::Xor
+            | BitwiseOperation::ShiftLeft
+            | BitwiseOperation::ShiftRightSigned
+            | BitwiseOperation::ShiftRightUnsigned
+            | BitwiseOperation::RotateLeft
+            | BitwiseOperation::RotateRight => n == 0,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+struct Optimizer<'a> {
+    func: &'a WastFunc,
+    validator: Validator,
+    rewrites: Vec<String>,
+}
+
+impl Optimizer<'_> {
+    fn same_local(&self, a: &str, b: &str) -> bool {
+        local_index(self.func, a).is_some_and(|a| Some(a) == local_index(self.func, b))
+    }
+
+    fn stack_types(&mut self, instructions: &[SerializedInstruction]) -> StackTypes {
+        self.validator.infer_stack_types(
+            instructions,
+            &self.func.info.input,
+            &self.func.locals,
+            &self.func.info.output,
+        )
+    }
+
+    /// Remove what the validator marks as unreachable, or [None] if the instructions are not valid
+    fn remove_unreachable(
+        &mut self,
+        instructions: Vec<SerializedInstruction>,
+    ) -> Option<Vec<SerializedInstruction>> {
+        let stack_types = self.stack_types(&instructions);
+        if stack_types.error.is_some() {
+            return None;
+        }
+        let root = linear_instructions_to_tree("", &mut instructions.clone()).ok()?;
+        let mut removed = vec![false; instructions.len()];
+        for (index, instruction) in instructions.iter().enumerate() {
+            if !stack_types.unreachable[index] || removed[index] {
+                continue;
+            }
+            match instruction {
+                // Kept for the blocks they close
+                SerializedInstruction::Block {
+                    kind: BlockKind::Else | BlockKind::End,
+                    ..
+                } => {}
+                // The whole block goes, along with its end
+                SerializedInstruction::Block { .. } => {
+                    if let Some(node) = root.iter().skip(1).find(|n| n.start == index as u32) {
+                        removed[node.start as usize..=node.end as usize].fill(true);
+                    }
+                }
+                _ => removed[index] = true,
+            }
+        }
+        let count = removed.iter().filter(|r| **r).count();
+        if count > 0 {
+            self.rewrites.push(format!(
+                "Removed {count} unreachable instruction{}",
+                if count == 1 { "" } else { "s" }
+            ));
+        }
+        Some(
+            instructions
+                .into_iter()
+                .zip(removed)
+                .filter_map(|(instruction, removed)| (!removed).then_some(instruction))
+                .collect(),
+        )
+    }
+
+    /// Rewrite the instruction together with the ones before it,
+    /// returning what was done or [None] to keep it as is
+    fn rewrite(
+        &self,
+        out: &mut Vec<SerializedInstruction>,
+        instruction: &SerializedInstruction,
+    ) -> Option<String> {
+        if let SerializedInstruction::Simple(SimpleInstruction::Nop) = instruction {
+            return Some("Removed `nop`, which does nothing".to_string());
+        }
+        if let Some(count) = operand_count(instruction) {
+            let start = out.len().checked_sub(count)?;
+            let operands: Option<Vec<Value>> = out[start..].iter().map(constant).collect();
+            if let Some(Ok(value)) = operands.map(|operands| evaluate(instruction, &operands)) {
+                let folded = out.split_off(start);
+                out.push(const_instruction(value));
+                return Some(format!(
+                    "Folded {} and `{instruction}` into `{}`",
+                    show(&folded),
+                    const_instruction(value)
+                ));
+            }
+        }
+        let last = out.last()?.clone();
+        let last_constant = constant(&last);
+        match instruction {
+            _ if operand_count(instruction) == Some(2)
+                && last_constant.is_some_and(|c| is_identity(instruction, c)) =>
+            {
+                let removed = out.pop()?;
+                Some(format!(
+                    "Removed `{removed}` and `{instruction}`, which leave the value unchanged"
+                ))
+            }
+            SerializedInstruction::Comparison {
+                kind: ComparisonOperation::Equal,
+                typ,
+            } if last_constant
+                .is_some_and(|c| c.is_zero() && matches!(c, Value::I32(_) | Value::I64(_))) =>
+            {
+                let removed = out.pop()?;
+                let eqz = SerializedInstruction::Comparison {
+                    kind: ComparisonOperation::EqualZero,
+                    typ: *typ,
+                };
+                let message = format!("Replaced `{removed}` and `{instruction}` with `{eqz}`");
+                out.push(eqz);
+                Some(message)
+            }
+            SerializedInstruction::Simple(SimpleInstruction::Drop)
+                if last_constant.is_some()
+                    || matches!(
+                        last,
+                        SerializedInstruction::Data {
+                            kind: DataInstruction::GetLocal | DataInstruction::GetGlobal,
+                            ..
+                        }
+                    ) =>
+            {
+                let removed = out.pop()?;
+                Some(format!(
+                    "Removed `{removed}` and `drop`, since the value is never used"
+                ))
+            }
+            SerializedInstruction::Data {
+                kind: DataInstruction::SetLocal,
+                location,
+            } => match &last {
+                SerializedInstruction::Data {
+                    kind: DataInstruction::GetLocal,
+                    location: got,
+                } if self.same_local(location, got) => {
+                    let removed = out.pop()?;
+                    Some(format!(
+                        "Removed `{removed}` and `{instruction}`, which store the value the local already has"
+                    ))
+                }
+                _ => None,
+            },
+            SerializedInstruction::Data {
+                kind: DataInstruction::GetLocal,
+                location,
+            } => match &last {
+                SerializedInstruction::Data {
+                    kind: DataInstruction::SetLocal,
+                    location: set,
+                } if self.same_local(location, set) => {
+                    let removed = out.pop()?;
+                    let tee = SerializedInstruction::Data {
+                        kind: DataInstruction::TeeLocal,
+                        location: set.clone(),
+                    };
+                    let message = format!("Combined `{removed}` and `{instruction}` into `{tee}`");
+                    out.push(tee);
+                    Some(message)
+                }
+                _ => None,
+            },
+            SerializedInstruction::Branch {
+                default_label,
+                other_labels,
+                is_conditional: true,
+                ..
+            } if other_labels.is_empty() => {
+                let condition = last_constant?;
+                let removed = out.pop()?;
+                if condition.is_zero() {
+                    return Some(format!(
+                        "Removed `{removed}` and `{instruction}`, which never branches"
+                    ));
+                }
+                // Targets are resolved again when the tree is rebuilt
+                let branch = SerializedInstruction::Branch {
+                    default_label: default_label.clone(),
+                    other_labels: Vec::new(),
+                    is_conditional: false,
+                    targets: Vec::new(),
+                };
+                let message = format!(
+                    "Replaced `{removed}` and `{instruction}` with `{branch}`, which always branches"
+                );
+                out.push(branch);
+                Some(message)
+            }
+            _ => None,
+        }
+    }
+
+    fn peephole(&mut self, instructions: Vec<SerializedInstruction>) -> Vec<SerializedInstruction> {
+        let mut out = Vec::with_capacity(instructions.len());
+        for instruction in instructions {
+            match self.rewrite(&mut out, &instruction) {
+                Some(rewrite) => self.rewrites.push(rewrite),
+                None => out.push(instruction),
+            }
+        }
+        out
+    }
+
+    /// Rewrite the function until nothing changes, always ending with valid instructions
+    fn run(&mut self) -> Vec<SerializedInstruction> {
+        let mut instructions = self.func.block.array.clone();
+        for _ in 0..MAX_PASSES {
+            let Some(reachable) = self.remove_unreachable(instructions.clone()) else {
+                break;
+            };
+            let next = self.peephole(reachable);
+            if next == instructions {
+                break;
+            }
+            instructions = next;
+        }
+        if self.stack_types(&instructions).error.is_some() {
+            // Only the original instructions are known to be valid
+            self.rewrites.clear();
+            return self.func.block.array.clone();
+        }
+        instructions
+    }
+}
+
+/// Line by line difference, keeping the longest run of instructions common to both
+fn diff(before: &[SerializedInstruction], after: &[SerializedInstruction]) -> Vec<DiffLine> {
+    let before: Vec<String> = before.iter().map(|i| i.to_string()).collect();
+    let after: Vec<String> = after.iter().map(|i| i.to_string()).collect();
+    // common[i][j] is the longest common length of before[i..] and after[j..]
+    let mut common = vec![vec![0_u32; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            common[i][j] = if before[i] == after[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let line = |kind, before: Option<usize>, after: Option<usize>, instruction: &String| DiffLine {
+        kind,
+        before: before.map(|i| i as u32),
+        after: after.map(|i| i as u32),
+        instruction: instruction.clone(),
+    };
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < before.len() || j < after.len() {
+        if i < before.len() && j < after.len() && before[i] == after[j] {
+            lines.push(line(DiffKind::Same, Some(i), Some(j), &before[i]));
+            i += 1;
+            j += 1;
+        } else if j == after.len() || (i < before.len() && common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(line(DiffKind::Removed, Some(i), None, &before[i]));
+            i += 1;
+        } else {
+            lines.push(line(DiffKind::Added, None, Some(j), &after[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// Optimize every function of a valid module
+pub fn optimize(text: &str) -> WatResult<Vec<OptimizedFunction>> {
+    let structure = inner_transform(text)?;
+    structure
+        .func
+        .iter()
+        .map(|func| {
+            let name = func.name().unwrap_or_default();
+            let mut optimizer = Optimizer {
+                func,
+                validator: Validator::new(&structure),
+                rewrites: Vec::new(),
+            };
+            let instructions = optimizer.run();
+            Ok(OptimizedFunction {
+                diff: diff(&func.block.array, &instructions),
+                tree: SerializedInstructionTree::try_from_serialized(&name, instructions)?,
+                rewrites: optimizer.rewrites,
+                name,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::module_with_func;
+
+    fn optimized(body: &str) -> OptimizedFunction {
+        optimize(&module_with_func("", "(result i32)", body))
+            .unwrap()
+            .remove(0)
+    }
+
+    /// Optimized instructions of a function with an `i32` parameter and local
+    fn after(body: &str) -> Vec<String> {
+        let function = optimize(&module_with_func(
+            "",
+            "(param $a i32) (result i32) (local $x i32)",
+            body,
+        ))
+        .unwrap()
+        .remove(0);
+        function
+            .diff
+            .iter()
+            .filter(|line| line.kind != DiffKind::Removed)
+            .map(|line| line.instruction.clone())
+            .collect()
+    }
+
+    #[test]
+    fn block_types_are_kept_in_the_diff() {
+        let function = optimized("block (result i32) i32.const 1 i32.const 2 i32.add end");
+        let lines: Vec<_> = function
+            .diff
+            .iter()
+            .filter(|line| line.kind != DiffKind::Removed)
+            .map(|line| line.instruction.as_str())
+            .collect();
+        assert_eq!(lines, ["block (result i32)", "i32.const 3", "end"]);
+    }
+
+    #[test]
+    fn constants_are_folded() {
+        assert_eq!(
+            after("i32.const 6 i32.const 7 i32.mul i32.const 2 i32.sub"),
+            ["i32.const 40"]
+        );
+        // Left for the program to trap on
+        assert_eq!(
+            after("i32.const 1 i32.const 0 i32.div_s"),
+            ["i32.const 1", "i32.const 0", "i32.div_s"]
+        );
+    }
+
+    #[test]
+    fn nops_and_identities_are_removed() {
+        assert_eq!(
+            after("nop local.get $a i32.const 0 i32.add i32.const 1 i32.mul"),
+            ["local.get $a"]
+        );
+        assert_eq!(after("local.get $a i32.const -1 i32.and"), ["local.get $a"]);
+    }
+
+    #[test]
+    fn comparing_with_zero_becomes_eqz() {
+        assert_eq!(
+            after("local.get $a i32.const 0 i32.eq"),
+            ["local.get $a", "i32.eqz"]
+        );
+    }
+
+    #[test]
+    fn unused_values_are_not_computed() {
+        assert_eq!(
+            after("i32.const 1 drop local.get $a drop local.get $a"),
+            ["local.get $a"]
+        );
+    }
+
+    #[test]
+    fn locals_are_not_stored_twice() {
+        assert_eq!(
+            after("local.get $a local.set $a local.get $a"),
+            ["local.get $a"]
+        );
+        assert_eq!(
+            after("local.get $a local.set $x local.get $x"),
+            ["local.get $a", "local.tee $x"]
+        );
+    }
+
+    #[test]
+    fn constant_conditions_decide_the_branch() {
+        assert_eq!(
+            after("block i32.const 0 br_if 0 end local.get $a"),
+            ["block", "end", "local.get $a"]
+        );
+        let function = optimized("block i32.const 1 br_if 0 call 0 drop end i32.const 2");
+        assert_eq!(
+            function.rewrites,
+            [
+                "Replaced `i32.const 1` and `br_if 0` with `br 0`, which always branches",
+                "Removed 2 unreachable instructions",
+            ]
+        );
+    }
+
+    #[test]
+    fn unreachable_code_is_removed() {
+        assert_eq!(
+            after("local.get $a return i32.const 1 i32.const 2 i32.add drop"),
+            ["local.get $a", "return"]
+        );
+    }
+}
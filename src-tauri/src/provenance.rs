@@ -0,0 +1,295 @@
+//! Where each value comes from: which instruction put it on the stack,
+//! and which instructions stored what a `local.get` or `global.get` reads.
+//!
+//! Found without running anything, by following every path through the function.
+//! Where paths meet, a value can come from more than one place.
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    cfg::{carry_values, successors},
+    dead_code::local_index,
+    error::WatResult,
+    inner_transform,
+    instruction::SerializedInstruction,
+    marker::{BlockKind, DataInstruction},
+    validator::{try_name_to_index, StackTypes, Validator},
+    GlobalData, WastFunc,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Type)]
+pub enum Origin {
+    /// Pushed or stored by the instruction at this index
+    Instruction(u32),
+    /// Parameter at this index
+    Param(u32),
+    /// Local that nothing has stored to yet, so it is 0
+    Zero,
+    /// Global as it was when the function was called
+    Entry,
+}
+
+type Origins = BTreeSet<Origin>;
+
+/// What is known before an instruction runs
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct State {
+    stack: Vec<Origins>,
+    locals: Vec<Origins>,
+    globals: Vec<Origins>,
+}
+
+impl State {
+    /// Add everything in the other state, returning whether anything was new
+    fn merge(&mut self, other: &State) -> bool {
+        let mut changed = false;
+        for (mine, theirs) in [
+            (&mut self.stack, &other.stack),
+            (&mut self.locals, &other.locals),
+            (&mut self.globals, &other.globals),
+        ] {
+            if mine.len() < theirs.len() {
+                mine.resize(theirs.len(), Origins::new());
+                changed = true;
+            }
+            for (mine, theirs) in mine.iter_mut().zip(theirs) {
+                let before = mine.len();
+                mine.extend(theirs);
+                changed |= mine.len() != before;
+            }
+        }
+        changed
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct FunctionProvenance {
+    pub name: String,
+    /// Where each value on the stack before each instruction came from, bottom first,
+    /// with one more entry for the stack at the end of the function
+    pub stack: Vec<Vec<Vec<Origin>>>,
+    /// Where each value each instruction pops came from, bottom first
+    pub operands: Vec<Vec<Vec<Origin>>>,
+    /// What each `local.get` and `global.get` can read, empty for other instructions
+    pub reads: Vec<Vec<Origin>>,
+}
+
+fn global_index(globals: &[GlobalData], location: &str) -> Option<usize> {
+    match try_name_to_index(location) {
+        Ok(index) => Some(index),
+        Err(name) => globals.iter().position(|g| g.name == name),
+    }
+}
+
+/// Follow the values through one function, whose stack types are already known
+fn function_provenance(
+    func: &WastFunc,
+    globals: &[GlobalData],
+    stack_types: &StackTypes,
+) -> FunctionProvenance {
+    let tree = &func.block;
+    let count = tree.array.len();
+    let mut states: Vec<Option<State>> = vec![None; count + 1];
+    let mut reads: Vec<Origins> = vec![Origins::new(); count];
+    let params = func.info.input.len();
+    states[0] = Some(State {
+        stack: Vec::new(),
+        locals: (0..params)
+            .map(|i| Origins::from([Origin::Param(i as u32)]))
+            .chain(func.locals.iter().map(|_| Origins::from([Origin::Zero])))
+            .collect(),
+        globals: vec![Origins::from([Origin::Entry]); globals.len()],
+    });
+    let mut pending: Vec<usize> = vec![0];
+    while let Some(index) = pending.pop() {
+        if index >= count {
+            continue;
+        }
+        let Some(mut state) = states[index].clone() else {
+            continue;
+        };
+        let instruction = &tree.array[index];
+        let origin = Origins::from([Origin::Instruction(index as u32)]);
+        let effect = &stack_types.effects[index];
+        match instruction {
+            SerializedInstruction::Data { kind, location } => {
+                let local = || local_index(func, location);
+                let global = || global_index(globals, location);
+                state
+                    .stack
+                    .truncate(state.stack.len().saturating_sub(effect.popped.len()));
+                match kind {
+                    DataInstruction::GetLocal => {
+                        reads[index] = local()
+                            .and_then(|l| state.locals.get(l).cloned())
+                            .unwrap_or_default();
+                    }
+                    DataInstruction::GetGlobal => {
+                        reads[index] = global()
+                            .and_then(|g| state.globals.get(g).cloned())
+                            .unwrap_or_default();
+                    }
+                    DataInstruction::SetLocal | DataInstruction::TeeLocal => {
+                        if let Some(stored) = local().and_then(|l| state.locals.get_mut(l)) {
+                            *stored = origin.clone();
+                        }
+                    }
+                    DataInstruction::SetGlobal => {
+                        if let Some(stored) = global().and_then(|g| state.globals.get_mut(g)) {
+                            *stored = origin.clone();
+                        }
+                    }
+                    DataInstruction::GetMemorySize | DataInstruction::SetMemorySize => {}
+                }
+                state
+                    .stack
+                    .extend(effect.pushed.iter().map(|_| origin.clone()));
+            }
+            // Values stay where they are at the edges of blocks
+            SerializedInstruction::Block { kind, .. } => {
+                if *kind == BlockKind::If {
+                    state.stack.pop();
+                }
+            }
+            SerializedInstruction::Branch {
+                is_conditional,
+                other_labels,
+                ..
+            } => {
+                if *is_conditional || !other_labels.is_empty() {
+                    state.stack.pop();
+                }
+            }
+            _ => {
+                state
+                    .stack
+                    .truncate(state.stack.len().saturating_sub(effect.popped.len()));
+                state
+                    .stack
+                    .extend(effect.pushed.iter().map(|_| origin.clone()));
+                // The called function can store to any global
+                if matches!(
+                    instruction,
+                    SerializedInstruction::Call { .. } | SerializedInstruction::CallIndirect { .. }
+                ) {
+                    state
+                        .globals
+                        .iter_mut()
+                        .for_each(|g| g.extend(origin.iter().copied()));
+                }
+            }
+        }
+        for (to, kind) in successors(tree, index as u32) {
+            let mut next = state.clone();
+            carry_values(tree, stack_types, index as u32, (to, kind), &mut next.stack);
+            let changed = match &mut states[to as usize] {
+                Some(existing) => existing.merge(&next),
+                empty => {
+                    *empty = Some(next);
+                    true
+                }
+            };
+            if changed {
+                pending.push(to as usize);
+            }
+        }
+    }
+    let to_vec = |origins: &Origins| origins.iter().copied().collect::<Vec<_>>();
+    let stack: Vec<Vec<Vec<Origin>>> = states
+        .iter()
+        .map(|state| {
+            state
+                .as_ref()
+                .map(|state| state.stack.iter().map(to_vec).collect())
+                .unwrap_or_default()
+        })
+        .collect();
+    let operands = stack
+        .iter()
+        .zip(&stack_types.effects)
+        .map(|(before, effect)| before[before.len().saturating_sub(effect.popped.len())..].to_vec())
+        .collect();
+    FunctionProvenance {
+        name: func.name().unwrap_or_default(),
+        stack,
+        operands,
+        reads: reads.iter().map(to_vec).collect(),
+    }
+}
+
+/// Where every value in every function comes from
+pub fn value_provenance(text: &str) -> WatResult<Vec<FunctionProvenance>> {
+    let structure = inner_transform(text)?;
+    let mut validator = Validator::new(&structure);
+    Ok(structure
+        .func
+        .iter()
+        .map(|func| {
+            let stack_types = validator.infer_stack_types(
+                &func.block.array,
+                &func.info.input,
+                &func.locals,
+                &func.info.output,
+            );
+            function_provenance(func, &structure.globals, &stack_types)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use Origin::*;
+
+    fn provenance(text: &str) -> Vec<FunctionProvenance> {
+        value_provenance(text).unwrap()
+    }
+
+    #[test]
+    fn operands_come_from_the_instructions_pushing_them() {
+        let func =
+            provenance("(module (func (param i32) (result i32) local.get 0 i32.const 1 i32.add))")
+                .remove(0);
+        assert_eq!(func.reads[0], [Param(0)]);
+        assert_eq!(
+            func.operands[2],
+            [vec![Instruction(0)], vec![Instruction(1)]]
+        );
+        assert_eq!(func.stack[3], [vec![Instruction(2)]]);
+    }
+
+    #[test]
+    fn paths_meet_with_every_origin() {
+        let func = provenance(
+            "(module (func (param i32) (result i32) (local $x i32)
+                local.get 0
+                if
+                    i32.const 5 local.set $x
+                end
+                local.get 0
+                if (result i32) i32.const 1 else i32.const 2 end
+                drop
+                local.get $x))",
+        )
+        .remove(0);
+        assert_eq!(func.reads[12], [Instruction(3), Zero]);
+        assert_eq!(func.operands[11], [vec![Instruction(7), Instruction(9)]]);
+    }
+
+    #[test]
+    fn calls_can_store_to_globals() {
+        let func = provenance(
+            "(module
+                (global $g (mut i32) (i32.const 0))
+                (func $f)
+                (func global.get $g drop call $f global.get $g drop))",
+        )
+        .remove(1);
+        assert_eq!(func.reads[0], [Entry]);
+        assert_eq!(func.reads[3], [Instruction(2), Entry]);
+    }
+}
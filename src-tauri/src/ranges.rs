@@ -0,0 +1,907 @@
+//! Which values each integer can have, found without running the function.
+//!
+//! Every integer on the stack and in a local is kept as a range of signed values,
+//! following every path through the function's control flow graph. Ranges that keep
+//! growing around a loop are widened to the whole type, so the analysis always finishes.
+//!
+//! Warnings are only given for ranges narrower than the whole type,
+//! otherwise every division by a parameter and every memory access would be flagged.
+
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    cfg::{carry_values, successors},
+    dead_code::local_index,
+    diagnostic::Diagnostic,
+    error::WatResult,
+    helper::SerializedNumber,
+    inner_transform,
+    instruction::SerializedInstruction,
+    interpreter::{evaluate, operand_count, Value},
+    marker::{
+        ArithmeticOperation, BitwiseOperation, BlockKind, ComparisonOperation, DataInstruction,
+        SerializableWatType,
+    },
+    source::SourceMap,
+    validator::{try_name_to_index, StackTypes, Validator},
+    InterpreterStructure, WastFunc,
+};
+
+/// Times an instruction is revisited before ranges that still grow are widened
+const WIDEN_AFTER: usize = 3;
+const PAGE_SIZE: i128 = 65536;
+
+/// What is known about a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Abstract {
+    Int {
+        is_64_bit: bool,
+        min: i64,
+        max: i64,
+    },
+    /// Floats, and anything else that is not tracked
+    Unknown,
+}
+
+fn limits(is_64_bit: bool) -> (i64, i64) {
+    if is_64_bit {
+        (i64::MIN, i64::MAX)
+    } else {
+        (i32::MIN as i64, i32::MAX as i64)
+    }
+}
+
+impl Abstract {
+    /// Any value of the type
+    fn any(typ: &SerializableWatType) -> Self {
+        match typ {
+            SerializableWatType::I32 => Self::full(false),
+            SerializableWatType::I64 => Self::full(true),
+            _ => Self::Unknown,
+        }
+    }
+
+    fn full(is_64_bit: bool) -> Self {
+        let (min, max) = limits(is_64_bit);
+        Self::Int {
+            is_64_bit,
+            min,
+            max,
+        }
+    }
+
+    fn exact(value: Value) -> Self {
+        match value {
+            Value::I32(n) => Self::Int {
+                is_64_bit: false,
+                min: n as i64,
+                max: n as i64,
+            },
+            Value::I64(n) => Self::Int {
+                is_64_bit: true,
+                min: n,
+                max: n,
+            },
+            _ => Self::Unknown,
+        }
+    }
+
+    /// The range, or the whole type if the range does not fit in it
+    fn bounded(is_64_bit: bool, min: i128, max: i128) -> Self {
+        let (low, high) = limits(is_64_bit);
+        if min < low as i128 || max > high as i128 || min > max {
+            Self::full(is_64_bit)
+        } else {
+            Self::Int {
+                is_64_bit,
+                min: min as i64,
+                max: max as i64,
+            }
+        }
+    }
+
+    /// 0 or 1, or just one of them if it is known
+    fn boolean(known: Option<bool>) -> Self {
+        let (min, max) = known.map_or((0, 1), |b| (b as i64, b as i64));
+        Self::Int {
+            is_64_bit: false,
+            min,
+            max,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        match self {
+            Self::Int {
+                is_64_bit,
+                min,
+                max,
+            } => (*min, *max) == limits(*is_64_bit),
+            Self::Unknown => true,
+        }
+    }
+
+    fn single(&self) -> Option<Value> {
+        match *self {
+            Self::Int {
+                is_64_bit: false,
+                min,
+                max,
+            } if min == max => Some(Value::I32(min as i32)),
+            Self::Int {
+                is_64_bit: true,
+                min,
+                max,
+            } if min == max => Some(Value::I64(min)),
+            _ => None,
+        }
+    }
+
+    fn signed(&self) -> Option<(i128, i128)> {
+        match *self {
+            Self::Int { min, max, .. } => Some((min as i128, max as i128)),
+            Self::Unknown => None,
+        }
+    }
+
+    /// The range read as unsigned numbers, if it does not wrap around
+    fn unsigned(&self) -> Option<(i128, i128)> {
+        match *self {
+            Self::Int {
+                is_64_bit,
+                min,
+                max,
+            } => {
+                let wrap = if is_64_bit { 1 << 64 } else { 1 << 32 };
+                match (min < 0, max < 0) {
+                    (false, _) => Some((min as i128, max as i128)),
+                    (true, true) => Some((min as i128 + wrap, max as i128 + wrap)),
+                    (true, false) => None,
+                }
+            }
+            Self::Unknown => None,
+        }
+    }
+
+    fn contains(&self, n: i64) -> bool {
+        match self {
+            Self::Int { min, max, .. } => (*min..=*max).contains(&n),
+            Self::Unknown => true,
+        }
+    }
+
+    fn join(self, other: Self) -> Self {
+        match (self, other) {
+            (
+                Self::Int {
+                    is_64_bit,
+                    min,
+                    max,
+                },
+                Self::Int {
+                    is_64_bit: other_is_64_bit,
+                    min: other_min,
+                    max: other_max,
+                },
+            ) if is_64_bit == other_is_64_bit => Self::Int {
+                is_64_bit,
+                min: min.min(other_min),
+                max: max.max(other_max),
+            },
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Jump any bound that grew since the older value straight to the end of the type
+    fn widen(self, newer: Self) -> Self {
+        match (self, newer) {
+            (
+                Self::Int { min, max, .. },
+                Self::Int {
+                    is_64_bit,
+                    min: new_min,
+                    max: new_max,
+                },
+            ) => {
+                let (low, high) = limits(is_64_bit);
+                Self::Int {
+                    is_64_bit,
+                    min: if new_min < min { low } else { new_min },
+                    max: if new_max > max { high } else { new_max },
+                }
+            }
+            _ => newer,
+        }
+    }
+}
+
+impl Display for Abstract {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Int { min, max, .. } if min == max => write!(f, "{min}"),
+            Self::Int { min, max, .. } => write!(f, "{min} to {max}"),
+            Self::Unknown => f.write_str("unknown"),
+        }
+    }
+}
+
+/// Whether `a` is before `b` (or equal, if not strict) for every value in the ranges
+fn order(a: (i128, i128), b: (i128, i128), is_strict: bool) -> Option<bool> {
+    let (always, never) = if is_strict {
+        (a.1 < b.0, a.0 >= b.1)
+    } else {
+        (a.1 <= b.0, a.0 > b.1)
+    };
+    if always {
+        Some(true)
+    } else if never {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn compare(kind: &ComparisonOperation, a: Abstract, b: Abstract) -> Abstract {
+    use ComparisonOperation as C;
+    let (signed_a, signed_b) = (a.signed(), b.signed());
+    let (unsigned_a, unsigned_b) = (a.unsigned(), b.unsigned());
+    let known = match kind {
+        C::Equal | C::NotEqual => {
+            let is_equal = match (a.single(), b.single()) {
+                (Some(x), Some(y)) => Some(x == y),
+                _ => signed_a
+                    .zip(signed_b)
+                    .and_then(|(a, b)| (a.1 < b.0 || b.1 < a.0).then_some(false)),
+            };
+            is_equal.map(|is_equal| is_equal == (*kind == C::Equal))
+        }
+        C::LessThenSigned => signed_a.zip(signed_b).and_then(|(a, b)| order(a, b, true)),
+        C::GreaterThenSigned => signed_a.zip(signed_b).and_then(|(a, b)| order(b, a, true)),
+        C::LessThenOrEqualToSigned => signed_a.zip(signed_b).and_then(|(a, b)| order(a, b, false)),
+        C::GreaterThenOrEqualToSigned => {
+            signed_a.zip(signed_b).and_then(|(a, b)| order(b, a, false))
+        }
+        C::LessThenUnsigned => unsigned_a
+            .zip(unsigned_b)
+            .and_then(|(a, b)| order(a, b, true)),
+        C::GreaterThenUnsigned => unsigned_a
+            .zip(unsigned_b)
+            .and_then(|(a, b)| order(b, a, true)),
+        C::LessThenOrEqualToUnsigned => unsigned_a
+            .zip(unsigned_b)
+            .and_then(|(a, b)| order(a, b, false)),
+        C::GreaterThenOrEqualToUnsigned => unsigned_a
+            .zip(unsigned_b)
+            .and_then(|(a, b)| order(b, a, false)),
+        C::EqualZero => None,
+    };
+    Abstract::boolean(known)
+}
+
+fn arithmetic(kind: &ArithmeticOperation, a: Abstract, b: Abstract, is_64_bit: bool) -> Abstract {
+    use ArithmeticOperation as A;
+    let full = Abstract::full(is_64_bit);
+    let (Some(x), Some(y)) = (a.signed(), b.signed()) else {
+        return full;
+    };
+    match kind {
+        A::Addition => Abstract::bounded(is_64_bit, x.0 + y.0, x.1 + y.1),
+        A::Subtraction => Abstract::bounded(is_64_bit, x.0 - y.1, x.1 - y.0),
+        A::Multiplication => {
+            let corners = [x.0 * y.0, x.0 * y.1, x.1 * y.0, x.1 * y.1];
+            Abstract::bounded(
+                is_64_bit,
+                *corners.iter().min().unwrap_or(&0),
+                *corners.iter().max().unwrap_or(&0),
+            )
+        }
+        A::DivisonSigned if x.0 >= 0 && y.0 >= 1 => {
+            Abstract::bounded(is_64_bit, x.0 / y.1, x.1 / y.0)
+        }
+        A::RemainderSigned if x.0 >= 0 && !b.contains(0) => {
+            let largest = y.0.abs().max(y.1.abs());
+            Abstract::bounded(is_64_bit, 0, x.1.min(largest - 1))
+        }
+        A::DivisonUnsigned | A::RemainderUnsigned => match (a.unsigned(), b.unsigned()) {
+            (Some(x), Some(y)) if y.0 >= 1 => {
+                if *kind == A::DivisonUnsigned {
+                    Abstract::bounded(is_64_bit, x.0 / y.1, x.1 / y.0)
+                } else {
+                    Abstract::bounded(is_64_bit, 0, x.1.min(y.1 - 1))
+                }
+            }
+            _ => full,
+        },
+        _ => full,
+    }
+}
+
+fn bitwise(kind: &BitwiseOperation, a: Abstract, b: Abstract, is_64_bit: bool) -> Abstract {
+    use BitwiseOperation as B;
+    let full = Abstract::full(is_64_bit);
+    let bits = if is_64_bit { 64 } else { 32 };
+    let (Some(x), Some(y)) = (a.signed(), b.signed()) else {
+        return full;
+    };
+    let shift = b.single().map(|value| match value {
+        Value::I64(n) => (n as u64 % bits) as u32,
+        Value::I32(n) => (n as u32 as u64 % bits) as u32,
+        _ => 0,
+    });
+    match (kind, shift) {
+        // Masking with a non-negative number can only clear bits
+        (B::And, _) if x.0 >= 0 || y.0 >= 0 => {
+            let mask = [x, y]
+                .iter()
+                .filter(|range| range.0 >= 0)
+                .map(|range| range.1)
+                .min()
+                .unwrap_or(0);
+            Abstract::bounded(is_64_bit, 0, mask)
+        }
+        (B::Or | B::Xor, _) if x.0 >= 0 && y.0 >= 0 => {
+            let largest = x.1.max(y.1) as u128;
+            Abstract::bounded(is_64_bit, 0, (largest + 1).next_power_of_two() as i128 - 1)
+        }
+        (B::ShiftLeft, Some(k)) if x.0 >= 0 => Abstract::bounded(is_64_bit, x.0 << k, x.1 << k),
+        (B::ShiftRightSigned, Some(k)) => Abstract::bounded(is_64_bit, x.0 >> k, x.1 >> k),
+        (B::ShiftRightUnsigned, Some(0)) => a,
+        (B::ShiftRightUnsigned, Some(k)) => match a.unsigned() {
+            Some(x) => Abstract::bounded(is_64_bit, x.0 >> k, x.1 >> k),
+            None => Abstract::bounded(is_64_bit, 0, ((1i128 << bits) - 1) >> k),
+        },
+        _ => full,
+    }
+}
+
+/// Value of an instruction that pushes one number, from what is known about its operands
+fn result(
+    instruction: &SerializedInstruction,
+    operands: &[Abstract],
+    typ: &SerializableWatType,
+) -> Abstract {
+    // Known operands give a known result, unless it traps
+    let known: Option<Vec<Value>> = operands.iter().map(Abstract::single).collect();
+    if let Some(Ok(value)) = known
+        .filter(|known| operand_count(instruction) == Some(known.len()))
+        .map(|known| evaluate(instruction, &known))
+    {
+        return Abstract::exact(value);
+    }
+    let is_64_bit = *typ == SerializableWatType::I64;
+    match (instruction, operands) {
+        (SerializedInstruction::Const { value, .. }, _) => Abstract::exact(Value::from(*value)),
+        (
+            SerializedInstruction::Comparison {
+                kind: ComparisonOperation::EqualZero,
+                ..
+            },
+            &[a],
+        ) => Abstract::boolean(match a.contains(0) {
+            false => Some(false),
+            true => a.single().map(|_| true),
+        }),
+        (SerializedInstruction::Comparison { kind, .. }, &[a, b]) => compare(kind, a, b),
+        (SerializedInstruction::Arithmetic { kind, .. }, &[a, b]) => {
+            arithmetic(kind, a, b, is_64_bit)
+        }
+        (
+            SerializedInstruction::Bitwise {
+                kind:
+                    BitwiseOperation::CountLeadingZero
+                    | BitwiseOperation::CountTrailingZero
+                    | BitwiseOperation::CountNonZero,
+                ..
+            },
+            _,
+        ) => Abstract::bounded(is_64_bit, 0, if is_64_bit { 64 } else { 32 }),
+        (SerializedInstruction::Bitwise { kind, .. }, &[a, b]) => bitwise(kind, a, b, is_64_bit),
+        (
+            SerializedInstruction::Data {
+                kind: DataInstruction::GetMemorySize,
+                ..
+            },
+            _,
+        ) => Abstract::bounded(false, 0, PAGE_SIZE),
+        (
+            SerializedInstruction::Data {
+                kind: DataInstruction::SetMemorySize,
+                ..
+            },
+            _,
+        ) => Abstract::bounded(false, -1, PAGE_SIZE),
+        _ => Abstract::any(typ),
+    }
+}
+
+/// What is known before an instruction runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct State {
+    stack: Vec<Abstract>,
+    locals: Vec<Abstract>,
+}
+
+impl State {
+    fn combine(&self, other: &State, is_widening: bool) -> State {
+        let combine = |mine: &Vec<Abstract>, theirs: &Vec<Abstract>| {
+            mine.iter()
+                .zip(theirs)
+                .map(|(mine, theirs)| {
+                    let joined = mine.join(*theirs);
+                    if is_widening {
+                        mine.widen(joined)
+                    } else {
+                        joined
+                    }
+                })
+                .collect()
+        };
+        State {
+            stack: combine(&self.stack, &other.stack),
+            locals: combine(&self.locals, &other.locals),
+        }
+    }
+}
+
+struct Analysis<'a> {
+    structure: &'a InterpreterStructure,
+    func: &'a WastFunc,
+    stack_types: &'a StackTypes,
+}
+
+impl Analysis<'_> {
+    fn local(&self, location: &str) -> Option<usize> {
+        local_index(self.func, location)
+    }
+
+    fn global(&self, location: &str) -> Option<Abstract> {
+        let globals = &self.structure.globals;
+        let global = match try_name_to_index(location) {
+            Ok(index) => globals.get(index),
+            Err(name) => globals.iter().find(|g| g.name == name),
+        }?;
+        Some(if global.is_mutable {
+            Abstract::any(&global.typ)
+        } else {
+            Abstract::exact(Value::from(global.val))
+        })
+    }
+
+    fn step(&self, index: usize, mut state: State) -> State {
+        let instruction = &self.func.block.array[index];
+        let effect = &self.stack_types.effects[index];
+        let operands = state
+            .stack
+            .split_off(state.stack.len().saturating_sub(effect.popped.len()));
+        match instruction {
+            SerializedInstruction::Data { kind, location } => {
+                let value = match kind {
+                    DataInstruction::GetLocal => self
+                        .local(location)
+                        .and_then(|l| state.locals.get(l).copied()),
+                    DataInstruction::GetGlobal => self.global(location),
+                    DataInstruction::SetLocal | DataInstruction::TeeLocal => {
+                        if let Some((l, value)) = self.local(location).zip(operands.last()) {
+                            if let Some(stored) = state.locals.get_mut(l) {
+                                *stored = *value;
+                            }
+                        }
+                        operands.last().copied()
+                    }
+                    _ => None,
+                };
+                state.stack.extend(
+                    effect
+                        .pushed
+                        .iter()
+                        .map(|typ| value.unwrap_or_else(|| result(instruction, &operands, typ))),
+                );
+            }
+            // Values stay where they are at the edges of blocks
+            SerializedInstruction::Block { kind, .. } => {
+                let kept = if *kind == BlockKind::If {
+                    &operands[..operands.len().saturating_sub(1)]
+                } else {
+                    &operands[..]
+                };
+                state.stack.extend(kept);
+            }
+            SerializedInstruction::Branch {
+                is_conditional,
+                other_labels,
+                ..
+            } => {
+                let kept = if *is_conditional || !other_labels.is_empty() {
+                    &operands[..operands.len().saturating_sub(1)]
+                } else {
+                    &operands[..]
+                };
+                state.stack.extend(kept);
+            }
+            _ => state.stack.extend(
+                effect
+                    .pushed
+                    .iter()
+                    .map(|typ| result(instruction, &operands, typ)),
+            ),
+        }
+        state
+    }
+
+    /// Problems with the instruction, given what is known before it runs
+    fn check(&self, index: usize, state: &State) -> Option<String> {
+        let stack = &state.stack;
+        let top = |depth: usize| stack.len().checked_sub(depth + 1).map(|i| stack[i]);
+        match &self.func.block.array[index] {
+            SerializedInstruction::Arithmetic {
+                kind:
+                    kind @ (ArithmeticOperation::DivisonSigned
+                    | ArithmeticOperation::DivisonUnsigned
+                    | ArithmeticOperation::RemainderSigned
+                    | ArithmeticOperation::RemainderUnsigned),
+                typ: SerializableWatType::I32 | SerializableWatType::I64,
+            } => {
+                let divisor = top(0)?;
+                let operation = match kind {
+                    ArithmeticOperation::RemainderSigned
+                    | ArithmeticOperation::RemainderUnsigned => "Remainder",
+                    _ => "Division",
+                };
+                match divisor.single() {
+                    Some(value) if value.is_zero() => {
+                        Some(format!("{operation} by zero always traps"))
+                    }
+                    None if !divisor.is_full() && divisor.contains(0) => Some(format!(
+                        "{operation} by zero is possible, the divisor is {divisor}"
+                    )),
+                    _ => None,
+                }
+            }
+            SerializedInstruction::Branch {
+                is_conditional: true,
+                other_labels,
+                ..
+            } if other_labels.is_empty() => {
+                let condition = top(0)?;
+                match condition {
+                    Abstract::Int { .. } if !condition.contains(0) => Some(format!(
+                        "Condition is always true ({condition}), so `br_if` always branches"
+                    )),
+                    _ if condition.single().is_some_and(|value| value.is_zero()) => {
+                        Some("Condition is always false, so `br_if` never branches".to_string())
+                    }
+                    _ => None,
+                }
+            }
+            SerializedInstruction::Memory {
+                location,
+                count,
+                offset,
+                is_storing,
+                ..
+            } => {
+                let address = top(usize::from(*is_storing))?;
+                let size = self.structure.initial_memory_size(location)? as i128;
+                let reach = |address: i128| address + *offset as i128 + count.byte_count() as i128;
+                match address.unsigned() {
+                    Some((low, _)) if reach(low) > size => Some(format!(
+                        "Memory access is always out of bounds, the address is {address} \
+                        and the memory starts with {size} bytes"
+                    )),
+                    Some((_, high)) if !address.is_full() && reach(high) > size => {
+                        Some(format!(
+                            "Memory access can be out of bounds, the address is {address} \
+                            and the memory starts with {size} bytes"
+                        ))
+                    }
+                    None if !address.is_full() => Some(format!(
+                        "Memory access can be out of bounds, the address can be negative ({address})"
+                    )),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// What is known before each instruction, [None] for instructions that are never reached
+    fn run(&self) -> Vec<Option<State>> {
+        let tree = &self.func.block;
+        let count = tree.array.len();
+        let mut states: Vec<Option<State>> = vec![None; count + 1];
+        let mut visits = vec![0; count + 1];
+        states[0] = Some(State {
+            stack: Vec::new(),
+            locals: self
+                .func
+                .info
+                .input
+                .iter()
+                .map(|(_, typ)| Abstract::any(typ))
+                .chain(self.func.locals.iter().map(|(_, typ)| {
+                    Value::default_of(typ).map_or(Abstract::Unknown, Abstract::exact)
+                }))
+                .collect(),
+        });
+        let mut pending = vec![0];
+        while let Some(index) = pending.pop() {
+            if index >= count {
+                continue;
+            }
+            let Some(state) = states[index].clone() else {
+                continue;
+            };
+            let state = self.step(index, state);
+            for (to, kind) in successors(tree, index as u32) {
+                let mut next = state.clone();
+                carry_values(
+                    tree,
+                    self.stack_types,
+                    index as u32,
+                    (to, kind),
+                    &mut next.stack,
+                );
+                let to = to as usize;
+                let combined = match &states[to] {
+                    Some(existing) => existing.combine(&next, visits[to] >= WIDEN_AFTER),
+                    None => next,
+                };
+                if states[to].as_ref() != Some(&combined) {
+                    states[to] = Some(combined);
+                    visits[to] += 1;
+                    pending.push(to);
+                }
+            }
+        }
+        states
+    }
+}
+
+/// Smallest and largest value an integer can have
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct ValueRange {
+    pub min: SerializedNumber,
+    pub max: SerializedNumber,
+}
+
+impl From<Abstract> for Option<ValueRange> {
+    fn from(value: Abstract) -> Self {
+        match value {
+            Abstract::Int {
+                is_64_bit: false,
+                min,
+                max,
+            } => Some(ValueRange {
+                min: (min as i32).into(),
+                max: (max as i32).into(),
+            }),
+            Abstract::Int { min, max, .. } => Some(ValueRange {
+                min: min.into(),
+                max: max.into(),
+            }),
+            Abstract::Unknown => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct FunctionRanges {
+    pub name: String,
+    /// Range of each value on the stack before each instruction, bottom first,
+    /// with one more entry for the end of the function.
+    /// Empty for instructions that are never reached, [None] for values that are not integers.
+    pub stack: Vec<Vec<Option<ValueRange>>>,
+    /// Range of each local (counting parameters first) before each instruction, like the stack
+    pub locals: Vec<Vec<Option<ValueRange>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct RangeReport {
+    pub functions: Vec<FunctionRanges>,
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// What is known before each instruction of a function, and its warnings
+struct FunctionAnalysis {
+    states: Vec<Option<State>>,
+    /// Instruction index and message
+    warnings: Vec<(usize, String)>,
+}
+
+fn analyze(structure: &InterpreterStructure) -> Vec<FunctionAnalysis> {
+    let mut validator = Validator::new(structure);
+    structure
+        .func
+        .iter()
+        .map(|func| {
+            let stack_types = validator.infer_stack_types(
+                &func.block.array,
+                &func.info.input,
+                &func.locals,
+                &func.info.output,
+            );
+            let analysis = Analysis {
+                structure,
+                func,
+                stack_types: &stack_types,
+            };
+            let states = analysis.run();
+            let warnings = states
+                .iter()
+                .enumerate()
+                .take(func.block.array.len())
+                .filter_map(|(index, state)| Some((index, analysis.check(index, state.as_ref()?)?)))
+                .collect();
+            FunctionAnalysis { states, warnings }
+        })
+        .collect()
+}
+
+/// Warnings of every function in text order
+fn to_diagnostics(analyses: &[FunctionAnalysis], source: &SourceMap) -> Vec<Diagnostic> {
+    let mut warnings: Vec<Diagnostic> = analyses
+        .iter()
+        .zip(&source.functions)
+        .flat_map(|(analysis, func_source)| {
+            analysis.warnings.iter().filter_map(|(index, message)| {
+                let span = func_source.instructions.get(*index)?.clone();
+                Some(Diagnostic::warning(span, message.clone()))
+            })
+        })
+        .collect();
+    warnings.sort_by_key(|warning| warning.span.start);
+    warnings
+}
+
+/// Warnings for a module that passed validation, in text order
+pub fn range_warnings(structure: &InterpreterStructure, source: &SourceMap) -> Vec<Diagnostic> {
+    to_diagnostics(&analyze(structure), source)
+}
+
+/// Range of every integer before every instruction, with warnings about the ranges
+pub fn value_ranges(text: &str) -> WatResult<RangeReport> {
+    let structure = inner_transform(text)?;
+    let source = SourceMap::try_new(text)?;
+    let analyses = analyze(&structure);
+    let to_ranges = |values: &Vec<Abstract>| values.iter().map(|v| (*v).into()).collect();
+    let functions = analyses
+        .iter()
+        .zip(&structure.func)
+        .map(|(analysis, func)| FunctionRanges {
+            name: func.name().unwrap_or_default(),
+            stack: analysis
+                .states
+                .iter()
+                .map(|state| {
+                    state
+                        .as_ref()
+                        .map(|s| to_ranges(&s.stack))
+                        .unwrap_or_default()
+                })
+                .collect(),
+            locals: analysis
+                .states
+                .iter()
+                .map(|state| {
+                    state
+                        .as_ref()
+                        .map(|s| to_ranges(&s.locals))
+                        .unwrap_or_default()
+                })
+                .collect(),
+        })
+        .collect();
+    Ok(RangeReport {
+        functions,
+        warnings: to_diagnostics(&analyses, &source),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::module_with_func;
+
+    /// Each warning as the instruction it points to and its message
+    fn warnings(body: &str) -> Vec<(String, String)> {
+        let text = module_with_func("(memory $m 1)", "(param i32)", body);
+        value_ranges(&text)
+            .unwrap()
+            .warnings
+            .into_iter()
+            .map(|warning| {
+                let span = warning.span.start as usize..warning.span.end as usize;
+                (text[span].to_string(), warning.message)
+            })
+            .collect()
+    }
+
+    fn warning(instruction: &str, message: &str) -> (String, String) {
+        (instruction.to_string(), message.to_string())
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(
+            warnings("i32.const 10 i32.const 0 i32.div_s drop"),
+            [warning("i32.div_s", "Division by zero always traps")]
+        );
+        assert_eq!(
+            warnings("i32.const 10 local.get 0 i32.const 3 i32.and i32.rem_u drop"),
+            [warning(
+                "i32.rem_u",
+                "Remainder by zero is possible, the divisor is 0 to 3"
+            )]
+        );
+        assert_eq!(
+            warnings("i32.const 10 local.get 0 i32.const 1 i32.or i32.div_u drop"),
+            []
+        );
+    }
+
+    #[test]
+    fn constant_conditions() {
+        assert_eq!(
+            warnings("block i32.const 2 br_if 0 end block i32.const 0 br_if 0 end"),
+            [
+                warning(
+                    "br_if 0",
+                    "Condition is always true (2), so `br_if` always branches"
+                ),
+                warning(
+                    "br_if 0",
+                    "Condition is always false, so `br_if` never branches"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn memory_out_of_bounds() {
+        assert_eq!(
+            warnings("i32.const 65534 i32.load $m drop"),
+            [warning(
+                "i32.load $m",
+                "Memory access is always out of bounds, the address is 65534 \
+                and the memory starts with 65536 bytes"
+            )]
+        );
+        assert_eq!(
+            warnings("local.get 0 i32.const 255 i32.and i32.const 65280 i32.add i32.load $m drop"),
+            [warning(
+                "i32.load $m",
+                "Memory access can be out of bounds, the address is 65280 to 65535 \
+                and the memory starts with 65536 bytes"
+            )]
+        );
+    }
+
+    #[test]
+    fn ranges_follow_each_instruction() {
+        let report = value_ranges(&module_with_func(
+            "",
+            "(param i32) (result i32)",
+            "local.get 0 i32.const 15 i32.and",
+        ))
+        .unwrap();
+        let ranges = &report.functions[0].stack;
+        assert_eq!(
+            ranges[3],
+            [Some(ValueRange {
+                min: 0_i32.into(),
+                max: 15_i32.into(),
+            })]
+        );
+        // Nothing is known about parameters
+        assert_eq!(
+            report.functions[0].locals[0],
+            [Some(ValueRange {
+                min: i32::MIN.into(),
+                max: i32::MAX.into(),
+            })]
+        );
+    }
+}
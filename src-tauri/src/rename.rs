@@ -0,0 +1,220 @@
+//! Rename a `$name` everywhere it refers to the same item.
+//!
+//! Names are resolved the same way the validator does: functions, globals and memory are
+//! looked up across the whole module, locals within their function, and labels by the
+//! innermost enclosing block with that name.
+
+use std::{ops::Range, ptr};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    error::{WatError, WatResult},
+    source::{is_id_char, name_at, tokens, Definition, NameKind, SourceMap},
+    NumLocationKind,
+};
+
+/// Replace the text in the span
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct TextEdit {
+    pub span: Range<u32>,
+    pub text: String,
+}
+
+/// A `$name` written in the text and the kind of item it names
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NameToken {
+    span: Range<u32>,
+    kind: NameKind,
+}
+
+/// Every `$name` whose kind is known from the keyword before it
+fn name_tokens(text: &str) -> Vec<NameToken> {
+    let tokens = tokens(text);
+    let word = |span: &Range<u32>| &text[span.start as usize..span.end as usize];
+    tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, span)| word(span).starts_with('$'))
+        .filter_map(|(i, span)| {
+            // Skip back over earlier names, like the labels of `br_table`
+            let keyword = tokens[..i]
+                .iter()
+                .rev()
+                .map(word)
+                .find(|w| !w.starts_with('$'))?;
+            Some(NameToken {
+                span: span.clone(),
+                kind: NameKind::written_after(keyword)?,
+            })
+        })
+        .collect()
+}
+
+/// Definition the name refers to
+fn resolve<'s>(source: &'s SourceMap, text: &str, token: &NameToken) -> Option<&'s Definition> {
+    let name = &text[token.span.start as usize + 1..token.span.end as usize];
+    let by_name = |def: &&Definition| def.name == name;
+    match token.kind {
+        NameKind::Function => source
+            .functions
+            .iter()
+            .filter_map(|f| f.name.as_ref())
+            .find(by_name),
+        NameKind::Global => source.globals.iter().find(by_name),
+        NameKind::Memory => source.memory.iter().find(by_name),
+        NameKind::Local => source
+            .function_at(token.span.start)?
+            .1
+            .locals
+            .iter()
+            .find(by_name),
+        NameKind::Label => {
+            let (_, function) = source.function_at(token.span.start)?;
+            if let Some(def) = function.labels.values().find(|def| def.span == token.span) {
+                return Some(def);
+            }
+            let index = function.instruction_at(token.span.start)?;
+            function
+                .labels_in_scope(text, index)
+                .into_iter()
+                .find(by_name)
+        }
+    }
+}
+
+fn resolution_error(kind: NameKind, name: &str) -> WatError {
+    match kind {
+        NameKind::Function => WatError::name_resolution_error(name, NumLocationKind::Function),
+        NameKind::Global => WatError::name_resolution_error(name, NumLocationKind::Global),
+        NameKind::Memory => WatError::name_resolution_error(name, NumLocationKind::Memory),
+        NameKind::Local => WatError::local_resolution_error(name),
+        NameKind::Label => WatError::label_resolution_error(name),
+    }
+}
+
+/// Check nothing else visible from the uses of the item already has the new name
+fn check_collision(
+    source: &SourceMap,
+    text: &str,
+    token: &NameToken,
+    target: &Definition,
+    new_name: &str,
+) -> WatResult<()> {
+    let is_taken = |def: &Definition| def.name == new_name && !ptr::eq(def, target);
+    let function = source.function_at(token.span.start).map(|(_, f)| f);
+    let collides = match token.kind {
+        NameKind::Function => source
+            .functions
+            .iter()
+            .filter_map(|f| f.name.as_ref())
+            .any(is_taken),
+        NameKind::Global => source.globals.iter().any(is_taken),
+        NameKind::Memory => source.memory.iter().any(is_taken),
+        NameKind::Local => function.is_some_and(|f| f.locals.iter().any(is_taken)),
+        // A label with the same name around or inside the block would change what branches target
+        NameKind::Label => function.is_some_and(|f| {
+            let contains = |outer: usize, inner: usize| {
+                f.block_end(text, outer)
+                    .is_some_and(|end| (outer..=end).contains(&inner))
+            };
+            f.labels
+                .iter()
+                .find(|(_, def)| ptr::eq(*def, target))
+                .is_some_and(|(block, _)| {
+                    f.labels.iter().any(|(other, def)| {
+                        is_taken(def) && (contains(*block, *other) || contains(*other, *block))
+                    })
+                })
+        }),
+    };
+    if collides {
+        Err(WatError::duplicate_name_error(&format!("${new_name}")))
+    } else {
+        Ok(())
+    }
+}
+
+/// Edits renaming the `$name` at the offset and everything referring to the same item
+pub fn rename_at(text: &str, offset: u32, new_name: &str) -> WatResult<Vec<TextEdit>> {
+    let new_name = new_name.strip_prefix('$').unwrap_or(new_name);
+    if new_name.is_empty() || !new_name.bytes().all(|b| is_id_char(&b)) {
+        return Err(WatError::invalid_name_error(new_name));
+    }
+    let source = SourceMap::try_new(text)?;
+    let span = name_at(text, offset).ok_or_else(WatError::nothing_to_rename_error)?;
+    let names = name_tokens(text);
+    let token = names
+        .iter()
+        .find(|token| token.span == span)
+        .ok_or_else(WatError::nothing_to_rename_error)?;
+    let target = resolve(&source, text, token).ok_or_else(|| {
+        resolution_error(
+            token.kind,
+            &text[span.start as usize + 1..span.end as usize],
+        )
+    })?;
+    check_collision(&source, text, token, target, new_name)?;
+    Ok(names
+        .iter()
+        .filter(|other| {
+            other.kind == token.kind
+                && resolve(&source, text, other).is_some_and(|def| ptr::eq(def, target))
+        })
+        .map(|other| TextEdit {
+            span: other.span.clone(),
+            text: format!("${new_name}"),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Text after renaming the name at the first occurrence of `at`
+    fn renamed(text: &str, at: &str, new_name: &str) -> WatResult<String> {
+        let offset = text.find(at).unwrap() as u32;
+        let mut edits = rename_at(text, offset, new_name)?;
+        edits.sort_by_key(|edit| std::cmp::Reverse(edit.span.start));
+        let mut text = text.to_string();
+        for edit in edits {
+            text.replace_range(edit.span.start as usize..edit.span.end as usize, &edit.text);
+        }
+        Ok(text)
+    }
+
+    #[test]
+    fn renames_every_use_of_the_same_item() {
+        let text = "(module (func $f (param $x i32) local.get $x drop) (func $g (local $x i32) local.get $x call $f drop) (export \"f\" (func $f)))";
+        assert_eq!(
+            renamed(text, "$f", "main").unwrap(),
+            text.replace("$f", "$main")
+        );
+        // The other function's `$x` is a different local
+        let locals = renamed(text, "$x i32)", "y").unwrap();
+        assert!(locals.starts_with("(module (func $f (param $y i32) local.get $y drop) (func $g (local $x i32) local.get $x"));
+    }
+
+    #[test]
+    fn labels_rename_by_scope() {
+        let text = "(module (func block $l block $l br $l end br $l end))";
+        let inner = renamed(text, "$l br", "inner").unwrap();
+        assert_eq!(
+            inner,
+            "(module (func block $l block $inner br $inner end br $l end))"
+        );
+    }
+
+    #[test]
+    fn collisions_and_bad_names_are_errors() {
+        let text = "(module (global $a i32 (i32.const 0)) (global $b i32 (i32.const 1)))";
+        assert!(renamed(text, "$a", "b").is_err());
+        assert!(renamed(text, "$a", "no space").is_err());
+        assert!(rename_at(text, 0, "c").is_err());
+        assert!(renamed(text, "$a", "$c")
+            .unwrap()
+            .contains("(global $c i32"));
+    }
+}
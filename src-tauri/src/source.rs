@@ -0,0 +1,604 @@
+//! Locations of functions, names and instructions in the WAT text.
+//!
+//! [wast] throws away the position of each instruction once an [wast::core::Expression] is parsed,
+//! so the module is walked a second time here, unfolding instructions the same way
+//! `wast`'s expression parser does while recording the span of each one.
+
+use std::{collections::HashMap, ops::Range};
+
+use wast::{
+    core::{
+        FunctionType, InlineExport, InlineImport, Instruction, LocalParser, ModuleField, TypeUse,
+    },
+    kw,
+    parser::{self, Parse, ParseBuffer, Parser},
+    token::{Id, NameAnnotation, Span},
+};
+
+use crate::error::{WatError, WatResult};
+
+/// A named item and where its name is written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub name: String,
+    pub span: Range<u32>,
+}
+
+impl Definition {
+    /// Only the start of the name is known from the parser,
+    /// the end is filled in by [SourceMap::try_new]
+    fn from_id(id: &Id) -> Self {
+        let start = offset(id.span());
+        Self {
+            name: id.name().to_string(),
+            span: start..start,
+        }
+    }
+
+    fn finish(&mut self, text: &str) {
+        self.span.end = token_end(text, self.span.start as usize);
+    }
+}
+
+/// Where a function and its parts are written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionSource {
+    /// The whole `(func ...)` field
+    pub span: Range<u32>,
+    /// The `func` keyword, used when the function has no name
+    pub keyword: Range<u32>,
+    pub name: Option<Definition>,
+    /// Named parameters and locals
+    pub locals: Vec<Definition>,
+    /// Labels of blocks, by the index of the block instruction
+    pub labels: HashMap<usize, Definition>,
+    /// One span per instruction, matching the instruction array of the function
+    pub instructions: Vec<Range<u32>>,
+}
+
+impl FunctionSource {
+    /// First word of the instruction, like `i32.add` or `block`
+    ///
+    /// Implicit `end`s of folded blocks are written as `)`.
+    pub fn mnemonic<'t>(&self, text: &'t str, index: usize) -> &'t str {
+        self.instructions
+            .get(index)
+            .and_then(|span| text.get(span.start as usize..span.end as usize))
+            .and_then(|ins| ins.split_whitespace().next())
+            .unwrap_or_default()
+    }
+
+    /// Labels of the blocks around an instruction, innermost first
+    pub fn labels_in_scope<'s>(&'s self, text: &str, index: usize) -> Vec<&'s Definition> {
+        let mut blocks = Vec::new();
+        for i in 0..index.min(self.instructions.len()) {
+            match self.mnemonic(text, i) {
+                "block" | "loop" | "if" => blocks.push(i),
+                "end" | ")" => {
+                    blocks.pop();
+                }
+                _ => {}
+            }
+        }
+        blocks
+            .iter()
+            .rev()
+            .filter_map(|i| self.labels.get(i))
+            .collect()
+    }
+
+    /// Index of the instruction closing the block that starts at the index,
+    /// [None] if no block starts there or it is never closed
+    pub fn block_end(&self, text: &str, index: usize) -> Option<usize> {
+        let mut depth = 0_usize;
+        for i in index..self.instructions.len() {
+            match self.mnemonic(text, i) {
+                "block" | "loop" | "if" => depth += 1,
+                "end" | ")" => {
+                    depth = depth.checked_sub(1)?;
+                    if depth == 0 {
+                        return Some(i);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Index of the innermost instruction whose span contains the offset
+    pub fn instruction_at(&self, offset: u32) -> Option<usize> {
+        self.instructions
+            .iter()
+            .enumerate()
+            .filter(|(_, span)| span.start <= offset && offset < span.end)
+            .min_by_key(|(_, span)| span.end - span.start)
+            .map(|(index, _)| index)
+    }
+}
+
+/// Spans for every function, global and memory of a module, in declaration order
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SourceMap {
+    pub functions: Vec<FunctionSource>,
+    pub globals: Vec<Definition>,
+    pub memory: Vec<Definition>,
+    /// The `global` keyword of every global, including the ones without a name
+    pub global_keywords: Vec<Range<u32>>,
+}
+
+impl SourceMap {
+    pub fn try_new(text: &str) -> WatResult<Self> {
+        let buffer = ParseBuffer::new(text).map_err(WatError::parsing_error)?;
+        let mut map = parser::parse::<SourceMapParser>(&buffer)
+            .map_err(WatError::parsing_error)?
+            .0;
+        // Only the start of each name is known from the parser
+        for function in &mut map.functions {
+            function
+                .name
+                .iter_mut()
+                .chain(function.locals.iter_mut())
+                .chain(function.labels.values_mut())
+                .for_each(|def| def.finish(text));
+        }
+        map.globals
+            .iter_mut()
+            .chain(map.memory.iter_mut())
+            .for_each(|def| def.finish(text));
+        Ok(map)
+    }
+
+    /// The function whose text contains the offset
+    pub fn function_at(&self, offset: u32) -> Option<(usize, &FunctionSource)> {
+        self.functions
+            .iter()
+            .enumerate()
+            .find(|(_, f)| f.span.start <= offset && offset < f.span.end)
+    }
+
+    /// Find where the `$name` written at the offset is defined
+    pub fn definition_at(&self, text: &str, offset: u32) -> Option<&Definition> {
+        let name_span = name_at(text, offset)?;
+        let name = &text[name_span.start as usize + 1..name_span.end as usize];
+        let by_name = |def: &&Definition| def.name == name;
+        let function_names = || self.functions.iter().filter_map(|f| f.name.as_ref());
+        if let Some((_, function)) = self.function_at(offset) {
+            // Already on a definition
+            if let Some(def) = function
+                .name
+                .iter()
+                .chain(function.locals.iter())
+                .chain(function.labels.values())
+                .find(|def| def.span == name_span)
+            {
+                return Some(def);
+            }
+            // Otherwise the instruction using the name says what kind of item it is
+            if let Some(index) = function.instruction_at(offset) {
+                let mnemonic = function.mnemonic(text, index);
+                return match NameKind::used_by(mnemonic)? {
+                    NameKind::Local => function.locals.iter().find(by_name),
+                    NameKind::Label => function
+                        .labels_in_scope(text, index)
+                        .into_iter()
+                        .find(by_name),
+                    NameKind::Function => function_names().find(by_name),
+                    NameKind::Global => self.globals.iter().find(by_name),
+                    NameKind::Memory => self.memory.iter().find(by_name),
+                };
+            }
+        }
+        // Module level uses, like exports and start, or a definition itself
+        function_names()
+            .chain(self.globals.iter())
+            .chain(self.memory.iter())
+            .find(|def| def.span == name_span)
+            .or_else(|| {
+                function_names()
+                    .chain(self.globals.iter())
+                    .chain(self.memory.iter())
+                    .find(by_name)
+            })
+    }
+}
+
+/// The kinds of items that can be named with `$`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameKind {
+    Function,
+    Global,
+    Memory,
+    Local,
+    Label,
+}
+
+impl NameKind {
+    /// Kind of name used by an instruction, if it uses one
+    pub fn used_by(mnemonic: &str) -> Option<Self> {
+        match mnemonic {
+            "call" | "return_call" | "ref.func" => Some(NameKind::Function),
+            "br" | "br_if" | "br_table" => Some(NameKind::Label),
+            m if m.starts_with("local.") => Some(NameKind::Local),
+            m if m.starts_with("global.") => Some(NameKind::Global),
+            m if m.starts_with("memory.") || m.contains(".load") || m.contains(".store") => {
+                Some(NameKind::Memory)
+            }
+            _ => None,
+        }
+    }
+
+    /// Kind of name written after a keyword, either defining the item or using it
+    pub fn written_after(keyword: &str) -> Option<Self> {
+        match keyword {
+            "func" | "start" => Some(NameKind::Function),
+            "global" => Some(NameKind::Global),
+            "memory" => Some(NameKind::Memory),
+            "param" | "local" => Some(NameKind::Local),
+            "block" | "loop" | "if" | "else" | "end" => Some(NameKind::Label),
+            mnemonic => Self::used_by(mnemonic),
+        }
+    }
+}
+
+/// Spans of every token, skipping whitespace and comments
+pub fn tokens(text: &str) -> Vec<Range<u32>> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match &bytes[i..] {
+            [b, ..] if b.is_ascii_whitespace() => i += 1,
+            [b';', b';', ..] => i = text[i..].find('\n').map_or(bytes.len(), |end| i + end),
+            [b'(', b';', ..] => i = text[i..].find(";)").map_or(bytes.len(), |end| i + end + 2),
+            _ => match token_end(text, i) as usize {
+                // A stray `;` or other whitespace, skip the character
+                end if end == i => i += text[i..].chars().next().map_or(1, char::len_utf8),
+                end => {
+                    tokens.push(i as u32..end as u32);
+                    i = end;
+                }
+            },
+        }
+    }
+    tokens
+}
+
+/// Characters that can be part of a `$name` or keyword
+pub fn is_id_char(b: &u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-./:<=>?@\\^_`|~".contains(b)
+}
+
+/// Span of the `$name` at or right before the offset
+pub fn name_at(text: &str, offset: u32) -> Option<Range<u32>> {
+    let bytes = text.as_bytes();
+    let offset = (offset as usize).min(bytes.len());
+    let start = bytes[..offset]
+        .iter()
+        .rposition(|b| !is_id_char(b))
+        .map_or(0, |i| i + 1);
+    let end = bytes[offset..]
+        .iter()
+        .position(|b| !is_id_char(b))
+        .map_or(bytes.len(), |i| offset + i);
+    (bytes.get(start) == Some(&b'$') && end > start + 1).then_some(start as u32..end as u32)
+}
+
+/// End of the token starting at `start`
+fn token_end(text: &str, start: usize) -> u32 {
+    let rest = &text[start.min(text.len())..];
+    let len = if rest.starts_with(['(', ')']) {
+        1
+    } else if rest.starts_with('"') {
+        // Skip over escaped characters inside strings
+        let mut escaped = false;
+        rest.char_indices()
+            .skip(1)
+            .find(|(_, c)| {
+                let is_end = !escaped && *c == '"';
+                escaped = !escaped && *c == '\\';
+                is_end
+            })
+            .map_or(rest.len(), |(i, _)| i + 1)
+    } else {
+        rest.find(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == ';')
+            .unwrap_or(rest.len())
+    };
+    (start + len) as u32
+}
+
+fn offset(span: Span) -> u32 {
+    span.offset() as u32
+}
+
+/// Where the last parsed token ends
+///
+/// `wast` reports the position right after the last token as its "previous span"
+fn end_of_previous(parser: Parser<'_>) -> u32 {
+    offset(parser.prev_span())
+}
+
+/// Wrapper to hook into [wast]'s parser
+struct SourceMapParser(SourceMap);
+
+impl<'a> Parse<'a> for SourceMapParser {
+    fn parse(parser: Parser<'a>) -> wast::parser::Result<Self> {
+        let _r = parser.register_annotation("name");
+        let mut map = SourceMap::default();
+        if parser.peek2::<kw::module>()? {
+            parser.parens(|p| {
+                p.parse::<kw::module>()?;
+                p.parse::<Option<Id>>()?;
+                p.parse::<Option<NameAnnotation>>()?;
+                parse_fields(p, &mut map)
+            })?;
+        } else {
+            parse_fields(parser, &mut map)?;
+        }
+        Ok(SourceMapParser(map))
+    }
+}
+
+fn parse_fields(parser: Parser<'_>, map: &mut SourceMap) -> wast::parser::Result<()> {
+    while !parser.is_empty() {
+        let start = offset(parser.cur_span());
+        let function = parser.parens(|p| {
+            if p.peek::<kw::func>()? {
+                return parse_func(p, start);
+            }
+            match p.parse::<ModuleField>()? {
+                ModuleField::Global(g) => {
+                    let keyword = offset(g.span);
+                    map.global_keywords
+                        .push(keyword..keyword + "global".len() as u32);
+                    map.globals.extend(g.id.as_ref().map(Definition::from_id))
+                }
+                ModuleField::Memory(m) => map.memory.extend(m.id.as_ref().map(Definition::from_id)),
+                _ => {}
+            }
+            Ok(None)
+        })?;
+        if let Some(mut function) = function {
+            function.span.end = end_of_previous(parser);
+            map.functions.push(function);
+        }
+    }
+    Ok(())
+}
+
+/// Same order as [wast::core::Func]'s parser, returns [None] for imported functions
+fn parse_func(parser: Parser<'_>, start: u32) -> wast::parser::Result<Option<FunctionSource>> {
+    let keyword = offset(parser.parse::<kw::func>()?.0);
+    let id = parser.parse::<Option<Id>>()?;
+    parser.parse::<Option<NameAnnotation>>()?;
+    parser.parse::<InlineExport>()?;
+    if parser.parse::<Option<InlineImport>>()?.is_some() {
+        parser.parse::<TypeUse<FunctionType>>()?;
+        return Ok(None);
+    }
+    let ty = parser.parse::<TypeUse<FunctionType>>()?;
+    let mut locals: Vec<Definition> = ty
+        .inline
+        .iter()
+        .flat_map(|f| f.params.iter())
+        .filter_map(|(id, _, _)| id.as_ref().map(Definition::from_id))
+        .collect();
+    while parser.peek2::<kw::local>()? {
+        parser.parens(|p| {
+            locals.extend(
+                p.parse::<LocalParser>()?
+                    .locals
+                    .iter()
+                    .filter_map(|l| l.id.as_ref().map(Definition::from_id)),
+            );
+            Ok(())
+        })?;
+    }
+    let mut function = FunctionSource {
+        span: start..start,
+        keyword: keyword..keyword + "func".len() as u32,
+        name: id.as_ref().map(Definition::from_id),
+        locals,
+        labels: HashMap::new(),
+        instructions: Vec::new(),
+    };
+    ExpressionSpans::default().parse(parser, &mut function)?;
+    Ok(Some(function))
+}
+
+/// An instruction waiting to be placed, with the label it defines
+type Pending = (Range<u32>, Option<Definition>);
+
+/// Mirror of the nesting levels in `wast`'s expression parser
+enum Level {
+    /// Folded instruction placed after its operands, or [None] for an implicit `end`
+    EndWith(Option<Pending>),
+    If(IfState),
+    IfArm,
+}
+
+enum IfState {
+    Clause(Pending),
+    Then,
+    Else,
+}
+
+enum Paren {
+    None,
+    Left,
+    Right,
+}
+
+#[derive(Default)]
+struct ExpressionSpans {
+    stack: Vec<Level>,
+}
+
+impl ExpressionSpans {
+    fn parse(
+        &mut self,
+        parser: Parser<'_>,
+        function: &mut FunctionSource,
+    ) -> wast::parser::Result<()> {
+        while !parser.is_empty() || !self.stack.is_empty() {
+            let paren_start = offset(parser.cur_span());
+            match self.paren(parser)? {
+                Paren::None => {
+                    let pending = parse_instruction(parser)?.0;
+                    push(function, pending);
+                }
+                Paren::Left => {
+                    if self.handle_if_lparen(parser, function)? {
+                        continue;
+                    }
+                    let (pending, instruction) = parse_instruction(parser)?;
+                    match instruction {
+                        BlockLike::Block => {
+                            push(function, pending);
+                            self.stack.push(Level::EndWith(None));
+                        }
+                        BlockLike::If => self.stack.push(Level::If(IfState::Clause(pending))),
+                        BlockLike::Try => return Err(parser.error("try blocks are not supported")),
+                        BlockLike::Other => self.stack.push(Level::EndWith(Some(pending))),
+                    }
+                }
+                Paren::Right => match self.stack.pop() {
+                    Some(Level::EndWith(Some(pending))) => push(function, pending),
+                    // Implicit `end` is placed on the closing parenthesis
+                    Some(Level::EndWith(None)) | Some(Level::If(_)) => {
+                        push(function, (paren_start..paren_start + 1, None))
+                    }
+                    Some(Level::IfArm) | None => {}
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn paren(&self, parser: Parser<'_>) -> wast::parser::Result<Paren> {
+        parser.step(|cursor| {
+            Ok(match cursor.lparen()? {
+                Some(rest) => (Paren::Left, rest),
+                None if self.stack.is_empty() => (Paren::None, cursor),
+                None => match cursor.rparen()? {
+                    Some(rest) => (Paren::Right, rest),
+                    None => (Paren::None, cursor),
+                },
+            })
+        })
+    }
+
+    fn handle_if_lparen(
+        &mut self,
+        parser: Parser<'_>,
+        function: &mut FunctionSource,
+    ) -> wast::parser::Result<bool> {
+        let Some(Level::If(state)) = self.stack.last_mut() else {
+            return Ok(false);
+        };
+        match state {
+            IfState::Clause(pending) => {
+                if !parser.peek::<kw::then>()? {
+                    return Ok(false);
+                }
+                parser.parse::<kw::then>()?;
+                push(function, pending.clone());
+                *state = IfState::Then;
+            }
+            IfState::Then => {
+                let start = offset(parser.parse::<kw::r#else>()?.0);
+                push(function, (start..start + "else".len() as u32, None));
+                *state = IfState::Else;
+            }
+            IfState::Else => {
+                return Err(parser.error("unexpected token: too many payloads inside of `(if)`"))
+            }
+        }
+        self.stack.push(Level::IfArm);
+        Ok(true)
+    }
+}
+
+/// How an instruction nests when folded
+enum BlockLike {
+    Block,
+    If,
+    Try,
+    Other,
+}
+
+fn parse_instruction(parser: Parser<'_>) -> wast::parser::Result<(Pending, BlockLike)> {
+    let start = offset(parser.cur_span());
+    let instruction = parser.parse::<Instruction>()?;
+    let span = start..end_of_previous(parser);
+    Ok(match instruction {
+        Instruction::Block(bt) | Instruction::Loop(bt) => (
+            (span, bt.label.as_ref().map(Definition::from_id)),
+            BlockLike::Block,
+        ),
+        Instruction::If(bt) => (
+            (span, bt.label.as_ref().map(Definition::from_id)),
+            BlockLike::If,
+        ),
+        Instruction::Try(_) => ((span, None), BlockLike::Try),
+        _ => ((span, None), BlockLike::Other),
+    })
+}
+
+fn push(function: &mut FunctionSource, (span, label): Pending) {
+    if let Some(label) = label {
+        function.labels.insert(function.instructions.len(), label);
+    }
+    function.instructions.push(span);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spanned<'t>(text: &'t str, span: &Range<u32>) -> &'t str {
+        &text[span.start as usize..span.end as usize]
+    }
+
+    #[test]
+    fn folded_instructions_map_to_their_text() {
+        let text =
+            "(module (func $f (param $a i32) (result i32) (i32.add (local.get $a) (i32.const 1))))";
+        let map = SourceMap::try_new(text).unwrap();
+        let function = &map.functions[0];
+        let structure = crate::build_structure(text).unwrap();
+        assert_eq!(
+            function.instructions.len(),
+            structure.functions()[0].instructions().len()
+        );
+        let mnemonics: Vec<_> = (0..3).map(|i| function.mnemonic(text, i)).collect();
+        assert_eq!(mnemonics, ["local.get", "i32.const", "i32.add"]);
+        assert!(spanned(text, &function.instructions[0]).starts_with("local.get $a"));
+        assert_eq!(spanned(text, &function.name.as_ref().unwrap().span), "$f");
+        assert_eq!(spanned(text, &function.locals[0].span), "$a");
+    }
+
+    #[test]
+    fn uses_resolve_to_definitions() {
+        let text = "(module (global $g i32 (i32.const 0)) (func $f (local $g i32) local.get $g global.get $g drop drop))";
+        let map = SourceMap::try_new(text).unwrap();
+        let local_use = text.find("local.get $g").unwrap() as u32 + 11;
+        let global_use = text.find("global.get $g").unwrap() as u32 + 12;
+        let local = map.definition_at(text, local_use).unwrap();
+        let global = map.definition_at(text, global_use).unwrap();
+        assert_eq!(local, &map.functions[0].locals[0]);
+        assert_eq!(global, &map.globals[0]);
+        assert_eq!(map.definition_at(text, 0), None);
+    }
+
+    #[test]
+    fn block_end_only_closes_blocks() {
+        let text = "(module (func block $a nop end drop))";
+        let map = SourceMap::try_new(text).unwrap();
+        let function = &map.functions[0];
+        assert_eq!(function.block_end(text, 0), Some(2));
+        // `nop` starts no block, so its `end` belongs to something else
+        assert_eq!(function.block_end(text, 1), None);
+        assert_eq!(function.block_end(text, 3), None);
+    }
+}
@@ -0,0 +1,982 @@
+//! Find arguments that make an exported function trap, by running it on symbols instead of numbers.
+//!
+//! Every path through the function (and the functions it calls) keeps the conditions it took
+//! as expressions over the arguments. At an instruction that can trap, a small solver
+//! looks for arguments meeting those conditions and the trap's own.
+//!
+//! Exploration is bounded: loops are unrolled until the step limit, and the solver searches
+//! among likely values instead of proving anything, so it can miss traps but never makes one up.
+//! Memory contents are not modelled, values loaded from memory are picked by the solver like arguments.
+
+use std::{ops::Range, rc::Rc};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    callgraph::resolve_function,
+    cfg::{carry_values, successors, EdgeKind},
+    dead_code::local_index,
+    error::{TrapKind, WatResult},
+    helper::SerializedNumber,
+    inner_transform,
+    instruction::SerializedInstruction,
+    interpreter::{evaluate, operand_count, Value},
+    marker::{
+        ArithmeticOperation, BitwiseOperation, BlockKind, ComparisonOperation, DataInstruction,
+        SerializableWatType, SimpleInstruction,
+    },
+    source::SourceMap,
+    validator::{try_name_to_index, StackTypes},
+    InterpreterStructure, NumLocationKind,
+};
+
+/// Instructions run over all paths of one export before giving up
+const MAX_STEPS: usize = 5_000;
+/// Paths waiting to be explored at once
+const MAX_PATHS: usize = 256;
+const MAX_CALL_DEPTH: usize = 8;
+/// Assignments the solver tries before giving up
+const MAX_TRIES: usize = 2_000;
+
+/// A value in terms of the symbols of a path
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Const(Value),
+    /// Index into the symbols of the path
+    Symbol(usize),
+    /// An instruction counted by [operand_count] applied to its operands
+    Apply(SerializedInstruction, Vec<Rc<Expr>>),
+}
+
+impl Expr {
+    /// Apply the instruction, computing it right away when every operand is known
+    fn apply(instruction: &SerializedInstruction, operands: Vec<Rc<Expr>>) -> Rc<Expr> {
+        // Keep counters changed in a loop as one addition, instead of a chain as long as the loop
+        if let Some(sum) = Self::fold_addition(instruction, &operands) {
+            return sum;
+        }
+        let known: Option<Vec<Value>> = operands
+            .iter()
+            .map(|operand| match **operand {
+                Expr::Const(value) => Some(value),
+                _ => None,
+            })
+            .collect();
+        match known.map(|known| evaluate(instruction, &known)) {
+            Some(Ok(value)) => Rc::new(Expr::Const(value)),
+            _ => Rc::new(Expr::Apply(instruction.clone(), operands)),
+        }
+    }
+
+    /// `x - c` as `x + -c`, and `(x + a) + b` as `x + (a + b)`
+    fn fold_addition(
+        instruction: &SerializedInstruction,
+        operands: &[Rc<Expr>],
+    ) -> Option<Rc<Expr>> {
+        let SerializedInstruction::Arithmetic {
+            kind: kind @ (ArithmeticOperation::Addition | ArithmeticOperation::Subtraction),
+            typ: typ @ (SerializableWatType::I32 | SerializableWatType::I64),
+        } = instruction
+        else {
+            return None;
+        };
+        let [x, Expr::Const(c)] = operands.iter().map(|o| &**o).collect::<Vec<_>>()[..] else {
+            return None;
+        };
+        let add = arithmetic(ArithmeticOperation::Addition, *typ);
+        let c = evaluate(&arithmetic(*kind, *typ), &[value_of(*typ, 0), *c]).ok()?;
+        let (x, c) = match x {
+            Expr::Const(_) => return None,
+            Expr::Apply(inner, inner_operands) if *inner == add => match &inner_operands[..] {
+                [y, d] => match **d {
+                    Expr::Const(d) => (y.clone(), evaluate(&add, &[d, c]).ok()?),
+                    _ => (operands[0].clone(), c),
+                },
+                _ => (operands[0].clone(), c),
+            },
+            _ => (operands[0].clone(), c),
+        };
+        // Nothing to fold, so stop here to not loop forever
+        if *kind == ArithmeticOperation::Addition && Rc::ptr_eq(&x, &operands[0]) {
+            return None;
+        }
+        Some(Rc::new(Expr::Apply(add, vec![x, Rc::new(Expr::Const(c))])))
+    }
+
+    fn evaluate(&self, model: &[Value]) -> Option<Value> {
+        match self {
+            Expr::Const(value) => Some(*value),
+            Expr::Symbol(index) => model.get(*index).copied(),
+            Expr::Apply(instruction, operands) => {
+                let operands = operands
+                    .iter()
+                    .map(|operand| operand.evaluate(model))
+                    .collect::<Option<Vec<_>>>()?;
+                evaluate(instruction, &operands).ok()
+            }
+        }
+    }
+
+    fn visit(&self, f: &mut impl FnMut(&Expr)) {
+        f(self);
+        if let Expr::Apply(_, operands) = self {
+            operands.iter().for_each(|operand| operand.visit(f));
+        }
+    }
+}
+
+fn comparison(kind: ComparisonOperation, typ: SerializableWatType) -> SerializedInstruction {
+    SerializedInstruction::Comparison { kind, typ }
+}
+
+fn arithmetic(kind: ArithmeticOperation, typ: SerializableWatType) -> SerializedInstruction {
+    SerializedInstruction::Arithmetic { kind, typ }
+}
+
+/// A condition on the symbols, true when the expression is not 0
+type Constraint = (Rc<Expr>, bool);
+
+/// Where the solver may pick a symbol's value from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SymbolSource {
+    Argument,
+    /// Result of an instruction that is not modelled, like a memory load
+    Unknown,
+}
+
+/// Small random number generator, seeded so results are the same every run
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+fn value_of(typ: SerializableWatType, bits: i64) -> Value {
+    match typ {
+        SerializableWatType::I64 => Value::I64(bits),
+        SerializableWatType::F32 => Value::F32(bits as f32),
+        SerializableWatType::F64 => Value::F64(bits as f64),
+        SerializableWatType::FuncRef => Value::FuncRef(u32::try_from(bits).ok()),
+        SerializableWatType::ExternRef => Value::ExternRef(u32::try_from(bits).ok()),
+        _ => Value::I32(bits as i32),
+    }
+}
+
+fn bits_of(value: Value) -> i64 {
+    match value {
+        Value::I32(n) => n as i64,
+        Value::I64(n) => n,
+        Value::F32(n) => n as i64,
+        Value::F64(n) => n as i64,
+        Value::FuncRef(index) | Value::ExternRef(index) => index.map_or(-1, i64::from),
+    }
+}
+
+/// Searches for symbol values that meet every constraint
+struct Solver<'a> {
+    types: &'a [SerializableWatType],
+    constraints: &'a [Constraint],
+    /// Values to start from, usually what met the constraints before the last one was added
+    start: &'a [Value],
+}
+
+impl Solver<'_> {
+    fn unmet(&self, model: &[Value]) -> usize {
+        self.constraints
+            .iter()
+            .filter(|(expr, expected)| {
+                expr.evaluate(model)
+                    .map_or(true, |value| value.is_zero() == *expected)
+            })
+            .count()
+    }
+
+    /// Value that makes the expression equal the target, going back through simple arithmetic
+    fn invert(expr: &Expr, target: Value, found: &mut Vec<(usize, Value)>) {
+        let Expr::Apply(instruction, operands) = expr else {
+            if let Expr::Symbol(index) = expr {
+                found.push((*index, target));
+            }
+            return;
+        };
+        let typ = target.typ();
+        let undo = |kind, a: Value, b: Value| evaluate(&arithmetic(kind, typ), &[a, b]).ok();
+        let [a, b] = &operands[..] else {
+            return;
+        };
+        let (inner, known, known_is_first) = match (&**a, &**b) {
+            (Expr::Const(c), _) => (b, *c, true),
+            (_, Expr::Const(c)) => (a, *c, false),
+            _ => return,
+        };
+        let undone = match instruction {
+            SerializedInstruction::Arithmetic {
+                kind: ArithmeticOperation::Addition,
+                ..
+            } => undo(ArithmeticOperation::Subtraction, target, known),
+            SerializedInstruction::Arithmetic {
+                kind: ArithmeticOperation::Subtraction,
+                ..
+            } if known_is_first => undo(ArithmeticOperation::Subtraction, known, target),
+            SerializedInstruction::Arithmetic {
+                kind: ArithmeticOperation::Subtraction,
+                ..
+            } => undo(ArithmeticOperation::Addition, target, known),
+            SerializedInstruction::Bitwise {
+                kind: BitwiseOperation::Xor,
+                ..
+            } => evaluate(instruction, &[target, known]).ok(),
+            _ => None,
+        };
+        if let Some(undone) = undone {
+            Self::invert(inner, undone, found);
+        }
+    }
+
+    /// Values worth trying for each symbol, from the constants in the constraints
+    fn candidates(&self) -> Vec<Vec<Value>> {
+        let mut constants: Vec<i64> = vec![0, 1, -1, 2, i32::MIN as i64, i32::MAX as i64];
+        let mut direct = Vec::new();
+        for (expr, expected) in self.constraints {
+            expr.visit(&mut |e| {
+                if let Expr::Const(value) = e {
+                    let n = bits_of(*value);
+                    constants.extend([n, n.wrapping_add(1), n.wrapping_sub(1), n.wrapping_neg()]);
+                }
+            });
+            match (&**expr, expected) {
+                (Expr::Apply(SerializedInstruction::Comparison { kind, typ }, operands), _) => {
+                    match (kind, &operands[..]) {
+                        (ComparisonOperation::EqualZero, [x]) if *expected => {
+                            Self::invert(x, value_of(*typ, 0), &mut direct);
+                        }
+                        (_, [x, y]) => {
+                            for (x, y) in [(x, y), (y, x)] {
+                                if let Expr::Const(c) = **y {
+                                    for step in [0, 1, -1] {
+                                        let target = value_of(*typ, bits_of(c).wrapping_add(step));
+                                        Self::invert(x, target, &mut direct);
+                                    }
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                // Conditions are `i32`s, which are false when 0
+                (_, false) => Self::invert(expr, Value::I32(0), &mut direct),
+                _ => {}
+            }
+        }
+        constants.sort();
+        constants.dedup();
+        self.types
+            .iter()
+            .enumerate()
+            .map(|(index, typ)| {
+                direct
+                    .iter()
+                    .filter(|(symbol, _)| *symbol == index)
+                    .map(|(_, value)| value_of(*typ, bits_of(*value)))
+                    .chain(constants.iter().map(|n| value_of(*typ, *n)))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Symbol values meeting every constraint, if the search finds them
+    fn solve(&self) -> Option<Vec<Value>> {
+        let mut model: Vec<Value> = self
+            .types
+            .iter()
+            .enumerate()
+            .map(|(index, typ)| self.start.get(index).copied().unwrap_or(value_of(*typ, 0)))
+            .collect();
+        let mut unmet = self.unmet(&model);
+        if unmet == 0 {
+            return Some(model);
+        }
+        let candidates = self.candidates();
+        let mut random = XorShift(0x9E37_79B9_7F4A_7C15);
+        let mut tries = 0;
+        while unmet > 0 && tries < MAX_TRIES {
+            // Change one symbol at a time while that gets closer
+            let mut improved = false;
+            for (symbol, values) in candidates.iter().enumerate() {
+                for value in values {
+                    let mut next = model.clone();
+                    next[symbol] = *value;
+                    let next_unmet = self.unmet(&next);
+                    tries += 1;
+                    if next_unmet == 0 {
+                        return Some(next);
+                    }
+                    if next_unmet < unmet {
+                        (model, unmet, improved) = (next, next_unmet, true);
+                    }
+                }
+            }
+            // Stuck, so start again somewhere else
+            if !improved {
+                for (symbol, values) in candidates.iter().enumerate() {
+                    let pick = random.next();
+                    model[symbol] = match pick % 3 {
+                        0 => values[(pick >> 8) as usize % values.len()],
+                        1 => value_of(self.types[symbol], (pick >> 8) as i64 % 64 - 32),
+                        _ => value_of(self.types[symbol], (pick >> 8) as i64),
+                    };
+                }
+                unmet = self.unmet(&model);
+                tries += 1;
+            }
+        }
+        (unmet == 0).then_some(model)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct TrapInput {
+    /// Export that was called
+    pub export: String,
+    pub args: Vec<SerializedNumber>,
+    pub kind: TrapKind,
+    /// Function the trap happens in, which can be one called by the export
+    pub function: String,
+    pub index: u32,
+    pub span: Option<Range<u32>>,
+    /// The path depends on values loaded from memory, which the solver picked too
+    pub reads_memory: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct TrapReport {
+    pub traps: Vec<TrapInput>,
+    /// Exports with paths left unexplored because of the limits
+    pub incomplete: Vec<String>,
+}
+
+/// A function call on a path
+#[derive(Debug, Clone)]
+struct Frame {
+    function: usize,
+    pc: u32,
+    locals: Vec<Rc<Expr>>,
+    stack: Vec<Rc<Expr>>,
+}
+
+/// One way through the export, with everything it assumed so far
+#[derive(Debug, Clone, Default)]
+struct Path {
+    frames: Vec<Frame>,
+    globals: Vec<Rc<Expr>>,
+    symbols: Vec<(SerializableWatType, SymbolSource)>,
+    constraints: Vec<Constraint>,
+    /// Symbol values meeting the constraints, when last solved
+    model: Vec<Value>,
+}
+
+impl Path {
+    fn symbol(&mut self, typ: SerializableWatType, source: SymbolSource) -> Rc<Expr> {
+        self.symbols.push((typ, source));
+        Rc::new(Expr::Symbol(self.symbols.len() - 1))
+    }
+
+    fn solve(&self, extra: &[Constraint]) -> Option<Vec<Value>> {
+        let types: Vec<_> = self.symbols.iter().map(|(typ, _)| *typ).collect();
+        let constraints: Vec<_> = self.constraints.iter().chain(extra).cloned().collect();
+        Solver {
+            types: &types,
+            constraints: &constraints,
+            start: &self.model,
+        }
+        .solve()
+    }
+
+    fn reads_memory(&self, extra: &[Constraint]) -> bool {
+        let mut reads = false;
+        for (expr, _) in self.constraints.iter().chain(extra) {
+            expr.visit(&mut |e| {
+                if let Expr::Symbol(index) = e {
+                    reads |= self.symbols[*index].1 == SymbolSource::Unknown;
+                }
+            });
+        }
+        reads
+    }
+}
+
+/// What a step did to the path
+enum Step {
+    /// Follow the edges out of the instruction, with the condition they test
+    Edges(Option<Rc<Expr>>),
+    /// Continue at the frame's program counter, after a call or return
+    Continue,
+    /// The path cannot go on
+    Stop,
+}
+
+struct Explorer<'a> {
+    structure: &'a InterpreterStructure,
+    stack_types: Vec<StackTypes>,
+    source: Option<SourceMap>,
+    export: String,
+    args: usize,
+    traps: Vec<TrapInput>,
+    steps: usize,
+    is_complete: bool,
+}
+
+impl Explorer<'_> {
+    /// Record a trap if the path can reach it with the extra constraints
+    fn trap(&mut self, path: &Path, kind: TrapKind, extra: &[Constraint]) {
+        let Some(frame) = path.frames.last() else {
+            return;
+        };
+        let (function, index) = (frame.function, frame.pc);
+        if self
+            .traps
+            .iter()
+            .any(|t| t.kind == kind && t.index == index && t.function == self.name(function))
+        {
+            return;
+        }
+        let Some(model) = path.solve(extra) else {
+            return;
+        };
+        self.traps.push(TrapInput {
+            export: self.export.clone(),
+            args: model[..self.args].iter().map(|v| (*v).into()).collect(),
+            kind,
+            function: self.name(function),
+            index,
+            span: self.source.as_ref().and_then(|source| {
+                source
+                    .functions
+                    .get(function)?
+                    .instructions
+                    .get(index as usize)
+                    .cloned()
+            }),
+            reads_memory: path.reads_memory(extra),
+        });
+    }
+
+    fn name(&self, function: usize) -> String {
+        self.structure.func[function].name().unwrap_or_default()
+    }
+
+    fn global(&self, location: &str) -> Option<usize> {
+        match try_name_to_index(location) {
+            Ok(index) => Some(index),
+            Err(name) => self.structure.globals.iter().position(|g| g.name == name),
+        }
+    }
+
+    /// Run the instruction at the top frame's program counter
+    fn step(&mut self, path: &mut Path) -> Step {
+        let structure = self.structure;
+        let Some(frame) = path.frames.last() else {
+            return Step::Stop;
+        };
+        let function = frame.function;
+        let func = &structure.func[function];
+        let index = frame.pc as usize;
+        let instruction = &func.block.array[index];
+        let effect = &self.stack_types[function].effects[index];
+        match instruction {
+            SerializedInstruction::Simple(SimpleInstruction::Unreachable) => {
+                self.trap(path, TrapKind::Unreachable, &[]);
+                return Step::Stop;
+            }
+            SerializedInstruction::Block {
+                kind: BlockKind::If,
+                ..
+            }
+            | SerializedInstruction::Branch {
+                is_conditional: true,
+                ..
+            } => {
+                let frame = path.frames.last_mut().expect("checked above");
+                return Step::Edges(frame.stack.pop());
+            }
+            SerializedInstruction::Branch { other_labels, .. } if !other_labels.is_empty() => {
+                let frame = path.frames.last_mut().expect("checked above");
+                return Step::Edges(frame.stack.pop());
+            }
+            SerializedInstruction::Simple(SimpleInstruction::Drop) => {
+                path.frames.last_mut().expect("checked above").stack.pop();
+            }
+            // Values stay where they are at the edges of blocks
+            SerializedInstruction::Simple(_)
+            | SerializedInstruction::Block { .. }
+            | SerializedInstruction::Branch { .. } => {}
+            // Tables are not supported, so `call_indirect` cannot be followed
+            SerializedInstruction::CallIndirect { .. } => return Step::Stop,
+            SerializedInstruction::Call { index: callee } => {
+                let Some(callee) = resolve_function(&structure.func, callee) else {
+                    return Step::Stop;
+                };
+                if path.frames.len() >= MAX_CALL_DEPTH {
+                    self.is_complete = false;
+                    return Step::Stop;
+                }
+                let callee_func = &structure.func[callee];
+                let frame = path.frames.last_mut().expect("checked above");
+                frame.pc += 1;
+                let args = frame.stack.split_off(
+                    frame
+                        .stack
+                        .len()
+                        .saturating_sub(callee_func.info.input.len()),
+                );
+                let locals = args
+                    .into_iter()
+                    .chain(callee_func.locals.iter().map(|(_, typ)| {
+                        Rc::new(Expr::Const(Value::default_of(typ).unwrap_or(Value::I32(0))))
+                    }))
+                    .collect();
+                path.frames.push(Frame {
+                    function: callee,
+                    pc: 0,
+                    locals,
+                    stack: Vec::new(),
+                });
+                return Step::Continue;
+            }
+            SerializedInstruction::Data { kind, location } => {
+                let local = local_index(func, location);
+                let global = self.global(location);
+                let value = match kind {
+                    DataInstruction::GetLocal => {
+                        let frame = path.frames.last().expect("checked above");
+                        local.and_then(|l| frame.locals.get(l).cloned())
+                    }
+                    DataInstruction::GetGlobal => global.and_then(|g| path.globals.get(g).cloned()),
+                    DataInstruction::SetLocal | DataInstruction::TeeLocal => {
+                        let frame = path.frames.last_mut().expect("checked above");
+                        let value = frame.stack.pop();
+                        if let Some((stored, value)) = local
+                            .and_then(|l| frame.locals.get_mut(l))
+                            .zip(value.clone())
+                        {
+                            *stored = value;
+                        }
+                        value.filter(|_| *kind == DataInstruction::TeeLocal)
+                    }
+                    DataInstruction::SetGlobal => {
+                        let frame = path.frames.last_mut().expect("checked above");
+                        let value = frame.stack.pop();
+                        if let Some((stored, value)) =
+                            global.and_then(|g| path.globals.get_mut(g)).zip(value)
+                        {
+                            *stored = value;
+                        }
+                        None
+                    }
+                    DataInstruction::GetMemorySize | DataInstruction::SetMemorySize => {
+                        let frame = path.frames.last_mut().expect("checked above");
+                        frame
+                            .stack
+                            .truncate(frame.stack.len().saturating_sub(effect.popped.len()));
+                        Some(path.symbol(SerializableWatType::I32, SymbolSource::Unknown))
+                    }
+                };
+                if let Some(value) = value {
+                    path.frames
+                        .last_mut()
+                        .expect("checked above")
+                        .stack
+                        .push(value);
+                }
+            }
+            SerializedInstruction::Memory {
+                location,
+                typ,
+                count,
+                offset,
+                is_storing,
+                ..
+            } => {
+                let frame = path.frames.last_mut().expect("checked above");
+                let operands = frame
+                    .stack
+                    .split_off(frame.stack.len().saturating_sub(effect.popped.len()));
+                let Some(address) = operands.first().cloned() else {
+                    return Step::Stop;
+                };
+                let size = structure.initial_memory_size(location).unwrap_or(0);
+                let reach = *offset as u64 + count.byte_count() as u64;
+                // In bounds while the address is at most the last place the access fits
+                let last = size
+                    .checked_sub(reach)
+                    .filter(|last| *last <= u32::MAX as u64);
+                let Some(last) = last else {
+                    self.trap(path, TrapKind::OutOfBoundsMemory, &[]);
+                    return Step::Stop;
+                };
+                let out_of_bounds = Expr::apply(
+                    &comparison(
+                        ComparisonOperation::GreaterThenUnsigned,
+                        SerializableWatType::I32,
+                    ),
+                    vec![
+                        address,
+                        Rc::new(Expr::Const(Value::I32(last as u32 as i32))),
+                    ],
+                );
+                if !matches!(*out_of_bounds, Expr::Const(v) if v.is_zero()) {
+                    self.trap(
+                        path,
+                        TrapKind::OutOfBoundsMemory,
+                        &[(out_of_bounds.clone(), true)],
+                    );
+                }
+                if matches!(*out_of_bounds, Expr::Const(v) if !v.is_zero()) {
+                    return Step::Stop;
+                }
+                path.constraints.push((out_of_bounds, false));
+                if !is_storing {
+                    let loaded = path.symbol(*typ, SymbolSource::Unknown);
+                    path.frames
+                        .last_mut()
+                        .expect("checked above")
+                        .stack
+                        .push(loaded);
+                }
+            }
+            SerializedInstruction::Const { value, .. } => {
+                let frame = path.frames.last_mut().expect("checked above");
+                frame.stack.push(Rc::new(Expr::Const(Value::from(*value))));
+            }
+            SerializedInstruction::DefaultString(_) => return Step::Stop,
+            _ => {
+                let count = operand_count(instruction).unwrap_or_default();
+                let frame = path.frames.last_mut().expect("checked above");
+                let operands = frame
+                    .stack
+                    .split_off(frame.stack.len().saturating_sub(count));
+                if let Some(cont) = self.check_division(path, instruction, &operands) {
+                    return cont;
+                }
+                let result = Expr::apply(instruction, operands);
+                path.frames
+                    .last_mut()
+                    .expect("checked above")
+                    .stack
+                    .push(result);
+            }
+        }
+        Step::Edges(None)
+    }
+
+    /// Look for division by zero and overflow, then assume neither happens
+    fn check_division(
+        &mut self,
+        path: &mut Path,
+        instruction: &SerializedInstruction,
+        operands: &[Rc<Expr>],
+    ) -> Option<Step> {
+        let SerializedInstruction::Arithmetic {
+            kind:
+                kind @ (ArithmeticOperation::DivisonSigned
+                | ArithmeticOperation::DivisonUnsigned
+                | ArithmeticOperation::RemainderSigned
+                | ArithmeticOperation::RemainderUnsigned),
+            typ: typ @ (SerializableWatType::I32 | SerializableWatType::I64),
+        } = instruction
+        else {
+            return None;
+        };
+        let [dividend, divisor] = operands else {
+            return None;
+        };
+        let constant = |n: i64| Rc::new(Expr::Const(value_of(*typ, n)));
+        let is_zero = Expr::apply(
+            &comparison(ComparisonOperation::EqualZero, *typ),
+            vec![divisor.clone()],
+        );
+        match *is_zero {
+            Expr::Const(v) if !v.is_zero() => {
+                self.trap(path, TrapKind::DivideByZero, &[]);
+                return Some(Step::Stop);
+            }
+            Expr::Const(_) => {}
+            _ => {
+                self.trap(path, TrapKind::DivideByZero, &[(is_zero.clone(), true)]);
+                path.constraints.push((is_zero, false));
+            }
+        }
+        if *kind == ArithmeticOperation::DivisonSigned {
+            let min = if *typ == SerializableWatType::I64 {
+                i64::MIN
+            } else {
+                i32::MIN as i64
+            };
+            let equal = comparison(ComparisonOperation::Equal, *typ);
+            let and = SerializedInstruction::Bitwise {
+                kind: BitwiseOperation::And,
+                is_64_bit: false,
+            };
+            let overflow = Expr::apply(
+                &and,
+                vec![
+                    Expr::apply(&equal, vec![dividend.clone(), constant(min)]),
+                    Expr::apply(&equal, vec![divisor.clone(), constant(-1)]),
+                ],
+            );
+            match *overflow {
+                Expr::Const(v) if !v.is_zero() => {
+                    self.trap(path, TrapKind::IntegerOverflow, &[]);
+                    return Some(Step::Stop);
+                }
+                Expr::Const(_) => {}
+                _ => {
+                    self.trap(path, TrapKind::IntegerOverflow, &[(overflow.clone(), true)]);
+                    path.constraints.push((overflow, false));
+                }
+            }
+        }
+        None
+    }
+
+    /// Take every way out of the instruction that the path can still meet
+    fn follow(&mut self, mut path: Path, condition: Option<Rc<Expr>>, pending: &mut Vec<Path>) {
+        let Some(frame) = path.frames.last() else {
+            return;
+        };
+        let function = frame.function;
+        let from = frame.pc;
+        let tree = &self.structure.func[function].block;
+        let other_labels = match &tree.array[from as usize] {
+            SerializedInstruction::Branch { other_labels, .. } => other_labels.len(),
+            _ => 0,
+        };
+        let edges = successors(tree, from);
+        let is_fork = edges.len() > 1;
+        for (to, kind) in edges {
+            let mut next = if is_fork {
+                path.clone()
+            } else {
+                std::mem::take(&mut path)
+            };
+            let frame = next.frames.last_mut().expect("checked above");
+            if let Some(condition) = &condition {
+                let typ = SerializableWatType::I32;
+                let constant = |n: i64| Rc::new(Expr::Const(value_of(typ, n)));
+                let constraint = match kind {
+                    EdgeKind::Branch | EdgeKind::Then => Some((condition.clone(), true)),
+                    EdgeKind::NotTaken | EdgeKind::Else => Some((condition.clone(), false)),
+                    EdgeKind::Table(i) => Some((
+                        Expr::apply(
+                            &comparison(ComparisonOperation::Equal, typ),
+                            vec![condition.clone(), constant(i as i64)],
+                        ),
+                        true,
+                    )),
+                    EdgeKind::TableDefault => Some((
+                        Expr::apply(
+                            &comparison(ComparisonOperation::GreaterThenOrEqualToUnsigned, typ),
+                            vec![condition.clone(), constant(other_labels as i64)],
+                        ),
+                        true,
+                    )),
+                    EdgeKind::Next | EdgeKind::Return => None,
+                };
+                if let Some((expr, expected)) = constraint {
+                    match *expr {
+                        Expr::Const(v) if v.is_zero() == expected => continue,
+                        Expr::Const(_) => {}
+                        _ => next.constraints.push((expr, expected)),
+                    }
+                }
+            }
+            carry_values(
+                tree,
+                &self.stack_types[function],
+                from,
+                (to, kind),
+                &mut frame.stack,
+            );
+            frame.pc = to;
+            // Drop paths the solver cannot find a way into
+            if is_fork {
+                match next.solve(&[]) {
+                    Some(model) => next.model = model,
+                    None => continue,
+                }
+            }
+            if pending.len() >= MAX_PATHS {
+                self.is_complete = false;
+                return;
+            }
+            pending.push(next);
+        }
+    }
+
+    /// Explore every path of the export, collecting the traps found
+    fn explore(&mut self, function: usize) {
+        let func = &self.structure.func[function];
+        let mut path = Path {
+            frames: Vec::new(),
+            globals: self
+                .structure
+                .globals
+                .iter()
+                .map(|g| Rc::new(Expr::Const(Value::from(g.val))))
+                .collect(),
+            symbols: Vec::new(),
+            constraints: Vec::new(),
+            model: Vec::new(),
+        };
+        let mut locals: Vec<Rc<Expr>> = func
+            .info
+            .input
+            .iter()
+            .map(|(_, typ)| path.symbol(*typ, SymbolSource::Argument))
+            .collect();
+        locals.extend(
+            func.locals.iter().map(|(_, typ)| {
+                Rc::new(Expr::Const(Value::default_of(typ).unwrap_or(Value::I32(0))))
+            }),
+        );
+        path.frames.push(Frame {
+            function,
+            pc: 0,
+            locals,
+            stack: Vec::new(),
+        });
+        let mut pending = vec![path];
+        while let Some(mut path) = pending.pop() {
+            while let Some(frame) = path.frames.last_mut() {
+                let func = &self.structure.func[frame.function];
+                // Fell off the end, so return to the caller
+                if frame.pc as usize >= func.block.array.len() {
+                    let results = frame
+                        .stack
+                        .split_off(frame.stack.len().saturating_sub(func.info.output.len()));
+                    path.frames.pop();
+                    match path.frames.last_mut() {
+                        Some(caller) => caller.stack.extend(results),
+                        None => break,
+                    }
+                    continue;
+                }
+                if self.steps >= MAX_STEPS {
+                    self.is_complete = false;
+                    return;
+                }
+                self.steps += 1;
+                match self.step(&mut path) {
+                    Step::Continue => {}
+                    Step::Stop => break,
+                    Step::Edges(condition) => {
+                        self.follow(path, condition, &mut pending);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Arguments that make each export trap, for every trap the search can reach
+pub fn find_traps(text: &str) -> WatResult<TrapReport> {
+    let structure = inner_transform(text)?;
+    let stack_types = structure.stack_types();
+    let source = SourceMap::try_new(text).ok();
+    let mut exports: Vec<(&String, usize)> = structure
+        .exported
+        .iter()
+        .filter(|(_, (kind, _))| *kind == NumLocationKind::Function)
+        .map(|(name, (_, index))| (name, *index as usize))
+        .collect();
+    exports.sort();
+    let mut report = TrapReport {
+        traps: Vec::new(),
+        incomplete: Vec::new(),
+    };
+    for (export, function) in exports {
+        let mut explorer = Explorer {
+            structure: &structure,
+            stack_types: stack_types.clone(),
+            source: source.clone(),
+            export: export.clone(),
+            args: structure.func[function].info.input.len(),
+            traps: Vec::new(),
+            steps: 0,
+            is_complete: true,
+        };
+        explorer.explore(function);
+        report.traps.extend(explorer.traps);
+        if !explorer.is_complete {
+            report.incomplete.push(export.clone());
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{invoke_function, Invocation};
+
+    /// Kind of each trap found, after checking its arguments really make the export trap that way
+    fn trap_kinds(text: &str) -> Vec<TrapKind> {
+        let report = find_traps(text).unwrap();
+        for trap in &report.traps {
+            let args: Vec<String> = trap.args.iter().map(|arg| arg.to_string()).collect();
+            match invoke_function(text, &trap.export, &args).unwrap() {
+                Invocation::Trapped(found) => assert_eq!(found.kind, trap.kind),
+                Invocation::Returned(_) => panic!("{} returned with {args:?}", trap.export),
+            }
+        }
+        report.traps.iter().map(|trap| trap.kind).collect()
+    }
+
+    #[test]
+    fn arguments_reach_division_traps() {
+        let kinds = trap_kinds(
+            r#"(module (func (export "div") (param i32 i32) (result i32)
+                local.get 0 local.get 1 i32.div_s))"#,
+        );
+        assert!(kinds.contains(&TrapKind::DivideByZero));
+        assert!(kinds.contains(&TrapKind::IntegerOverflow));
+    }
+
+    #[test]
+    fn conditions_on_the_path_are_solved() {
+        let text = r#"(module (func (export "guarded") (param i32) (result i32)
+            local.get 0 i32.const 1234 i32.eq
+            if unreachable end
+            i32.const 0))"#;
+        assert_eq!(trap_kinds(text), [TrapKind::Unreachable]);
+        let trap = &find_traps(text).unwrap().traps[0];
+        assert_eq!(trap.args, [SerializedNumber::from(1234)]);
+        assert_eq!(trap.function, "0");
+        assert_eq!(trap.index, 4);
+        assert!(!trap.reads_memory);
+    }
+
+    #[test]
+    fn safe_functions_have_no_traps() {
+        let report = find_traps(
+            r#"(module (func (export "safe") (param i32) (result i32)
+                i32.const 10 local.get 0 i32.const 1 i32.or i32.div_u))"#,
+        )
+        .unwrap();
+        assert_eq!(report.traps, []);
+        assert!(report.incomplete.is_empty());
+    }
+
+    #[test]
+    fn endless_loops_are_incomplete() {
+        let report = find_traps(r#"(module (func (export "spin") loop br 0 end))"#).unwrap();
+        assert_eq!(report.incomplete, ["spin"]);
+    }
+}
@@ -0,0 +1,6 @@
+//! Module text shared by the unit tests
+
+/// A module with one function, after other fields like memories and globals
+pub fn module_with_func(fields: &str, signature: &str, body: &str) -> String {
+    format!("(module {fields} (func {signature} {body}))")
+}
@@ -2,7 +2,10 @@
 //!
 //! Based loosely on algorithm described in <https://webassembly.github.io/spec/core/appendix/algorithm.html>
 
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
 
 use crate::{
     error::{WatError, WatResult},
@@ -51,18 +54,34 @@ impl<Value> ValueMapping<Value> {
     /// Get a possible mutable reference to value by its name
     #[allow(dead_code)]
     pub fn get_mut_by_name(&mut self, name: &str) -> Option<&mut Value> {
-        let Some(index) = self.mapping.get(name) else {
-            return None;
-        };
+        let index = self.mapping.get(name)?;
         self.get_mut_by_index(*index)
     }
 
+    /// Names with their index, in index order
+    pub fn names(&self) -> Vec<(&str, usize)> {
+        let mut names: Vec<_> = self
+            .mapping
+            .iter()
+            .map(|(name, index)| (name.as_str(), *index))
+            .collect();
+        names.sort_by_key(|(_, index)| *index);
+        names
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
     /// Get a possoble reference to value
     ///
     /// Will try to convert the key to an index,
     /// if success, then get by that index,
     /// otherwise, get by name
-    #[allow(dead_code)]
     pub fn get(&self, key: &str) -> Option<&Value> {
         str::parse::<usize>(key).map_or_else(
             |_err| self.get_by_name(key),
@@ -130,8 +149,11 @@ pub struct Validator {
     control_stack: Vec<ControlFrame>,
     /// Global values mapping name to (mutablitiy, type)
     globals: ValueMapping<(bool, SerializableWatType)>,
-    memory_names: HashSet<String>,
+    /// Memories by index or name
+    memories: ValueMapping<()>,
     functions: ValueMapping<(Vec<SerializableWatType>, Vec<SerializableWatType>)>,
+    /// Types popped and pushed by the current instruction
+    effect: StackEffect,
 }
 
 impl Validator {
@@ -144,7 +166,11 @@ impl Validator {
                 .iter()
                 .map(|g| (g.name.clone(), (g.is_mutable, g.typ)))
                 .collect(),
-            memory_names: structure.memory.iter().map(|m| m.name.clone()).collect(),
+            memories: structure
+                .memory
+                .iter()
+                .map(|m| ((!m.name.is_empty()).then(|| m.name.clone()), ()))
+                .collect(),
             functions: structure
                 .func
                 .iter()
@@ -158,6 +184,7 @@ impl Validator {
                     )
                 })
                 .collect(),
+            effect: StackEffect::default(),
         }
     }
 
@@ -169,12 +196,27 @@ impl Validator {
 
     /// Push type onto the value stack
     fn push_val(&mut self, typ: SerializableWatType) {
+        self.effect.pushed.push(typ);
         self.value_stack.push(typ);
     }
 
     /// Pop value from value stack, return value or empty stack error
-    fn pop_val(&mut self) -> WatResult<SerializableWatType> {
-        self.value_stack.pop().ok_or(WatError::empty_stack(1))
+    ///
+    /// Returns [None] if the current block is unreachable and has nothing left on the stack,
+    /// since any type could be there.
+    fn pop_val(&mut self) -> WatResult<Option<SerializableWatType>> {
+        if let Some(frame) = self.control_stack.last() {
+            if self.value_stack.len() == frame.height {
+                return if frame.unreachable {
+                    Ok(None)
+                } else {
+                    Err(WatError::empty_stack(1))
+                };
+            }
+        }
+        let typ = self.value_stack.pop().ok_or(WatError::empty_stack(1))?;
+        self.effect.popped.insert(0, typ);
+        Ok(Some(typ))
     }
 
     /// Pop the expected type from the value stack, returning value or error
@@ -182,11 +224,11 @@ impl Validator {
         &mut self,
         expected: &SerializableWatType,
     ) -> WatResult<SerializableWatType> {
-        let actual = self.pop_val()?;
-        if &actual != expected {
-            Err(WatError::unexpected_type(expected, &actual))
-        } else {
-            Ok(actual)
+        match self.pop_val()? {
+            Some(actual) if &actual != expected => {
+                Err(WatError::unexpected_type(expected, &actual))
+            }
+            _ => Ok(*expected),
         }
     }
 
@@ -211,7 +253,7 @@ impl Validator {
     fn push_control(
         &mut self,
         opcode: marker::BlockKind,
-        label: &String,
+        label: &str,
         input: Vec<SerializableWatType>,
         output: Vec<SerializableWatType>,
     ) {
@@ -220,29 +262,29 @@ impl Validator {
             label: if label.is_empty() {
                 None
             } else {
-                Some(label.clone())
+                Some(label.to_string())
             },
-            start_types: input,
+            start_types: input.clone(),
             end_types: output,
             height: self.value_stack.len(),
             unreachable: false,
         };
         self.control_stack.push(frame);
+        self.push_vals(&input);
     }
 
     fn pop_control(&mut self) -> WatResult<ControlFrame> {
-        let Some(frame) = self.control_stack.pop() else {
+        let Some(frame) = self.control_stack.last().cloned() else {
             return Err(WatError::empty_stack(1));
         };
         self.pop_vals(&frame.end_types)?;
         if self.value_stack.len() != frame.height {
-            Err(WatError::not_enough_on_stack(
-                frame.height,
-                self.value_stack.len(),
-            ))
-        } else {
-            Ok(frame)
+            return Err(WatError::extra_items_on_stack_error(
+                &self.value_stack[frame.height..],
+            ));
         }
+        self.control_stack.pop();
+        Ok(frame)
     }
 
     fn label_types(&self, frame: ControlFrame) -> Vec<SerializableWatType> {
@@ -255,7 +297,7 @@ impl Validator {
 
     fn unreachable(&mut self) {
         if let Some(top_control) = self.control_stack.last_mut() {
-            self.value_stack.reserve(top_control.height);
+            self.value_stack.truncate(top_control.height);
             top_control.unreachable = true;
         }
     }
@@ -279,6 +321,7 @@ impl Validator {
                 }
                 SimpleInstruction::Return => {
                     self.pop_vals(output)?;
+                    self.unreachable();
                     Ok(())
                 }
             },
@@ -318,6 +361,7 @@ impl Validator {
                 default_label,
                 other_labels,
                 is_conditional,
+                ..
             } => {
                 let default_frame = self.try_get_control_frame(default_label)?;
                 let default_vals = self.label_types(default_frame);
@@ -350,11 +394,17 @@ impl Validator {
                     Ok(())
                 }
             }
-            SerializedInstruction::Call { index, inout } => {
-                if self.functions.get(index).is_some() {
+            SerializedInstruction::CallIndirect { type_use, .. } => {
+                self.expected_pop_val(&SerializableWatType::I32)?;
+                self.pop_vals(&type_use.get_input_types())?;
+                self.push_vals(&type_use.output);
+                Ok(())
+            }
+            SerializedInstruction::Call { index } => {
+                if let Some((params, results)) = self.functions.get(index).cloned() {
                     // Assumes success on the called function
-                    self.pop_vals(&inout.get_input_types())?;
-                    self.push_vals(&inout.output);
+                    self.pop_vals(&params)?;
+                    self.push_vals(&results);
                     Ok(())
                 } else {
                     Err(WatError::name_resolution_error(
@@ -415,7 +465,7 @@ impl Validator {
                     }
                 }
                 marker::DataInstruction::GetMemorySize => {
-                    if self.memory_names.contains(location) {
+                    if self.memories.get(location).is_some() {
                         self.push_val(SerializableWatType::I32);
                         Ok(())
                     } else {
@@ -427,7 +477,7 @@ impl Validator {
                 }
 
                 marker::DataInstruction::SetMemorySize => {
-                    if self.memory_names.contains(location) {
+                    if self.memories.get(location).is_some() {
                         self.expected_pop_val(&SerializableWatType::I32)?;
                         self.push_val(SerializableWatType::I32);
                         Ok(())
@@ -445,7 +495,7 @@ impl Validator {
                 is_storing,
                 ..
             } => {
-                if self.memory_names.contains(location) {
+                if self.memories.get(location).is_some() {
                     if *is_storing {
                         self.expected_pop_val(typ)?;
                         self.expected_pop_val(&SerializableWatType::I32)?;
@@ -474,6 +524,7 @@ impl Validator {
             }
             SerializedInstruction::Comparison { typ, kind } => {
                 if matches!(kind, crate::marker::ComparisonOperation::EqualZero) {
+                    self.expected_pop_val(typ)?;
                     self.push_val(SerializableWatType::I32);
                     Ok(())
                 } else {
@@ -626,17 +677,14 @@ impl Validator {
 
     fn try_get_control_frame(&self, label: &str) -> WatResult<ControlFrame> {
         Ok(match try_name_to_index(label) {
-            Ok(index) => {
-                self.control_stack
-                    .get(index)
-                    .ok_or(WatError::index_out_of_range_range(
-                        self.control_stack.len(),
-                        index,
-                    ))?
-            }
+            // Relative depth, where 0 is the innermost block
+            Ok(index) => self.control_stack.iter().rev().nth(index).ok_or(
+                WatError::index_out_of_range_range(self.control_stack.len(), index),
+            )?,
             Err(name) => self
                 .control_stack
                 .iter()
+                .rev()
                 .find_map(|cf| {
                     cf.label
                         .as_ref()
@@ -655,16 +703,96 @@ impl Validator {
         locals: &[(Option<String>, SerializableWatType)],
         results: &[SerializableWatType],
     ) -> WatResult<()> {
+        match self
+            .infer_stack_types(instuctions, params, locals, results)
+            .error
+        {
+            Some((_, err)) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Check a single instruction against the types on the stack, as if it were in the function body.
+    ///
+    /// Returns the types it pops and pushes, or [None] if the types don't match.
+    pub fn try_instruction(
+        &mut self,
+        instruction: &SerializedInstruction,
+        stack: &[SerializableWatType],
+        locals: &ValueMapping<SerializableWatType>,
+        results: &[SerializableWatType],
+    ) -> Option<StackEffect> {
+        self.reset_stack();
+        self.push_control(marker::BlockKind::Block, "", Vec::new(), results.to_vec());
+        self.push_vals(stack);
+        self.effect = StackEffect::default();
+        self.validate(instruction, results, locals).ok()?;
+        Some(std::mem::take(&mut self.effect))
+    }
+
+    /// Validate a function, keeping the types on the stack before each instruction.
+    ///
+    /// Stops at the first invalid instruction.
+    pub fn infer_stack_types(
+        &mut self,
+        instuctions: &[SerializedInstruction],
+        params: &[(Option<String>, SerializableWatType)],
+        locals: &[(Option<String>, SerializableWatType)],
+        results: &[SerializableWatType],
+    ) -> StackTypes {
         self.reset_stack();
         let local_vars = params.iter().chain(locals.iter()).cloned().collect();
-        for instruction in instuctions {
-            self.validate(instruction, results, &local_vars)?;
+        // Function body acts as the outermost block
+        self.push_control(marker::BlockKind::Block, "", Vec::new(), results.to_vec());
+        let mut stack_types = StackTypes::default();
+        for (index, instruction) in instuctions.iter().enumerate() {
+            stack_types.before.push(self.value_stack.clone());
+            stack_types
+                .unreachable
+                .push(self.control_stack.iter().any(|frame| frame.unreachable));
+            self.effect = StackEffect::default();
+            if let Err(err) = self.validate(instruction, results, &local_vars) {
+                stack_types.error = Some((index, err));
+                return stack_types;
+            }
+            stack_types.effects.push(std::mem::take(&mut self.effect));
         }
-        self.pop_vals(results)?;
-        if !self.value_stack.is_empty() {
-            Err(WatError::extra_items_on_stack_error(&self.value_stack))
-        } else {
-            Ok(())
+        stack_types.before.push(self.value_stack.clone());
+        if let Err(err) = self.pop_control() {
+            stack_types.error = Some((instuctions.len(), err));
+        }
+        stack_types
+    }
+}
+
+/// Types an instruction takes from and puts on the stack, both in stack order (top is last)
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, Type)]
+pub struct StackEffect {
+    pub popped: Vec<SerializableWatType>,
+    pub pushed: Vec<SerializableWatType>,
+}
+
+/// Types on the value stack while validating a function
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StackTypes {
+    /// Stack before each instruction, with one more entry for the stack at the end of the function
+    pub before: Vec<Vec<SerializableWatType>>,
+    /// Types popped and pushed by each valid instruction
+    pub effects: Vec<StackEffect>,
+    /// Whether each validated instruction follows a `br`, `br_table`, `return` or `unreachable`
+    /// in its block or an enclosing one, so it can never run
+    pub unreachable: Vec<bool>,
+    /// Index of the instruction that failed validation and why,
+    /// the index is the instruction count if the function ends with the wrong stack
+    pub error: Option<(usize, WatError)>,
+}
+
+impl StackTypes {
+    /// Stack after the instruction at the index, if it was validated
+    pub fn after(&self, index: usize) -> Option<&Vec<SerializableWatType>> {
+        match &self.error {
+            Some((failed, _)) if *failed <= index => None,
+            _ => self.before.get(index + 1),
         }
     }
 }
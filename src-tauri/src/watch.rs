@@ -0,0 +1,477 @@
+//! Watch expressions, evaluated after every traced step.
+//!
+//! An expression is one of:
+//! - `$count` or `local 0`: a local of the running function
+//! - `global $g` or `global 0`: a global
+//! - `i32[$ptr + 4]`: a little-endian `i32`, `i64`, `f32` or `f64` in memory
+//! - `mem[0..16] as utf8`: bytes in memory, shown `as hex` when not given
+//!
+//! Memory accesses use memory 0 unless a memory is named before the `[`, as in `i32 $heap[8]`.
+//! Addresses add and subtract numbers, locals and globals.
+//! Names follow the rules of [ValueMapping::get]: a number is an index, anything else a name.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::{
+    error::{WatError, WatResult},
+    helper::SerializedNumber,
+    interpreter::Value,
+    marker::{ByteKind, SerializableWatType},
+    memory::{LinearMemory, MAX_INSPECT_BYTES},
+    validator::ValueMapping,
+    NumLocationKind,
+};
+
+/// Local or global, by index or name
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Variable {
+    Local(String),
+    Global(String),
+}
+
+/// Part of an address, added or subtracted
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Term {
+    Number(u64),
+    Variable(Variable),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Address {
+    /// Terms with whether they are subtracted
+    terms: Vec<(bool, Term)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BytesFormat {
+    Hex,
+    Utf8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum WatchExpr {
+    Variable(Variable),
+    Load {
+        typ: SerializableWatType,
+        memory: String,
+        address: Address,
+    },
+    Bytes {
+        memory: String,
+        start: Address,
+        end: Address,
+        format: BytesFormat,
+    },
+}
+
+/// A parsed watch expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watch {
+    text: String,
+    expr: WatchExpr,
+}
+
+/// Value of a watch expression at one step
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub enum WatchValue {
+    Number(SerializedNumber),
+    Bytes(Vec<u8>),
+    Text(String),
+    /// The expression cannot be evaluated here, like a local of another function
+    Error(WatError),
+}
+
+impl Display for WatchValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchValue::Number(n) => write!(f, "{n}"),
+            WatchValue::Bytes(bytes) => write!(
+                f,
+                "{}",
+                bytes
+                    .iter()
+                    .map(|b| format!("{b:02x}"))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+            WatchValue::Text(text) => write!(f, "{text:?}"),
+            WatchValue::Error(err) => write!(f, "<{}>", err.message().unwrap_or_default()),
+        }
+    }
+}
+
+/// What a watch can see when it is evaluated
+pub(crate) struct WatchScope<'a, M> {
+    pub locals: &'a ValueMapping<Value>,
+    pub globals: &'a ValueMapping<Value>,
+    /// Memory by index or name
+    pub memory: M,
+}
+
+impl Watch {
+    /// The expression as it was written
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub(crate) fn evaluate<'a, M>(&self, scope: &WatchScope<'a, M>) -> WatchValue
+    where
+        M: Fn(&str) -> WatResult<&'a LinearMemory>,
+    {
+        self.expr.evaluate(scope).unwrap_or_else(WatchValue::Error)
+    }
+}
+
+impl FromStr for Watch {
+    type Err = WatError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            text,
+            tokens: tokenize(text),
+            next: 0,
+        };
+        let expr = parser.watch()?;
+        match parser.tokens.get(parser.next) {
+            None => Ok(Watch {
+                text: text.trim().to_string(),
+                expr,
+            }),
+            Some(extra) => Err(WatError::invalid_watch_error(
+                text,
+                &format!("unexpected `{extra}`"),
+            )),
+        }
+    }
+}
+
+impl WatchExpr {
+    fn evaluate<'a, M>(&self, scope: &WatchScope<'a, M>) -> WatResult<WatchValue>
+    where
+        M: Fn(&str) -> WatResult<&'a LinearMemory>,
+    {
+        match self {
+            WatchExpr::Variable(variable) => {
+                Ok(WatchValue::Number(variable.evaluate(scope)?.into()))
+            }
+            WatchExpr::Load {
+                typ,
+                memory,
+                address,
+            } => {
+                let size = match typ {
+                    SerializableWatType::I32 | SerializableWatType::F32 => ByteKind::Bits32,
+                    _ => ByteKind::Bits64,
+                };
+                let (bits, _) = (scope.memory)(memory)?.load(address.evaluate(scope)?, 0, size)?;
+                Ok(WatchValue::Number(Value::from_bits(typ, bits)?.into()))
+            }
+            WatchExpr::Bytes {
+                memory,
+                start,
+                end,
+                format,
+            } => {
+                let start = start.evaluate(scope)?;
+                let length = end.evaluate(scope)?.saturating_sub(start);
+                if length > MAX_INSPECT_BYTES as u64 {
+                    return Err(WatError::number_to_large(&SerializedNumber::from(
+                        length as i64,
+                    )));
+                }
+                let bytes = (scope.memory)(memory)?.read(start, length)?;
+                Ok(match format {
+                    BytesFormat::Hex => WatchValue::Bytes(bytes),
+                    BytesFormat::Utf8 => {
+                        WatchValue::Text(String::from_utf8_lossy(&bytes).into_owned())
+                    }
+                })
+            }
+        }
+    }
+}
+
+impl Variable {
+    fn evaluate<M>(&self, scope: &WatchScope<M>) -> WatResult<Value> {
+        match self {
+            Variable::Local(name) => scope
+                .locals
+                .get(name)
+                .copied()
+                .ok_or(WatError::local_resolution_error(name)),
+            Variable::Global(name) => {
+                scope
+                    .globals
+                    .get(name)
+                    .copied()
+                    .ok_or(WatError::name_resolution_error(
+                        name,
+                        NumLocationKind::Global,
+                    ))
+            }
+        }
+    }
+}
+
+impl Address {
+    /// Sum of the terms, wrapping like `i64.add`
+    fn evaluate<M>(&self, scope: &WatchScope<M>) -> WatResult<u64> {
+        self.terms
+            .iter()
+            .try_fold(0_u64, |sum, (is_negative, term)| {
+                let value = match term {
+                    Term::Number(n) => *n,
+                    Term::Variable(variable) => match variable.evaluate(scope)? {
+                        Value::I32(n) => n as u32 as u64,
+                        Value::I64(n) => n as u64,
+                        other => Err(WatError::unexpected_type(
+                            &SerializableWatType::I32,
+                            &other.typ(),
+                        ))?,
+                    },
+                };
+                Ok(if *is_negative {
+                    sum.wrapping_sub(value)
+                } else {
+                    sum.wrapping_add(value)
+                })
+            })
+    }
+}
+
+/// Split into names, numbers and symbols, where `..` is one symbol
+fn tokenize(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(first) = rest.chars().next() {
+        let length = if rest.starts_with("..") {
+            2
+        } else if first.is_alphanumeric() || first == '$' || first == '_' {
+            rest.char_indices()
+                .find(|(i, c)| {
+                    !(c.is_alphanumeric() || "$_.'".contains(*c)) || rest[*i..].starts_with("..")
+                })
+                .map_or(rest.len(), |(i, _)| i)
+        } else {
+            first.len_utf8()
+        };
+        tokens.push(&rest[..length]);
+        rest = rest[length..].trim_start();
+    }
+    tokens
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<&'a str>,
+    next: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, reason: &str) -> WatError {
+        WatError::invalid_watch_error(self.text, reason)
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.next).copied()
+    }
+
+    fn take(&mut self) -> WatResult<&'a str> {
+        let token = self.peek().ok_or(self.error("it ends too early"))?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> WatResult<()> {
+        match self.take()? {
+            token if token == expected => Ok(()),
+            token => Err(self.error(&format!("expected `{expected}` but found `{token}`"))),
+        }
+    }
+
+    /// A `$name` or an index, without the `$`
+    fn name(&mut self) -> WatResult<String> {
+        let token = self.take()?;
+        match token.strip_prefix('$') {
+            Some(name) if !name.is_empty() => Ok(name.to_string()),
+            None if token.parse::<u32>().is_ok() => Ok(token.to_string()),
+            _ => Err(self.error(&format!("`{token}` is not a name or an index"))),
+        }
+    }
+
+    /// An optional memory before `[`, memory 0 when there is none
+    fn memory(&mut self) -> WatResult<String> {
+        if self.peek() == Some("[") {
+            Ok("0".to_string())
+        } else {
+            self.name()
+        }
+    }
+
+    fn watch(&mut self) -> WatResult<WatchExpr> {
+        let token = self.peek().ok_or(self.error("it is empty"))?;
+        let typ = match token {
+            "i32" => Some(SerializableWatType::I32),
+            "i64" => Some(SerializableWatType::I64),
+            "f32" => Some(SerializableWatType::F32),
+            "f64" => Some(SerializableWatType::F64),
+            _ => None,
+        };
+        if let Some(typ) = typ {
+            self.next += 1;
+            let memory = self.memory()?;
+            self.expect("[")?;
+            let address = self.address()?;
+            self.expect("]")?;
+            return Ok(WatchExpr::Load {
+                typ,
+                memory,
+                address,
+            });
+        }
+        if token == "mem" {
+            self.next += 1;
+            let memory = self.memory()?;
+            self.expect("[")?;
+            let start = self.address()?;
+            self.expect("..")?;
+            let end = self.address()?;
+            self.expect("]")?;
+            let format = if self.peek() == Some("as") {
+                self.next += 1;
+                match self.take()? {
+                    "hex" => BytesFormat::Hex,
+                    "utf8" => BytesFormat::Utf8,
+                    other => Err(self.error(&format!("cannot show bytes as `{other}`")))?,
+                }
+            } else {
+                BytesFormat::Hex
+            };
+            return Ok(WatchExpr::Bytes {
+                memory,
+                start,
+                end,
+                format,
+            });
+        }
+        Ok(WatchExpr::Variable(self.variable()?))
+    }
+
+    fn variable(&mut self) -> WatResult<Variable> {
+        match self.peek() {
+            Some("global") => {
+                self.next += 1;
+                Ok(Variable::Global(self.name()?))
+            }
+            Some("local") => {
+                self.next += 1;
+                Ok(Variable::Local(self.name()?))
+            }
+            Some(token) if token.starts_with('$') => Ok(Variable::Local(self.name()?)),
+            Some(token) => Err(self.error(&format!("`{token}` is not a local or global"))),
+            None => Err(self.error("it ends too early")),
+        }
+    }
+
+    fn address(&mut self) -> WatResult<Address> {
+        let mut terms = vec![(false, self.term()?)];
+        while let Some(sign @ ("+" | "-")) = self.peek() {
+            self.next += 1;
+            terms.push((sign == "-", self.term()?));
+        }
+        Ok(Address { terms })
+    }
+
+    fn term(&mut self) -> WatResult<Term> {
+        let token = self.peek().ok_or(self.error("it ends too early"))?;
+        let number = match token.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(&hex.replace('_', ""), 16).ok(),
+            None => token.replace('_', "").parse().ok(),
+        };
+        match number {
+            Some(n) => {
+                self.next += 1;
+                Ok(Term::Number(n))
+            }
+            None => Ok(Term::Variable(self.variable()?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_kind_of_watch() {
+        let parse = |text: &str| text.parse::<Watch>().unwrap().expr;
+        assert_eq!(
+            parse("$count"),
+            WatchExpr::Variable(Variable::Local("count".to_string()))
+        );
+        assert_eq!(
+            parse("global 0"),
+            WatchExpr::Variable(Variable::Global("0".to_string()))
+        );
+        assert_eq!(
+            parse("i32[$ptr + 4]"),
+            WatchExpr::Load {
+                typ: SerializableWatType::I32,
+                memory: "0".to_string(),
+                address: Address {
+                    terms: vec![
+                        (false, Term::Variable(Variable::Local("ptr".to_string()))),
+                        (false, Term::Number(4)),
+                    ]
+                },
+            }
+        );
+        assert_eq!(
+            parse("mem $heap[0..0x10] as utf8"),
+            WatchExpr::Bytes {
+                memory: "heap".to_string(),
+                start: Address {
+                    terms: vec![(false, Term::Number(0))]
+                },
+                end: Address {
+                    terms: vec![(false, Term::Number(16))]
+                },
+                format: BytesFormat::Utf8,
+            }
+        );
+        assert!("i32[$p".parse::<Watch>().is_err());
+        assert!("count".parse::<Watch>().is_err());
+        assert!("mem[0..4] as octal".parse::<Watch>().is_err());
+    }
+
+    #[test]
+    fn watches_follow_each_step() {
+        let structure = crate::inner_transform(
+            r#"(module (memory 1) (global $g (mut i32) (i32.const 7))
+                (func (export "f") (param $p i32)
+                    local.get $p
+                    global.get $g
+                    i32.store))"#,
+        )
+        .unwrap();
+        let watches = ["i32[$p]", "global 0", "mem[4..6] as utf8", "$missing"]
+            .iter()
+            .map(|text| text.parse().unwrap())
+            .collect();
+        let mut machine = crate::interpreter::Machine::new(&structure)
+            .unwrap()
+            .with_tracing()
+            .with_watches(watches);
+        machine.invoke(0, &[Value::I32(4)]).unwrap();
+        let first = &machine.trace()[0].watches;
+        let last = &machine.trace()[2].watches;
+        assert_eq!(first[0], WatchValue::Number(0.into()));
+        assert_eq!(last[0], WatchValue::Number(7.into()));
+        assert_eq!(last[1], WatchValue::Number(7.into()));
+        assert_eq!(last[2], WatchValue::Text("\u{7}\0".to_string()));
+        assert!(matches!(last[3], WatchValue::Error(_)));
+    }
+}
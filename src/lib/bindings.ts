@@ -14,61 +14,229 @@ const invoke = () => window.__TAURI_INVOKE__;
  * Helper function to auto convert
  */
 export function transform(text: string) {
-    return invoke()<TransfromResult>("transform", { text })
+    return invoke()<CommandResult<InterpreterStructure>>("transform", { text })
 }
 
-export type SerializedInstructionTree = { root: SerializedInstructionNode[]; array: SerializedInstruction[] }
+/**
+ * Describe the instruction at a byte offset of the text
+ */
+export function inspectAt(text: string, offset: number) {
+    return invoke()<CommandResult<Inspection | null>>("inspect_at", { text,offset })
+}
+
+/**
+ * Completion candidates for the word ending at a byte offset of the text
+ */
+export function completeAt(text: string, offset: number) {
+    return invoke()<Completions>("complete_at", { text,offset })
+}
+
+/**
+ * Rename the `$name` at a byte offset of the text, returning the edits to make
+ */
+export function renameAt(text: string, offset: number, newName: string) {
+    return invoke()<CommandResult<TextEdit[]>>("rename_at", { text,offset,newName })
+}
+
+/**
+ * Control flow graph of every function, with DOT and Mermaid forms
+ */
+export function controlFlowGraphs(text: string) {
+    return invoke()<CommandResult<FunctionGraph[]>>("control_flow_graphs", { text })
+}
+
+/**
+ * Errors and warnings to show under the text
+ */
+export function diagnostics(text: string) {
+    return invoke()<Diagnostic[]>("diagnostics", { text })
+}
+
+/**
+ * Run the enabled lint rules, with fixes where possible
+ */
+export function lint(text: string, config: LintConfig) {
+    return invoke()<CommandResult<Lint[]>>("lint", { text,config })
+}
+
+/**
+ * Fold constants and simplify every function, with a diff against the original instructions
+ */
+export function optimize(text: string) {
+    return invoke()<CommandResult<OptimizedFunction[]>>("optimize", { text })
+}
+
+/**
+ * Which functions call which, with recursion and unreachable functions marked
+ */
+export function callGraph(text: string) {
+    return invoke()<CommandResult<CallGraphReport>>("call_graph", { text })
+}
+
+/**
+ * Stack height, nesting depth and instruction cost of every function, without running it
+ */
+export function functionCosts(text: string) {
+    return invoke()<CommandResult<FunctionCost[]>>("function_costs", { text })
+}
+
+/**
+ * Which instruction produced every value on the stack, and what every `local.get` and `global.get` reads
+ */
+export function valueProvenance(text: string) {
+    return invoke()<CommandResult<FunctionProvenance[]>>("value_provenance", { text })
+}
+
+/**
+ * Range of every integer before every instruction, warning about division by zero,
+ * out of bounds memory access and `br_if` conditions that never change
+ */
+export function valueRanges(text: string) {
+    return invoke()<CommandResult<RangeReport>>("value_ranges", { text })
+}
+
+/**
+ * Find arguments that make exported functions trap
+ */
+export function findTraps(text: string) {
+    return invoke()<CommandResult<TrapReport>>("find_traps", { text })
+}
+
+/**
+ * Call a function by export name, or its name or index, with arguments written as text
+ */
+export function invokeFunction(text: string, name: string, args: string[]) {
+    return invoke()<CommandResult<Invocation>>("invoke_function", { text,name,args })
+}
+
+/**
+ * Call a function like [invoke_function], with every step it runs, the memory it reads and writes,
+ * and the value of each watch after it
+ */
+export function traceFunction(text: string, name: string, args: string[], watches: string[]) {
+    return invoke()<CommandResult<TraceStep[]>>("trace_function", { text,name,args,watches })
+}
+
+/**
+ * Copy data into memory and run the start function, the phase before any export is called
+ */
+export function instantiateModule(text: string) {
+    return invoke()<CommandResult<Instantiation>>("instantiate_module", { text })
+}
+
+/**
+ * Every export with its signature, type or limits, and default arguments for functions
+ */
+export function listExports(text: string) {
+    return invoke()<CommandResult<Export[]>>("list_exports", { text })
+}
+
+/**
+ * Every way to show a number: signed, unsigned, hex, binary and as float bits
+ */
+export function numberViews(number: SerializedNumber) {
+    return invoke()<NumberViews>("number_views", { number })
+}
+
+/**
+ * Hex dump of a memory, by index or name, after instantiation
+ */
+export function memoryDump(text: string, memory: string, address: number, length: number) {
+    return invoke()<CommandResult<HexDump>>("memory_dump", { text,memory,address,length })
+}
+
+/**
+ * Numbers of one type read from a memory after instantiation
+ */
+export function memoryValues(text: string, memory: string, address: number, typ: SerializableWatType, count: number) {
+    return invoke()<CommandResult<TypedValues>>("memory_values", { text,memory,address,typ,count })
+}
+
+/**
+ * UTF-8 string at an address of a memory after instantiation
+ */
+export function memoryString(text: string, memory: string, address: number, layout: StringLayout) {
+    return invoke()<CommandResult<DecodedString>>("memory_string", { text,memory,address,layout })
+}
+
+export type SerializedInstructionTree = { root: SerializedInstructionNode[]; array: SerializedInstruction[]; descriptions: string[] }
+/**
+ * A number serialized as an array of bytes in big-endian order.
+ */
+export type SerializedNumber = { first_bytes: number[]; second_bytes: number[] | null; typ: SerializableWatType }
 /**
  * Memory Instructions
  */
 export type DataInstruction = "GetLocal" | "GetGlobal" | "SetLocal" | "SetGlobal" | "TeeLocal" | "GetMemorySize" | "SetMemorySize"
 /**
- * Comparison operations
+ * Bitwise operations
  */
-export type ComparisonOperation = "EqualZero" | "Equal" | "NotEqual" | "LessThenSigned" | "LessThenUnsigned" | "GreaterThenSigned" | "GreaterThenUnsigned" | "LessThenOrEqualToSigned" | "LessThenOrEqualToUnsigned" | "GreaterThenOrEqualToSigned" | "GreaterThenOrEqualToUnsigned"
-export type InterpreterStructure = { name: string; exported: { [key: string]: [NumLocationKind, number] }; globals: GlobalData[]; memory: MemoryData[]; free_data: DataValue[]; func: WastFunc[]; start: string | null }
+export type FloatOperation = "AbsoluteValue" | "Negation" | "Ceiling" | "Floor" | "Truncate" | "Nearest" | "SquareRoot" | "Minimum" | "Maximum" | "CopySign"
+/**
+ * Everything known about one instruction
+ */
+export type Inspection = { function: number; index: number; span: { start: number; end: number }; instruction: SerializedInstruction; description: string; effect: StackEffect | null; stack_before: SerializableWatType[] | null; stack_after: SerializableWatType[] | null; error: WatError | null; blocks: EnclosingBlock[] }
+/**
+ * Simple Instructions
+ */
+export type SimpleInstruction = "Unreachable" | "Nop" | "Drop" | "Return"
 export type DataValue = { id: string; is_string: boolean; data: number[] }
 /**
- * A node representing the instruction block.
+ * Types an instruction takes from and puts on the stack, both in stack order (top is last)
+ */
+export type StackEffect = { popped: SerializableWatType[]; pushed: SerializableWatType[] }
+export type GlobalData = { name: string; typ: SerializableWatType; is_mutable: boolean; val: SerializedNumber }
+export type WatError = { span: { start: number; end: number } | null; stage: ErrorStage; message: string | null; trap: TrapKind | null }
+/**
+ * A block that contains the inspected instruction
  */
-export type SerializedInstructionNode = { kind: NodeMark; label: string; depth: number; start: number; end: number; parent: number; children: { [key: number]: number } }
+export type EnclosingBlock = { node: number; kind: NodeMark; label: string; start: number; end: number }
+/**
+ * Represents input and output of a block of instructions.
+ * For functions, inputs are parameters and outputs are results.
+ */
+export type InputOutput = { index: string | null; input: ([string | null, SerializableWatType])[]; output: SerializableWatType[] }
 /**
  * Bitwise operations
  */
 export type BitwiseOperation = "CountLeadingZero" | "CountTrailingZero" | "CountNonZero" | "And" | "Or" | "Xor" | "ShiftLeft" | "ShiftRightSigned" | "ShiftRightUnsigned" | "RotateLeft" | "RotateRight"
+export type InterpreterStructure = { name: string; exported: { [key: string]: [NumLocationKind, number] }; globals: GlobalData[]; memory: MemoryData[]; free_data: DataValue[]; func: WastFunc[]; start: string | null }
 /**
- * Bitwise operations
+ * A node representing the instruction block.
  */
-export type FloatOperation = "AbsoluteValue" | "Negation" | "Ceiling" | "Floor" | "Truncate" | "Nearest" | "SquareRoot" | "Minimum" | "Maximum" | "CopySign"
+export type SerializedInstructionNode = { kind: NodeMark; label: string; depth: number; start: number; end: number; parent: number; children: { [key: number]: number }; branches: number[] }
+export type ErrorStage = "Parsing" | "TypeChecking" | "NameResolving" | "Unimplemented" | "Runtime"
 /**
- * All Wat types that can be (currently) serialized.
- * 
- * ## Limitations
- * All except [ValType::Ref] are supported, but must explicity convert.
+ * Arithmetic operations
  */
-export type SerializableWatType = "I32" | "I64" | "F32" | "F64" | "V128"
+export type ArithmeticOperation = "Addition" | "Subtraction" | "Multiplication" | "DivisonSigned" | "DivisonUnsigned" | "RemainderSigned" | "RemainderUnsigned"
 /**
  * Serialized instructions based on parts of [Instruction],
  * but is more generic over types (e.g. a single Add instruction that carries the type).
  */
-export type SerializedInstruction = { Simple: SimpleInstruction } | { Block: { label: string; kind: BlockKind; inout: InputOutput | null } } | { Branch: { default_label: string; other_labels: string[]; is_conditional: boolean } } | { Call: { index: string; inout: InputOutput } } | { Data: { kind: DataInstruction; location: string } } | { Memory: { location: string; typ: SerializableWatType; count: ByteKind; offset: number; alignment: ByteKind; is_storing: boolean } } | { Const: { typ: SerializableWatType; value: SerializedNumber } } | { Comparison: { kind: ComparisonOperation; typ: SerializableWatType } } | { Arithmetic: { kind: ArithmeticOperation; typ: SerializableWatType } } | { Bitwise: { kind: BitwiseOperation; is_64_bit: boolean } } | { Float: { kind: FloatOperation; is_64_bit: boolean } } | { Conversion: NumericConversionKind } | { DefaultString: string }
+export type SerializedInstruction = { Simple: SimpleInstruction } | { Block: { label: string; kind: BlockKind; inout: InputOutput | null } } | { Branch: { default_label: string; other_labels: string[]; is_conditional: boolean; targets: BranchTarget[] } } | { Call: { index: string } } | { CallIndirect: { table: string; type_use: InputOutput } } | { Data: { kind: DataInstruction; location: string } } | { Memory: { location: string; typ: SerializableWatType; count: ByteKind; offset: number; alignment: ByteKind; is_storing: boolean; is_signed: boolean } } | { Const: { typ: SerializableWatType; value: SerializedNumber } } | { Comparison: { kind: ComparisonOperation; typ: SerializableWatType } } | { Arithmetic: { kind: ArithmeticOperation; typ: SerializableWatType } } | { Bitwise: { kind: BitwiseOperation; is_64_bit: boolean } } | { Float: { kind: FloatOperation; is_64_bit: boolean } } | { Conversion: NumericConversionKind } | { DefaultString: string }
+export type NumLocationKind = "Function" | "Global" | "Memory" | "Type"
 /**
- * A simple enum to make sure result always succeeds.
- * 
- * Allow the TypeScript side to know about WatError
+ * Control flow instructions
  */
-export type TransfromResult = { Ok: InterpreterStructure } | { Err: WatError }
-export type GlobalData = { name: string; typ: SerializableWatType; is_mutable: boolean; val: SerializedNumber }
-export type WatError = { span: { start: number; end: number } | null; stage: ErrorStage; message: string | null }
-export type ErrorStage = "Parsing" | "TypeChecking" | "NameResolving" | "Unimplemented"
+export type BlockKind = "Block" | "If" | "Else" | "Loop" | "End"
 /**
- * Arithmetic operations
+ * Numeric Conversion Type
  */
-export type ArithmeticOperation = "Addition" | "Subtraction" | "Multiplication" | "DivisonSigned" | "DivisonUnsigned" | "RemainderSigned" | "RemainderUnsigned"
+export type NumericConversionKind = "WrapInt" | "SignedTruncF32ToI32" | "UnsignedTruncF32ToI32" | "SignedTruncF64ToI32" | "UnsignedTruncF64ToI32" | "SignedTruncF32ToI64" | "UnsignedTruncF32ToI64" | "SignedTruncF64ToI64" | "UnsignedTruncF64ToI64" | "SignedExtend" | "UnsignedExtend" | "SignedConvertI32ToF32" | "UnsignedConvertI32ToF32" | "SignedConvertI64ToF32" | "UnsignedConvertI64ToF32" | "SignedConvertI32ToF64" | "UnsignedConvertI32ToF64" | "SignedConvertI64ToF64" | "UnsignedConvertI64ToF64" | "DemoteFloat" | "PromoteFloat" | "Reinterpret32FToI" | "Reinterpret32IToF" | "Reinterpret64FToI" | "Reinterpret64IToF"
 /**
  * The kind of byte
  */
 export type ByteKind = "Bits8" | "Bits16" | "Bits32" | "Bits64"
+export type MemoryData = { name: string; min: SerializedNumber; max: SerializedNumber; is_32: boolean; is_shared: boolean; data: { [key: number]: DataValue } }
+/**
+ * All Wat types that can be (currently) serialized.
+ * 
+ * ## Limitations
+ * Of the reference types, only `funcref` and `externref` are supported, but must explicity convert.
+ */
+export type SerializableWatType = "I32" | "I64" | "F32" | "F64" | "V128" | "FuncRef" | "ExternRef"
+export type NodeMark = "Block" | "Loop" | { Conditional: number }
 /**
  * A basic Wa(s)t Function
  * 
@@ -77,26 +245,153 @@ export type ByteKind = "Bits8" | "Bits16" | "Bits32" | "Bits64"
  */
 export type WastFunc = { info: InputOutput; locals: ([string | null, SerializableWatType])[]; block: SerializedInstructionTree }
 /**
- * Control flow instructions
+ * Comparison operations
  */
-export type BlockKind = "Block" | "If" | "Else" | "Loop" | "End"
+export type ComparisonOperation = "EqualZero" | "Equal" | "NotEqual" | "LessThenSigned" | "LessThenUnsigned" | "GreaterThenSigned" | "GreaterThenUnsigned" | "LessThenOrEqualToSigned" | "LessThenOrEqualToUnsigned" | "GreaterThenOrEqualToSigned" | "GreaterThenOrEqualToUnsigned"
 /**
- * Numeric Conversion Type
+ * Result of a command that always succeeds, so the TypeScript side knows about [WatError]
  */
-export type NumericConversionKind = "WrapInt" | "SignedTruncF32ToI32" | "UnsignedTruncF32ToI32" | "SignedTruncF64ToI32" | "UnsignedTruncF64ToI32" | "SignedTruncF32ToI64" | "UnsignedTruncF32ToI64" | "SignedTruncF64ToI64" | "UnsignedTruncF64ToI64" | "SignedExtend" | "UnsignedExtend" | "SignedConvertI32ToF32" | "UnsignedConvertI32ToF32" | "SignedConvertI64ToF32" | "UnsignedConvertI64ToF32" | "SignedConvertI32ToF64" | "UnsignedConvertI32ToF64" | "SignedConvertI64ToF64" | "UnsignedConvertI64ToF64" | "DemoteFloat" | "PromoteFloat" | "Reinterpret32FToI" | "Reinterpret32IToF" | "Reinterpret64FToI" | "Reinterpret64IToF"
+export type CommandResult<T> = { Ok: T } | { Err: WatError }
+export type Completions = { span: { start: number; end: number }; items: CompletionItem[] }
+export type CompletionItem = { label: string; kind: CompletionKind; detail: string; rank: number }
+export type CompletionKind = "Instruction" | "Local" | "Global" | "Function" | "Label" | "Memory"
 /**
- * A number serialized as an array of bytes in big-endian order.
+ * Replace the text in the span
  */
-export type SerializedNumber = { first_bytes: number[]; second_bytes: number[] | null; typ: SerializableWatType }
-export type NodeMark = "Block" | "Loop" | { Conditional: number }
-export type MemoryData = { name: string; min: SerializedNumber; max: SerializedNumber; is_32: boolean; is_shared: boolean; data: { [key: number]: DataValue } }
+export type TextEdit = { span: { start: number; end: number }; text: string }
 /**
- * Simple Instructions
+ * Control flow graph of a function, along with its exported forms
  */
-export type SimpleInstruction = "Unreachable" | "Nop" | "Drop" | "Return"
-export type NumLocationKind = "Function" | "Global" | "Memory" | "Type"
+export type FunctionGraph = { name: string; graph: ControlFlowGraph; dot: string; mermaid: string }
 /**
- * Represents input and output of a block of instructions.
- * For functions, inputs are parameters and outputs are results.
+ * Instructions that always run one after another
  */
-export type InputOutput = { index: string | null; input: ([string | null, SerializableWatType])[]; output: SerializableWatType[] }
+export type BasicBlock = { instructions: { start: number; end: number } }
+export type ControlFlowGraph = { blocks: BasicBlock[]; edges: Edge[] }
+export type Edge = { from: number; to: number; kind: EdgeKind; is_back_edge: boolean }
+/**
+ * Why control moves from one block to another
+ */
+export type EdgeKind = "Next" | "Branch" | "NotTaken" | { Table: number } | "TableDefault" | "Then" | "Else" | "Return"
+/**
+ * Where a branch goes, resolved while building the block tree
+ */
+export type BranchTarget = { node: number; depth: number; continuation: number }
+/**
+ * A message about part of the text
+ */
+export type Diagnostic = { span: { start: number; end: number }; severity: Severity; message: string }
+export type Severity = "Error" | "Warning"
+export type Rule = "PreferTee" | "PreferEqz" | "RedundantNop" | "UnnamedExport" | "ShadowedLabel"
+/**
+ * Which rules to run, all of them by default
+ */
+export type LintConfig = { disabled: Rule[] }
+export type Lint = { rule: Rule; diagnostic: Diagnostic; fix: TextEdit[] | null }
+export type OptimizedFunction = { name: string; tree: SerializedInstructionTree; rewrites: string[]; diff: DiffLine[] }
+/**
+ * One line of the difference between the instructions before and after optimizing
+ */
+export type DiffLine = { kind: DiffKind; before: number | null; after: number | null; instruction: string }
+export type DiffKind = "Same" | "Removed" | "Added"
+/**
+ * Call graph of a module, along with its DOT form
+ */
+export type CallGraphReport = { graph: CallGraph; dot: string }
+export type Recursion = "Direct" | { Mutual: number[] }
+export type CallNode = { name: string; exports: string[]; is_start: boolean; is_reachable: boolean; recursion: Recursion | null }
+export type CallEdge = { from: number; to: number; count: number; is_recursive: boolean }
+/**
+ * Functions as nodes, in module order, and calls between them as edges
+ */
+export type CallGraph = { nodes: CallNode[]; edges: CallEdge[] }
+/**
+ * Fewest and most instructions one call can run
+ */
+export type Cost = { min: number | null; max: number | null }
+export type FunctionCost = { name: string; max_stack_height: number; max_nesting_depth: number; cost: Cost; unbounded_loops: number[] }
+export type Origin = { Instruction: number } | { Param: number } | "Zero" | "Entry"
+export type FunctionProvenance = { name: string; stack: Origin[][][]; operands: Origin[][][]; reads: Origin[][] }
+export type FunctionRanges = { name: string; stack: ((ValueRange | null)[])[]; locals: ((ValueRange | null)[])[] }
+export type RangeReport = { functions: FunctionRanges[]; warnings: Diagnostic[] }
+/**
+ * Smallest and largest value an integer can have
+ */
+export type ValueRange = { min: SerializedNumber; max: SerializedNumber }
+export type TrapReport = { traps: TrapInput[]; incomplete: string[] }
+/**
+ * Why execution stopped, for errors that are traps in the WebAssembly specification
+ */
+export type TrapKind = "Unreachable" | "IntegerOverflow" | "DivideByZero" | "InvalidConversion" | "OutOfBoundsMemory" | "OutOfBoundsTable" | "CallStackExhausted"
+export type TrapInput = { export: string; args: SerializedNumber[]; kind: TrapKind; function: string; index: number; span: { start: number; end: number } | null; reads_memory: boolean }
+/**
+ * Results of calling a function, or the trap that stopped it
+ */
+export type Invocation = { Returned: SerializedNumber[] } | { Trapped: Trap }
+/**
+ * A call that was running when a trap happened
+ */
+export type CallSite = { function: string; index: number }
+/**
+ * Where and why execution trapped
+ */
+export type Trap = { kind: TrapKind; message: string; call_stack: CallSite[] }
+/**
+ * Kind of value a float's bits hold
+ */
+export type FloatClass = "Zero" | "Subnormal" | "Normal" | "Infinity" | "QuietNan" | "SignalingNan"
+/**
+ * The fields of a float, as bits from the most significant
+ */
+export type FloatFields = { is_negative: boolean; exponent_bits: string; mantissa_bits: string; exponent: number | null; class: FloatClass }
+/**
+ * Every way the bits of a number can be shown
+ */
+export type NumberViews = { signed: string; unsigned: string; hex: string; binary: string; float: string; fields: FloatFields }
+/**
+ * One row of a hex dump
+ */
+export type HexRow = { address: number; bytes: number[]; ascii: string }
+export type HexDump = { rows: HexRow[]; segments: SegmentRegion[] }
+/**
+ * Consecutive numbers of one type read from memory
+ */
+export type TypedValues = { typ: SerializableWatType; values: SerializedNumber[]; segments: SegmentRegion[] }
+export type DecodedString = { address: number; bytes: number[]; text: string; is_valid_utf8: boolean; is_truncated: boolean; segment: SegmentRegion | null }
+/**
+ * Bytes written by a data segment when the module is instantiated
+ */
+export type SegmentRegion = { id: string; start: number; length: number; is_string: boolean }
+/**
+ * How a string is laid out in memory
+ */
+export type StringLayout = "NullTerminated" | "LengthPrefixed"
+/**
+ * One load or store, at the address after adding the offset
+ */
+export type MemoryAccess = { memory: number; address: number; old: number[]; new: number[] | null }
+/**
+ * Data segment copied into memory while instantiating
+ */
+export type DataWrite = { memory: number; segment: SegmentRegion }
+/**
+ * What instantiating a module did, before any export is called
+ */
+export type Instantiation = { globals: GlobalData[]; data: DataWrite[]; start: string | null; start_writes: MemoryAccess[]; trap: Trap | null }
+/**
+ * What an export refers to
+ */
+export type ExportItem = { Function: { params: Param[]; results: SerializableWatType[] } } | { Global: { typ: SerializableWatType; is_mutable: boolean; value: SerializedNumber } } | { Memory: { min: number; max: number | null; is_32: boolean; is_shared: boolean } }
+export type Export = { name: string; kind: NumLocationKind; index: number; item_name: string; item: ExportItem }
+/**
+ * A parameter of an exported function
+ */
+export type Param = { name: string | null; typ: SerializableWatType; default: string | null }
+/**
+ * Value of a watch expression at one step
+ */
+export type WatchValue = { Number: SerializedNumber } | { Bytes: number[] } | { Text: string } | { Error: WatError }
+/**
+ * A single executed instruction, recorded when tracing
+ */
+export type TraceStep = { step: number; function: string; call_depth: number; index: number; instruction: SerializedInstruction; stack_before: SerializedNumber[]; stack_after: SerializedNumber[]; memory: MemoryAccess[]; watches: WatchValue[]; trap: TrapKind | null }
\ No newline at end of file
@@ -1,4 +1,4 @@
-import { deserialize_number, instruction_in_plain_english } from "$lib";
+import { deserialize_number } from "$lib";
 import type * as command from "$lib/bindings"
 import type { StackOperationKind } from "./stackAnim";
 
@@ -6,7 +6,8 @@ export type EvalResult = {
     instruction: command.SerializedInstruction,
     locals: VariableTableType,
     action: string,
-    continuation: {label: string|number, goto: "Return"| "End" | "Else" | "Block"}|null
+    /** For branches, `target` is the position of the taken label in the branch's targets */
+    continuation: {label: string|number, goto: "Return"| "End" | "Else" | "Block", target?: number}|null
 }
 
 export type MyError = {
@@ -320,7 +321,7 @@ export function formatStack(stack:(bigint|number)[], maxShown:number = 0):string
     }
 }
 
-export function eval_single_instruction(instruction: command.SerializedInstruction, stack: StackType, data: WasmData, locals: VariableTableType):EvalResult|MyError{
+export function eval_single_instruction(instruction: command.SerializedInstruction, description: string, stack: StackType, data: WasmData, locals: VariableTableType):EvalResult|MyError{
     if ("Simple" in instruction){
         switch (instruction.Simple) {
             case "Unreachable":
@@ -328,7 +329,7 @@ export function eval_single_instruction(instruction: command.SerializedInstructi
             case "Nop":
                 break;
             case "Return":
-                return {instruction, action: instruction_in_plain_english(instruction), continuation: {label:0, goto:"Return"}, locals: structuredClone(locals)};
+                return {instruction, action: description, continuation: {label:0, goto:"Return"}, locals: structuredClone(locals)};
             case "Drop":
                 stack.pop()
                 break;
@@ -346,19 +347,29 @@ export function eval_single_instruction(instruction: command.SerializedInstructi
             const [a] = numberOrError;
             // If zero, then skip to else
             if(a === 0 || a === 0n) {
-                return {instruction, action: instruction_in_plain_english(instruction), continuation: {label: 0, goto:"Else"}, locals: structuredClone(locals)};
+                return {instruction, action: description, continuation: {label: 0, goto:"Else"}, locals: structuredClone(locals)};
             }
             // Otherwise, continue until else
         }
         // If we reach an else, skip to end
         else if (instruction.Block.kind === "Else"){
-            return {instruction, action: instruction_in_plain_english(instruction), continuation: {label: 0, goto:"End"}, locals: structuredClone(locals)};
+            return {instruction, action: description, continuation: {label: 0, goto:"End"}, locals: structuredClone(locals)};
         }
         
     }
     else if("Branch" in instruction){
-        if(instruction.Branch.other_labels.length > 0){
-            return unimplemented_instruction_error(instruction);
+        const labels = instruction.Branch.other_labels;
+        if(labels.length > 0){
+            const numberOrError = stack_pop(stack, 1);
+            if("message" in numberOrError){
+                // is Error
+                return numberOrError;
+            }
+            const [a] = numberOrError;
+            // Operand is read as unsigned, so negative values also take the default
+            const target = typeof a === "number" && a >= 0 && a < labels.length ? a : labels.length;
+            const label = target < labels.length ? labels[target] : instruction.Branch.default_label;
+            return {instruction, action: description, continuation: {label: tryNumberify(label), goto:"Block", target}, locals: structuredClone(locals)}
         }
         if(instruction.Branch.is_conditional){
             const numberOrError = stack_pop(stack, 1);
@@ -369,13 +380,13 @@ export function eval_single_instruction(instruction: command.SerializedInstructi
             const [a] = numberOrError;
             // If zero, just go to next item
             if(a === 0 || a === 0n) {
-                return {instruction, action: instruction_in_plain_english(instruction), continuation:null, locals: structuredClone(locals)};
+                return {instruction, action: description, continuation:null, locals: structuredClone(locals)};
             }
         }
         // Do branch to label or block index 
-        return {instruction, action: instruction_in_plain_english(instruction), continuation: {label: tryNumberify(instruction.Branch.default_label), goto:"Block"}, locals: structuredClone(locals)}
+        return {instruction, action: description, continuation: {label: tryNumberify(instruction.Branch.default_label), goto:"Block"}, locals: structuredClone(locals)}
     }
-    else if("Call" in instruction){
+    else if("Call" in instruction || "CallIndirect" in instruction){
         return unimplemented_instruction_error(instruction);
     }
     else if("Data" in instruction){
@@ -743,7 +754,7 @@ export function eval_single_instruction(instruction: command.SerializedInstructi
             }
         }
     }
-    return {instruction, action: instruction_in_plain_english(instruction), continuation: null, locals: structuredClone(locals)}
+    return {instruction, action: description, continuation: null, locals: structuredClone(locals)}
 }
 
 export function exec_instructions(tree: command.SerializedInstructionTree, data: WasmData, locals:VariableTableType):{result: EvalResult; previous: (number | bigint)[]; current: (number | bigint)[];}[]|MyError{
@@ -773,12 +784,13 @@ export function exec_instructions(tree: command.SerializedInstructionTree, data:
         }
         // Evaluate instruction
         const instruction = tree.array[index];
+        const description = tree.descriptions[index];
         previousStack = structuredClone(currentStack);
-        const action = eval_single_instruction(instruction, currentStack, data, locals);
+        const action = eval_single_instruction(instruction, description, currentStack, data, locals);
         // Return errors immediately
         if("message" in action){
             // console.log(final);
-            action.message += `(@ Step ${current_step} on ${instruction_in_plain_english(instruction)})`
+            action.message += `(@ Step ${current_step} on ${description})`
             return action;
         }
         // Push result
@@ -807,51 +819,24 @@ export function exec_instructions(tree: command.SerializedInstructionTree, data:
             else if ((action.continuation.goto === "Else" || action.continuation.goto === "End") && typeof currentBlock.kind !== "object"){
                 return unimplemented_error("Else or End can only be continued from conditional");
             }
-            else if(action.continuation.goto === "Block"){
-                // Number label -> go to nth enclosing block, current block is 0
-                if(typeof action.continuation.label === "number" ){
-                    while(action.continuation.label > 0){
-                        // console.log(`${action.continuation.label} CHILD -> PARENT (${currentBlock.parent})`);
-                        currentBlock = tree.root[currentBlock.parent];
-                        action.continuation.label--;
-                    }
-                    if (currentBlock.kind === "Block"){
-                        // console.log(`Skip to END: from ${index} to ${currentBlock.end}`);
-                        // For block, go to end
-                        index = currentBlock.end;
-                    }
-                    else if (currentBlock.kind === "Loop"){
-                        // console.log(`Skip to START: from ${index} to ${currentBlock.start}`);
-                        // For loop, go to start
-                        index = currentBlock.start
-                    }
+            else if(action.continuation.goto === "Block" && "Branch" in instruction){
+                // Targets are resolved when the tree is built, so jump straight there
+                const target = instruction.Branch.targets[action.continuation.target ?? 0];
+                if(target === undefined){
+                    return name_resolution_error(instruction.Branch.default_label);
                 }
-                // String label -> go to enclosing block with the label, traversing up parents
-                else if(typeof action.continuation.label === "string"){
-                    while(action.continuation.label !== currentBlock.label && currentBlock.depth !== 0){
-                        // console.log(`${action.continuation.label} CHILD -> PARENT (${currentBlock.parent})`);
-                        currentBlock = tree.root[currentBlock.parent];
-                    }
-                    // Return error if label is not found
-                    if(action.continuation.label !== currentBlock.label){
-                        return name_resolution_error(action.continuation.label);
-                    }
-                    // Otherwise, update index
-                    if (currentBlock.kind === "Block"){
-                        // console.log(`Skip to END: from ${index} to ${currentBlock.end}`);
-                        // For block, go to end
-                        index = currentBlock.end;
-                    }
-                    else if (currentBlock.kind === "Loop"){
-                        // console.log(`Skip to START: from ${index} to ${currentBlock.start}`);
-                        // For loop, go to start
-                        index = currentBlock.start
-                    }
-
+                // Branching to the function body leaves the function
+                if(target.node === 0){
+                    break;
                 }
+                const targetBlock = tree.root[target.node];
+                // Loops stay entered, blocks are left for their parent
+                currentBlock = targetBlock.kind === "Loop" ? targetBlock : tree.root[targetBlock.parent];
+                // Step back one, since index is incremented below
+                index = target.continuation - 1;
             }
         }
-        // console.log(instruction_in_plain_english(tree.array[index]));
+        // console.log(tree.descriptions[index]);
         index++;
         current_step++;
         // console.log(`AFTER ${index-1}: `, [currentBlock.kind, currentBlock.label, currentBlock.depth], [currentBlock.start, currentBlock.end], [currentBlock.parent, currentBlock.children]);
@@ -886,7 +871,7 @@ export function operationFromInstruction(instruction: command.SerializedInstruct
         }
         return {kind: "Nop", name: "Unconditional Branch"}
     }
-    else if("Call" in instruction){
+    else if("Call" in instruction || "CallIndirect" in instruction){
         // TODO: Need to make stack change on call
     }
     else if("Data" in instruction){